@@ -1,6 +1,13 @@
+#[cfg(feature = "sdk")]
 use std::{env, path::PathBuf};
 
 fn main() {
+    #[cfg(feature = "sdk")]
+    generate_bindings();
+}
+
+#[cfg(feature = "sdk")]
+fn generate_bindings() {
     println!("cargo:rustc-link-search=c:/MSFS SDK/SimConnect SDK/lib/static");
     println!("cargo:rustc-link-lib=static=SimConnect");
 
@@ -0,0 +1,939 @@
+// Background traffic replayed from ADS-B captures: readsb and tar1090
+// `aircraft.json` snapshots (plain or gzipped) and SBS BaseStation logs.
+// Targets are spawned as non-ATC aircraft with the container title the
+// `ModelMatcher` picks for their type, have their AI released, and are then
+// placed every frame. Positions are drawn `delay` behind the recording clock
+// so there is usually a later report to interpolate towards, and dead
+// reckoned for a capped time when there is not.
+
+use super::bindings::*;
+use super::definition::{DataDefinition, DataStruct};
+use super::message::Message;
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+const EARTH_RADIUS_NM: f64 = 3440.065;
+const KNOTS_TO_MPS: f64 = 0.514_444;
+const GRAVITY_MPS2: f64 = 9.806_65;
+
+fn invalid(what: &str) -> SimConnectError {
+    SimConnectError::new(&format!("Invalid ADS-B data: {}", what), None)
+}
+
+// One message about one aircraft. SBS logs spread position, velocity and
+// identity over separate messages, so most fields are optional.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    // Unix seconds.
+    pub time: f64,
+    pub icao24: u32,
+    pub callsign: Option<String>,
+    pub aircraft_type: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude_ft: Option<f64>,
+    pub on_ground: bool,
+    pub ground_speed_kt: Option<f64>,
+    pub track: Option<f64>,
+    pub vertical_rate_fpm: Option<f64>,
+}
+
+impl Report {
+    fn has_position(&self) -> bool {
+        self.latitude.is_some() && self.longitude.is_some()
+    }
+}
+
+// Parses one `aircraft.json` snapshot. Report times are the snapshot time
+// less the age of the position.
+pub fn parse_aircraft_json(text: &str) -> SimConnectResult<Vec<Report>> {
+    let json: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| invalid(&e.to_string()))?;
+    let now = json["now"]
+        .as_f64()
+        .ok_or_else(|| invalid("snapshot without now"))?;
+    let aircraft = json["aircraft"]
+        .as_array()
+        .ok_or_else(|| invalid("snapshot without aircraft"))?;
+
+    let mut reports = Vec::new();
+    for entry in aircraft {
+        // Hex ids starting with `~` are TIS-B targets without an ICAO address.
+        let Some(icao24) = entry["hex"]
+            .as_str()
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        else {
+            continue;
+        };
+        let age = entry["seen_pos"]
+            .as_f64()
+            .or(entry["seen"].as_f64())
+            .unwrap_or(0.0);
+        let on_ground = entry["alt_baro"].as_str() == Some("ground");
+        let text = |key: &str| {
+            entry[key]
+                .as_str()
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+        };
+        reports.push(Report {
+            time: now - age,
+            icao24,
+            callsign: text("flight"),
+            aircraft_type: text("t"),
+            latitude: entry["lat"].as_f64(),
+            longitude: entry["lon"].as_f64(),
+            altitude_ft: entry["alt_geom"].as_f64().or(entry["alt_baro"].as_f64()),
+            on_ground,
+            ground_speed_kt: entry["gs"].as_f64(),
+            track: entry["track"].as_f64().or(entry["true_heading"].as_f64()),
+            vertical_rate_fpm: entry["geom_rate"].as_f64().or(entry["baro_rate"].as_f64()),
+        });
+    }
+    Ok(reports)
+}
+
+// Parses an SBS BaseStation log, `MSG` lines only. Times are taken as UTC.
+pub fn parse_sbs(reader: impl BufRead) -> SimConnectResult<Vec<Report>> {
+    let mut reports = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| invalid(&e.to_string()))?;
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields[0] != "MSG" {
+            continue;
+        }
+        let error = || invalid(&format!("bad SBS message on line {}", number + 1));
+        if fields.len() < 22 {
+            return Err(error());
+        }
+
+        let number_at = |index: usize| -> SimConnectResult<Option<f64>> {
+            match fields[index].trim() {
+                "" => Ok(None),
+                text => text.parse().map(Some).map_err(|_| error()),
+            }
+        };
+        let callsign = fields[10].trim();
+        reports.push(Report {
+            time: sbs_time(fields[6], fields[7]).ok_or_else(error)?,
+            icao24: u32::from_str_radix(fields[4].trim(), 16).map_err(|_| error())?,
+            callsign: (!callsign.is_empty()).then(|| callsign.to_string()),
+            aircraft_type: None,
+            altitude_ft: number_at(11)?,
+            ground_speed_kt: number_at(12)?,
+            track: number_at(13)?,
+            latitude: number_at(14)?,
+            longitude: number_at(15)?,
+            vertical_rate_fpm: number_at(16)?,
+            on_ground: matches!(fields[21].trim(), "-1" | "1"),
+        });
+    }
+    Ok(reports)
+}
+
+// `2023/10/01` and `12:00:00.000` to Unix seconds.
+fn sbs_time(date: &str, time: &str) -> Option<f64> {
+    let date: Vec<i64> = date
+        .split('/')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<f64> = time
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    if date.len() != 3 || time.len() != 3 {
+        return None;
+    }
+
+    // Days from civil, after Howard Hinnant.
+    let (y, m, d) = (date[0] - (date[1] <= 2) as i64, date[1], date[2]);
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Some(days as f64 * 86_400.0 + time[0] * 3600.0 + time[1] * 60.0 + time[2])
+}
+
+// Reports from a capture, in time order.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    reports: Vec<Report>,
+    next: usize,
+}
+
+impl Recording {
+    pub fn new(mut reports: Vec<Report>) -> Self {
+        reports.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { reports, next: 0 }
+    }
+
+    // Every `.json` and `.json.gz` snapshot in `dir`.
+    pub fn load_snapshots(dir: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let dir = dir.as_ref();
+        let entries = fs::read_dir(dir).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", dir.display(), e), None)
+        })?;
+
+        let mut reports = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| SimConnectError::new(&e.to_string(), None))?
+                .path();
+            let name = path.to_string_lossy();
+            if !(name.ends_with(".json") || name.ends_with(".json.gz")) {
+                continue;
+            }
+            let bytes = fs::read(&path).map_err(|e| {
+                SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+            })?;
+            let mut text = String::new();
+            let read = match name.ends_with(".gz") {
+                true => GzDecoder::new(bytes.as_slice()).read_to_string(&mut text),
+                false => bytes.as_slice().read_to_string(&mut text),
+            };
+            read.map_err(|e| invalid(&format!("{}: {}", path.display(), e)))?;
+            reports.extend(parse_aircraft_json(&text)?);
+        }
+        Ok(Self::new(reports))
+    }
+
+    pub fn load_sbs(path: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let path = path.as_ref();
+        let file = fs::File::open(path).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+        })?;
+        Ok(Self::new(parse_sbs(BufReader::new(file))?))
+    }
+
+    pub fn start(&self) -> Option<f64> {
+        self.reports.first().map(|r| r.time)
+    }
+
+    pub fn end(&self) -> Option<f64> {
+        self.reports.last().map(|r| r.time)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.reports.len()
+    }
+
+    // The reports up to and including `time` not taken before.
+    pub fn take_until(&mut self, time: f64) -> &[Report] {
+        let start = self.next;
+        while self.next < self.reports.len() && self.reports[self.next].time <= time {
+            self.next += 1;
+        }
+        &self.reports[start..self.next]
+    }
+
+    pub fn rewind(&mut self) {
+        self.next = 0;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Model {
+    aircraft_type: String,
+    // A trailing `*` matches any rest of the type.
+    prefix: bool,
+    airline: Option<String>,
+    title: String,
+}
+
+// Picks container titles by ICAO type code and airline. Exact types beat
+// prefixes, longer prefixes beat shorter ones, and entries for the airline
+// beat those for any airline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelMatcher {
+    models: Vec<Model>,
+    default_title: String,
+}
+
+impl ModelMatcher {
+    pub fn new(default_title: &str) -> Self {
+        Self {
+            models: Vec::new(),
+            default_title: default_title.to_string(),
+        }
+    }
+
+    // `type_pattern` is a type code such as `B738`, or a prefix such as
+    // `B73*`.
+    pub fn model(mut self, type_pattern: &str, airline: Option<&str>, title: &str) -> Self {
+        let (aircraft_type, prefix) = match type_pattern.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (type_pattern, false),
+        };
+        self.models.push(Model {
+            aircraft_type: aircraft_type.to_ascii_uppercase(),
+            prefix,
+            airline: airline.map(str::to_ascii_uppercase),
+            title: title.to_string(),
+        });
+        self
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+        })?;
+        Self::parse(&text)
+    }
+
+    // One `type[:airline] = title` per line, `*` alone sets the default.
+    // Lines starting with `;` or `#` are comments.
+    pub fn parse(text: &str) -> SimConnectResult<Self> {
+        let mut matcher = Self::new("");
+        let mut default_title = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, title) = line
+                .split_once('=')
+                .map(|(p, t)| (p.trim(), t.trim()))
+                .filter(|(p, t)| !p.is_empty() && !t.is_empty())
+                .ok_or_else(|| invalid(&format!("expected type = title on line {}", number + 1)))?;
+            if pattern == "*" {
+                default_title = Some(title.to_string());
+                continue;
+            }
+            matcher = match pattern.split_once(':') {
+                Some((aircraft_type, airline)) => {
+                    matcher.model(aircraft_type.trim(), Some(airline.trim()), title)
+                }
+                None => matcher.model(pattern, None, title),
+            };
+        }
+        matcher.default_title =
+            default_title.ok_or_else(|| invalid("model table without a `*` default"))?;
+        Ok(matcher)
+    }
+
+    pub fn title(&self, aircraft_type: Option<&str>, callsign: Option<&str>) -> &str {
+        let aircraft_type = aircraft_type.unwrap_or_default().to_ascii_uppercase();
+        // Airline callsigns start with the three letter ICAO designator.
+        let airline = callsign
+            .and_then(|c| c.get(..3))
+            .filter(|a| a.bytes().all(|b| b.is_ascii_alphabetic()))
+            .map(str::to_ascii_uppercase);
+
+        self.models
+            .iter()
+            .filter(|m| match m.prefix {
+                true => aircraft_type.starts_with(&m.aircraft_type),
+                false => aircraft_type == m.aircraft_type,
+            })
+            .filter(|m| m.airline.is_none() || m.airline == airline)
+            .max_by_key(|m| (!m.prefix, m.aircraft_type.len(), m.airline.is_some()))
+            .map_or(&self.default_title, |m| &m.title)
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct Pose {
+    latitude: f64,
+    longitude: f64,
+    altitude_ft: f64,
+    pitch: f64,
+    bank: f64,
+    heading: f64,
+}
+
+unsafe impl DataStruct for Pose {}
+
+// Targets on the ground are left at the elevation the sim put them at, as
+// ADS-B ground reports carry no altitude.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct GroundPose {
+    latitude: f64,
+    longitude: f64,
+    pitch: f64,
+    bank: f64,
+    heading: f64,
+}
+
+unsafe impl DataStruct for GroundPose {}
+
+impl From<Pose> for GroundPose {
+    fn from(pose: Pose) -> Self {
+        Self {
+            latitude: pose.latitude,
+            longitude: pose.longitude,
+            pitch: pose.pitch,
+            bank: pose.bank,
+            heading: pose.heading,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: f64,
+    latitude: f64,
+    longitude: f64,
+    altitude_ft: f64,
+    track: f64,
+    on_ground: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Spawn {
+    Waiting,
+    Requested {
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        at: f64,
+    },
+    Live(SIMCONNECT_OBJECT_ID),
+}
+
+struct Target {
+    callsign: Option<String>,
+    aircraft_type: Option<String>,
+    samples: VecDeque<Sample>,
+    ground_speed_kt: f64,
+    vertical_rate_fpm: f64,
+    last_seen: f64,
+    spawn: Spawn,
+}
+
+impl Target {
+    fn update(&mut self, report: &Report) {
+        self.last_seen = self.last_seen.max(report.time);
+        if report.callsign.is_some() {
+            self.callsign = report.callsign.clone();
+        }
+        if report.aircraft_type.is_some() {
+            self.aircraft_type = report.aircraft_type.clone();
+        }
+        self.ground_speed_kt = report.ground_speed_kt.unwrap_or(self.ground_speed_kt);
+        self.vertical_rate_fpm = report.vertical_rate_fpm.unwrap_or(self.vertical_rate_fpm);
+
+        let (Some(latitude), Some(longitude)) = (report.latitude, report.longitude) else {
+            return;
+        };
+        // Snapshots repeat positions that have not been updated since.
+        let last = self.samples.back().copied();
+        if last.is_some_and(|s| report.time <= s.time) {
+            return;
+        }
+        self.samples.push_back(Sample {
+            time: report.time,
+            latitude,
+            longitude,
+            // Ground reports carry no altitude, keep the last one known.
+            altitude_ft: report
+                .altitude_ft
+                .or(last.map(|s| s.altitude_ft))
+                .unwrap_or(0.0),
+            track: report.track.or(last.map(|s| s.track)).unwrap_or(0.0),
+            on_ground: report.on_ground,
+        });
+    }
+
+    // The pose at `time` and whether it is on the ground, which holds from
+    // one report to the next.
+    fn pose(&self, time: f64, extrapolate_for: f64) -> Option<(Pose, bool)> {
+        let first = self.samples.front()?;
+        if time <= first.time {
+            return Some((self.attitude(first, 0.0), first.on_ground));
+        }
+
+        let after = self.samples.iter().position(|s| s.time > time);
+        let (a, b) = match after {
+            Some(index) => (&self.samples[index - 1], &self.samples[index]),
+            None => {
+                let last = self.samples.back()?;
+                let dt = (time - last.time).min(extrapolate_for);
+                let distance_nm = self.ground_speed_kt * dt / 3600.0;
+                let (latitude, longitude) =
+                    destination(last.latitude, last.longitude, last.track, distance_nm);
+                let moved = Sample {
+                    time: last.time + dt,
+                    latitude,
+                    longitude,
+                    altitude_ft: match last.on_ground {
+                        true => last.altitude_ft,
+                        false => last.altitude_ft + self.vertical_rate_fpm * dt / 60.0,
+                    },
+                    track: last.track,
+                    on_ground: last.on_ground,
+                };
+                let turn = self.turn_rate();
+                return Some((self.attitude(&moved, turn), last.on_ground));
+            }
+        };
+
+        let t = (time - a.time) / (b.time - a.time);
+        let sample = Sample {
+            time,
+            latitude: a.latitude + (b.latitude - a.latitude) * t,
+            longitude: a.longitude + wrap_degrees(b.longitude - a.longitude) * t,
+            altitude_ft: a.altitude_ft + (b.altitude_ft - a.altitude_ft) * t,
+            track: a.track + wrap_degrees(b.track - a.track) * t,
+            on_ground: a.on_ground,
+        };
+        let turn = wrap_degrees(b.track - a.track) / (b.time - a.time);
+        Some((self.attitude(&sample, turn), a.on_ground))
+    }
+
+    // Degrees per second over the last two samples.
+    fn turn_rate(&self) -> f64 {
+        let n = self.samples.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let (a, b) = (&self.samples[n - 2], &self.samples[n - 1]);
+        wrap_degrees(b.track - a.track) / (b.time - a.time)
+    }
+
+    fn attitude(&self, sample: &Sample, turn_rate: f64) -> Pose {
+        let speed_mps = self.ground_speed_kt * KNOTS_TO_MPS;
+        let (pitch, bank) = match sample.on_ground || speed_mps < 1.0 {
+            true => (0.0, 0.0),
+            false => {
+                let climb_mps = self.vertical_rate_fpm * 0.3048 / 60.0;
+                let bank = (speed_mps * turn_rate.to_radians() / GRAVITY_MPS2).atan();
+                (
+                    climb_mps.atan2(speed_mps).to_degrees(),
+                    bank.to_degrees().clamp(-30.0, 30.0),
+                )
+            }
+        };
+        // The sim counts nose up and right wing down as negative.
+        Pose {
+            latitude: sample.latitude,
+            longitude: sample.longitude,
+            altitude_ft: sample.altitude_ft,
+            pitch: -pitch,
+            bank: -bank,
+            heading: sample.track.rem_euclid(360.0),
+        }
+    }
+}
+
+fn wrap_degrees(delta: f64) -> f64 {
+    (delta + 180.0).rem_euclid(360.0) - 180.0
+}
+
+fn destination(latitude: f64, longitude: f64, track: f64, distance_nm: f64) -> (f64, f64) {
+    let (lat, lon, course) = (
+        latitude.to_radians(),
+        longitude.to_radians(),
+        track.to_radians(),
+    );
+    let d = distance_nm / EARTH_RADIUS_NM;
+    let lat2 = (lat.sin() * d.cos() + lat.cos() * d.sin() * course.cos()).asin();
+    let lon2 = lon + (course.sin() * d.sin() * lat.cos()).atan2(d.cos() - lat.sin() * lat2.sin());
+    (lat2.to_degrees(), wrap_degrees(lon2.to_degrees()))
+}
+
+#[derive(Debug, Clone)]
+pub struct InjectorOptions {
+    pub delay: Duration,
+    pub extrapolate_for: Duration,
+    pub stale_after: Duration,
+    pub max_targets: usize,
+}
+
+impl InjectorOptions {
+    pub fn new() -> Self {
+        Self {
+            delay: Duration::from_secs(2),
+            extrapolate_for: Duration::from_secs(10),
+            stale_after: Duration::from_secs(30),
+            max_targets: 50,
+        }
+    }
+
+    // How far behind the recording clock targets are drawn, 2 seconds by
+    // default. Longer delays ride out larger gaps between reports.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // Dead reckoning past the last report stops after this, 10 seconds by
+    // default.
+    pub fn extrapolate_for(mut self, limit: Duration) -> Self {
+        self.extrapolate_for = limit;
+        self
+    }
+
+    // Targets not heard from for this long are removed, 30 seconds by
+    // default.
+    pub fn stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    pub fn max_targets(mut self, max_targets: usize) -> Self {
+        self.max_targets = max_targets;
+        self
+    }
+}
+
+impl Default for InjectorOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Injector {
+    matcher: ModelMatcher,
+    options: InjectorOptions,
+    definition: DataDefinition<Pose>,
+    ground_definition: DataDefinition<GroundPose>,
+    request_ids: Range<SIMCONNECT_DATA_REQUEST_ID>,
+    next_request_id: SIMCONNECT_DATA_REQUEST_ID,
+    targets: HashMap<u32, Target>,
+    // Spawns given up on, whose aircraft is removed if it still turns up.
+    abandoned: HashSet<SIMCONNECT_DATA_REQUEST_ID>,
+}
+
+impl Injector {
+    // Registers the position definition under `define_id` and the one
+    // without altitude used on the ground under `ground_define_id`. Spawns
+    // and removals use the request ids in `request_ids` round robin.
+    pub fn new(
+        simconnect: &impl SimConnectApi,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        ground_define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        request_ids: Range<SIMCONNECT_DATA_REQUEST_ID>,
+        matcher: ModelMatcher,
+        options: InjectorOptions,
+    ) -> SimConnectResult<Self> {
+        if request_ids.is_empty() {
+            return Err(SimConnectError::new(
+                "Injector needs at least one request id",
+                None,
+            ));
+        }
+
+        let mut definition = DataDefinition::new(define_id);
+        for (name, units) in [
+            ("PLANE LATITUDE", "degrees"),
+            ("PLANE LONGITUDE", "degrees"),
+            ("PLANE ALTITUDE", "feet"),
+            ("PLANE PITCH DEGREES", "degrees"),
+            ("PLANE BANK DEGREES", "degrees"),
+            ("PLANE HEADING DEGREES TRUE", "degrees"),
+        ] {
+            definition.add(simconnect, name, units, DataType::Float64, 0.0)?;
+        }
+        let mut ground_definition = DataDefinition::new(ground_define_id);
+        for (name, units) in [
+            ("PLANE LATITUDE", "degrees"),
+            ("PLANE LONGITUDE", "degrees"),
+            ("PLANE PITCH DEGREES", "degrees"),
+            ("PLANE BANK DEGREES", "degrees"),
+            ("PLANE HEADING DEGREES TRUE", "degrees"),
+        ] {
+            ground_definition.add(simconnect, name, units, DataType::Float64, 0.0)?;
+        }
+
+        Ok(Self {
+            matcher,
+            options,
+            definition,
+            ground_definition,
+            next_request_id: request_ids.start,
+            request_ids,
+            targets: HashMap::new(),
+            abandoned: HashSet::new(),
+        })
+    }
+
+    // Ids of spawns the sim has not answered yet are skipped, so a late
+    // answer cannot be taken for a later request.
+    fn next_request_id(&mut self) -> SimConnectResult<SIMCONNECT_DATA_REQUEST_ID> {
+        for _ in self.request_ids.clone() {
+            let id = self.next_request_id;
+            self.next_request_id = match id + 1 < self.request_ids.end {
+                true => id + 1,
+                false => self.request_ids.start,
+            };
+            let pending = self.abandoned.contains(&id)
+                || self.targets.values().any(
+                    |t| matches!(t.spawn, Spawn::Requested { request_id, .. } if request_id == id),
+                );
+            if !pending {
+                return Ok(id);
+            }
+        }
+        Err(SimConnectError::new(
+            "Injector ran out of request ids, spawns are still pending on all of them",
+            None,
+        ))
+    }
+
+    pub fn push(&mut self, report: &Report) {
+        if !self.targets.contains_key(&report.icao24) {
+            if !report.has_position() || self.targets.len() >= self.options.max_targets {
+                return;
+            }
+            self.targets.insert(
+                report.icao24,
+                Target {
+                    callsign: None,
+                    aircraft_type: None,
+                    samples: VecDeque::new(),
+                    ground_speed_kt: 0.0,
+                    vertical_rate_fpm: 0.0,
+                    last_seen: report.time,
+                    spawn: Spawn::Waiting,
+                },
+            );
+        }
+        if let Some(target) = self.targets.get_mut(&report.icao24) {
+            target.update(report);
+        }
+    }
+
+    // Takes the assigned object ids of spawned targets.
+    pub fn handle_message(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        message: &Message,
+    ) -> SimConnectResult<()> {
+        let Message::AssignedObjectId {
+            request_id,
+            object_id,
+        } = message
+        else {
+            return Ok(());
+        };
+        let target = self.targets.values_mut().find(
+            |t| matches!(t.spawn, Spawn::Requested { request_id: id, .. } if id == *request_id),
+        );
+        let Some(target) = target else {
+            if self.abandoned.remove(request_id) {
+                let request_id = self.next_request_id()?;
+                simconnect.ai_remove_object(*object_id, request_id)?;
+            }
+            return Ok(());
+        };
+        target.spawn = Spawn::Live(*object_id);
+        let request_id = self.next_request_id()?;
+        simconnect.ai_release_control(*object_id, request_id)
+    }
+
+    // Spawns, moves and removes targets for the recording clock at `time`,
+    // meant to be called on every `Frame` event.
+    pub fn frame(&mut self, simconnect: &impl SimConnectApi, time: f64) -> SimConnectResult<()> {
+        let draw_at = time - self.options.delay.as_secs_f64();
+        let stale_after = self.options.stale_after.as_secs_f64();
+        let extrapolate_for = self.options.extrapolate_for.as_secs_f64();
+
+        let gone: Vec<u32> = self
+            .targets
+            .iter()
+            .filter(|(_, t)| match t.spawn {
+                // A spawn the sim has not answered in time is given up on.
+                Spawn::Requested { at, .. } => time - at > stale_after,
+                _ => time - t.last_seen > stale_after,
+            })
+            .map(|(icao24, _)| *icao24)
+            .collect();
+        for icao24 in gone {
+            self.remove(simconnect, icao24)?;
+        }
+
+        let icao24s: Vec<u32> = self.targets.keys().copied().collect();
+        for icao24 in icao24s {
+            let target = &self.targets[&icao24];
+            if !target.samples.front().is_some_and(|s| s.time <= draw_at) {
+                continue;
+            }
+            let Some((pose, on_ground)) = target.pose(draw_at, extrapolate_for) else {
+                continue;
+            };
+
+            match target.spawn {
+                Spawn::Waiting => {
+                    let title = self
+                        .matcher
+                        .title(target.aircraft_type.as_deref(), target.callsign.as_deref())
+                        .to_string();
+                    let tail = target
+                        .callsign
+                        .clone()
+                        .unwrap_or_else(|| format!("{:06X}", icao24));
+                    let init_pos = InitPosition {
+                        latitude: pose.latitude,
+                        longitude: pose.longitude,
+                        altitude: pose.altitude_ft,
+                        pitch: pose.pitch,
+                        bank: pose.bank,
+                        heading: pose.heading,
+                        on_ground,
+                        airspeed: target.ground_speed_kt as u32,
+                    };
+                    let request_id = self.next_request_id()?;
+                    simconnect.ai_create_non_atc_aircraft(&title, &tail, init_pos, request_id)?;
+                    if let Some(target) = self.targets.get_mut(&icao24) {
+                        target.spawn = Spawn::Requested {
+                            request_id,
+                            at: time,
+                        };
+                    }
+                }
+                Spawn::Requested { .. } => {}
+                Spawn::Live(object_id) if on_ground => {
+                    self.ground_definition
+                        .set(simconnect, object_id, &GroundPose::from(pose))?
+                }
+                Spawn::Live(object_id) => self.definition.set(simconnect, object_id, &pose)?,
+            }
+
+            // Samples before the pair being interpolated are done with.
+            if let Some(target) = self.targets.get_mut(&icao24) {
+                while target.samples.len() > 2 && target.samples[1].time <= draw_at {
+                    target.samples.pop_front();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn live(&self) -> impl Iterator<Item = (u32, SIMCONNECT_OBJECT_ID)> + '_ {
+        self.targets.iter().filter_map(|(icao24, t)| match t.spawn {
+            Spawn::Live(object_id) => Some((*icao24, object_id)),
+            _ => None,
+        })
+    }
+
+    fn remove(&mut self, simconnect: &impl SimConnectApi, icao24: u32) -> SimConnectResult<()> {
+        match self.targets.remove(&icao24).map(|t| t.spawn) {
+            Some(Spawn::Live(object_id)) => {
+                let request_id = self.next_request_id()?;
+                simconnect.ai_remove_object(object_id, request_id)?;
+            }
+            Some(Spawn::Requested { request_id, .. }) => {
+                self.abandoned.insert(request_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Removes every spawned target.
+    pub fn clear(&mut self, simconnect: &impl SimConnectApi) -> SimConnectResult<()> {
+        let icao24s: Vec<u32> = self.targets.keys().copied().collect();
+        for icao24 in icao24s {
+            self.remove(simconnect, icao24)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::Replay;
+    use crate::session::{SessionMetadata, SessionWriter};
+    use std::io::Cursor;
+
+    // Accepts the requests and never answers.
+    fn simconnect() -> Replay<Cursor<Vec<u8>>> {
+        let writer = SessionWriter::new(Vec::new(), &SessionMetadata::new("test")).unwrap();
+        Replay::open(Cursor::new(writer.finish().unwrap())).unwrap()
+    }
+
+    fn report(time: f64, altitude_ft: Option<f64>, on_ground: bool) -> Report {
+        Report {
+            time,
+            icao24: 0x4CA123,
+            latitude: Some(51.47),
+            longitude: Some(-0.45 + time * 0.001),
+            altitude_ft,
+            track: Some(90.0),
+            ground_speed_kt: Some(150.0),
+            on_ground,
+            ..Report::default()
+        }
+    }
+
+    #[test]
+    fn keeps_ground_targets_out_of_the_altitude() {
+        let mut target = Target {
+            callsign: None,
+            aircraft_type: None,
+            samples: VecDeque::new(),
+            ground_speed_kt: 0.0,
+            vertical_rate_fpm: 0.0,
+            last_seen: 0.0,
+            spawn: Spawn::Waiting,
+        };
+        target.update(&report(0.0, None, true));
+        target.update(&report(10.0, Some(1500.0), false));
+
+        let (pose, on_ground) = target.pose(5.0, 10.0).unwrap();
+        assert!(on_ground);
+        assert_eq!((pose.pitch, pose.bank), (0.0, 0.0));
+
+        let (pose, on_ground) = target.pose(12.0, 10.0).unwrap();
+        assert!(!on_ground);
+        assert_eq!({ pose.altitude_ft }, 1500.0);
+    }
+
+    #[test]
+    fn keeps_request_ids_of_abandoned_spawns() {
+        let simconnect = simconnect();
+        let options = InjectorOptions::new()
+            .delay(Duration::ZERO)
+            .stale_after(Duration::from_secs(5));
+        let mut injector = Injector::new(
+            &simconnect,
+            1,
+            2,
+            10..12,
+            ModelMatcher::new("Traffic"),
+            options,
+        )
+        .unwrap();
+
+        injector.push(&report(0.0, Some(1500.0), false));
+        injector.frame(&simconnect, 0.0).unwrap();
+        // Never answered, given up on and spawned again.
+        injector.frame(&simconnect, 6.0).unwrap();
+        injector.push(&report(6.0, Some(1500.0), false));
+        injector.frame(&simconnect, 6.0).unwrap();
+        assert_eq!(
+            injector.targets[&0x4CA123].spawn,
+            Spawn::Requested {
+                request_id: 11,
+                at: 6.0
+            }
+        );
+
+        // The first id is still abandoned and the second is pending.
+        injector.push(&Report {
+            icao24: 0x4CA124,
+            ..report(6.0, Some(1500.0), false)
+        });
+        assert!(injector.frame(&simconnect, 6.0).is_err());
+
+        // The first spawn turns up late and is removed, not bound.
+        let late = Message::AssignedObjectId {
+            request_id: 10,
+            object_id: 7,
+        };
+        injector.handle_message(&simconnect, &late).unwrap();
+        assert_eq!(injector.live().count(), 0);
+        assert!(injector.abandoned.is_empty());
+    }
+}
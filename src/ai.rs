@@ -0,0 +1,429 @@
+// Spawns AI objects and tracks them until they are gone, available with the
+// `tokio` feature. A spawn resolves once the sim has assigned the object id.
+// Objects the sim removes are noticed through `ObjectRemoved`, and objects
+// still alive are removed when their handle or the manager is dropped.
+// Request ids are taken round robin from the range the manager is given, so
+// the range must be wide enough for the spawns in flight at once. Objects
+// whose spawn timed out or was cancelled are removed once their id arrives.
+
+use super::async_client::{AsyncSimConnect, MessageStream};
+use super::bindings::*;
+use super::message::Message;
+use super::simconnect::SimConnect;
+use super::types::*;
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    released: bool,
+}
+
+enum Spawn {
+    Waiting,
+    Arrived(SIMCONNECT_OBJECT_ID),
+    Abandoned,
+}
+
+struct Shared {
+    objects: HashMap<SIMCONNECT_OBJECT_ID, Tracked>,
+    spawns: HashMap<SIMCONNECT_DATA_REQUEST_ID, Spawn>,
+    request_ids: Range<SIMCONNECT_DATA_REQUEST_ID>,
+    next_request_id: SIMCONNECT_DATA_REQUEST_ID,
+}
+
+impl Shared {
+    fn next_request_id(&mut self) -> SIMCONNECT_DATA_REQUEST_ID {
+        let id = self.next_request_id;
+        self.next_request_id = match id + 1 < self.request_ids.end {
+            true => id + 1,
+            false => self.request_ids.start,
+        };
+        id
+    }
+
+    fn remove_object(&mut self, simconnect: &AsyncSimConnect, object_id: SIMCONNECT_OBJECT_ID) {
+        let request_id = self.next_request_id();
+        let _ = simconnect
+            .client()
+            .try_send(move |simconnect| simconnect.ai_remove_object(object_id, request_id));
+    }
+}
+
+type SharedState = Arc<Mutex<Shared>>;
+
+pub struct AiManager {
+    simconnect: AsyncSimConnect,
+    shared: SharedState,
+    removed_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    timeout: Duration,
+    watcher: JoinHandle<()>,
+    shut_down: bool,
+}
+
+impl AiManager {
+    pub async fn new(
+        simconnect: AsyncSimConnect,
+        removed_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        request_ids: Range<SIMCONNECT_DATA_REQUEST_ID>,
+    ) -> SimConnectResult<Self> {
+        if request_ids.is_empty() {
+            return Err(SimConnectError::new(
+                "AI manager needs at least one request id",
+                None,
+            ));
+        }
+
+        let shared = Arc::new(Mutex::new(Shared {
+            objects: HashMap::new(),
+            spawns: HashMap::new(),
+            next_request_id: request_ids.start,
+            request_ids,
+        }));
+        // Subscribed before the event so no removal is missed.
+        let messages = simconnect.messages();
+        simconnect
+            .call(move |simconnect| {
+                simconnect.subscribe_to_system_event(removed_event_id, "ObjectRemoved")
+            })
+            .await?;
+        let watcher = tokio::spawn(watch(
+            messages,
+            simconnect.clone(),
+            removed_event_id,
+            shared.clone(),
+        ));
+
+        Ok(Self {
+            simconnect,
+            shared,
+            removed_event_id,
+            timeout: Duration::from_secs(10),
+            watcher,
+            shut_down: false,
+        })
+    }
+
+    // How long a spawn waits for the object id, 10 seconds by default.
+    pub fn spawn_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn objects(&self) -> Vec<SIMCONNECT_OBJECT_ID> {
+        self.shared
+            .lock()
+            .unwrap()
+            .objects
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    pub async fn spawn_non_atc_aircraft(
+        &self,
+        container_title: &str,
+        tail_number: &str,
+        init_pos: InitPosition,
+    ) -> SimConnectResult<AiObject> {
+        let (title, tail) = (container_title.to_string(), tail_number.to_string());
+        self.spawn(move |simconnect, request_id| {
+            simconnect.ai_create_non_atc_aircraft(&title, &tail, init_pos, request_id)
+        })
+        .await
+    }
+
+    pub async fn spawn_parked_atc_aircraft(
+        &self,
+        container_title: &str,
+        tail_number: &str,
+        airport_id: &str,
+    ) -> SimConnectResult<AiObject> {
+        let (title, tail, airport) = (
+            container_title.to_string(),
+            tail_number.to_string(),
+            airport_id.to_string(),
+        );
+        self.spawn(move |simconnect, request_id| {
+            simconnect.ai_create_parked_atc_aircraft(&title, &tail, &airport, request_id)
+        })
+        .await
+    }
+
+    pub async fn spawn_enroute_atc_aircraft(
+        &self,
+        container_title: &str,
+        tail_number: &str,
+        flight_number: i32,
+        flight_plan_path: &str,
+        flight_plan_position: f64,
+        touch_and_go: bool,
+    ) -> SimConnectResult<AiObject> {
+        let (title, tail, plan) = (
+            container_title.to_string(),
+            tail_number.to_string(),
+            flight_plan_path.to_string(),
+        );
+        self.spawn(move |simconnect, request_id| {
+            simconnect.ai_create_enroute_atc_aircraft(
+                &title,
+                &tail,
+                flight_number,
+                &plan,
+                flight_plan_position,
+                touch_and_go,
+                request_id,
+            )
+        })
+        .await
+    }
+
+    pub async fn spawn_simulated_object(
+        &self,
+        container_title: &str,
+        init_pos: InitPosition,
+    ) -> SimConnectResult<AiObject> {
+        let title = container_title.to_string();
+        self.spawn(move |simconnect, request_id| {
+            simconnect.ai_create_simulated_object(&title, init_pos, request_id)
+        })
+        .await
+    }
+
+    async fn spawn<F>(&self, create: F) -> SimConnectResult<AiObject>
+    where
+        F: FnOnce(&SimConnect, SIMCONNECT_DATA_REQUEST_ID) -> SimConnectResult<()> + Send + 'static,
+    {
+        let request_id = {
+            let mut shared = self.shared.lock().unwrap();
+            let request_id = shared.next_request_id();
+            shared.spawns.insert(request_id, Spawn::Waiting);
+            request_id
+        };
+        let mut guard = SpawnGuard {
+            simconnect: &self.simconnect,
+            shared: &self.shared,
+            request_id,
+            resolved: false,
+        };
+        let object_id = self
+            .simconnect
+            .create_object(
+                request_id,
+                move |simconnect| create(simconnect, request_id),
+                self.timeout,
+            )
+            .await?;
+
+        guard.resolved = true;
+        let mut shared = self.shared.lock().unwrap();
+        shared.spawns.remove(&request_id);
+        shared
+            .objects
+            .insert(object_id, Tracked { released: false });
+        drop(shared);
+        Ok(AiObject {
+            object_id,
+            simconnect: self.simconnect.clone(),
+            shared: self.shared.clone(),
+        })
+    }
+
+    // Removes every tracked object and stops watching for removals.
+    pub async fn shutdown(mut self) -> SimConnectResult<()> {
+        self.shut_down = true;
+        let objects = self.take_objects();
+        let event_id = self.removed_event_id;
+        self.simconnect
+            .call(move |simconnect| {
+                for (object_id, request_id) in objects {
+                    simconnect.ai_remove_object(object_id, request_id)?;
+                }
+                simconnect.unsubscribe_from_system_event(event_id)
+            })
+            .await
+    }
+
+    fn take_objects(&self) -> Vec<(SIMCONNECT_OBJECT_ID, SIMCONNECT_DATA_REQUEST_ID)> {
+        let mut shared = self.shared.lock().unwrap();
+        let objects: Vec<_> = shared.objects.drain().map(|(id, _)| id).collect();
+        objects
+            .into_iter()
+            .map(|id| (id, shared.next_request_id()))
+            .collect()
+    }
+}
+
+impl Drop for AiManager {
+    fn drop(&mut self) {
+        self.watcher.abort();
+        if self.shut_down {
+            return;
+        }
+        let objects = self.take_objects();
+        let event_id = self.removed_event_id;
+        let _ = self.simconnect.client().try_send(move |simconnect| {
+            for (object_id, request_id) in objects {
+                simconnect.ai_remove_object(object_id, request_id)?;
+            }
+            simconnect.unsubscribe_from_system_event(event_id)
+        });
+    }
+}
+
+// Left behind by a spawn that did not resolve, so an object id arriving
+// after the spawn gave up is removed again.
+struct SpawnGuard<'a> {
+    simconnect: &'a AsyncSimConnect,
+    shared: &'a SharedState,
+    request_id: SIMCONNECT_DATA_REQUEST_ID,
+    resolved: bool,
+}
+
+impl Drop for SpawnGuard<'_> {
+    fn drop(&mut self) {
+        if self.resolved {
+            return;
+        }
+        let mut shared = self.shared.lock().unwrap();
+        match shared.spawns.remove(&self.request_id) {
+            Some(Spawn::Arrived(object_id)) => shared.remove_object(self.simconnect, object_id),
+            _ => {
+                shared.spawns.insert(self.request_id, Spawn::Abandoned);
+            }
+        }
+    }
+}
+
+async fn watch(
+    mut messages: MessageStream,
+    simconnect: AsyncSimConnect,
+    removed_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    shared: SharedState,
+) {
+    while let Some(message) = std::future::poll_fn(|cx| Pin::new(&mut messages).poll_next(cx)).await
+    {
+        match message {
+            Message::EventObjectAddRemove { event, .. } if event.event_id == removed_event_id => {
+                shared.lock().unwrap().objects.remove(&event.data);
+            }
+            Message::AssignedObjectId {
+                request_id,
+                object_id,
+            } => {
+                let mut shared = shared.lock().unwrap();
+                match shared.spawns.get(&request_id) {
+                    Some(Spawn::Waiting) => {
+                        shared.spawns.insert(request_id, Spawn::Arrived(object_id));
+                    }
+                    Some(Spawn::Abandoned) => {
+                        shared.spawns.remove(&request_id);
+                        shared.remove_object(&simconnect, object_id);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// A spawned object, removed from the sim when dropped unless the sim removed
+// it first.
+pub struct AiObject {
+    object_id: SIMCONNECT_OBJECT_ID,
+    simconnect: AsyncSimConnect,
+    shared: SharedState,
+}
+
+impl AiObject {
+    pub fn object_id(&self) -> SIMCONNECT_OBJECT_ID {
+        self.object_id
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.tracked().is_some()
+    }
+
+    // Whether the AI pilot has been released, leaving the object to be
+    // driven with `set_data_on_sim_object`.
+    pub fn is_released(&self) -> bool {
+        self.tracked().is_some_and(|t| t.released)
+    }
+
+    fn tracked(&self) -> Option<Tracked> {
+        self.shared
+            .lock()
+            .unwrap()
+            .objects
+            .get(&self.object_id)
+            .copied()
+    }
+
+    fn request_id(&self) -> SimConnectResult<SIMCONNECT_DATA_REQUEST_ID> {
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.objects.contains_key(&self.object_id) {
+            return Err(SimConnectError::new(
+                &format!("AI object {} has been removed", self.object_id),
+                None,
+            ));
+        }
+        Ok(shared.next_request_id())
+    }
+
+    fn set_released(&self, released: bool) {
+        if let Some(tracked) = self.shared.lock().unwrap().objects.get_mut(&self.object_id) {
+            tracked.released = released;
+        }
+    }
+
+    pub async fn release_control(&self) -> SimConnectResult<()> {
+        let (object_id, request_id) = (self.object_id, self.request_id()?);
+        self.simconnect
+            .call(move |simconnect| simconnect.ai_release_control(object_id, request_id))
+            .await?;
+        self.set_released(true);
+        Ok(())
+    }
+
+    // SimConnect has no inverse of `ai_release_control`. Handing the aircraft
+    // a flight plan gives the AI pilot something to fly again.
+    pub async fn retake_control(&self, flight_plan_path: &str) -> SimConnectResult<()> {
+        let (object_id, request_id) = (self.object_id, self.request_id()?);
+        let plan = flight_plan_path.to_string();
+        self.simconnect
+            .call(move |simconnect| {
+                simconnect.ai_set_aircraft_flight_plan(object_id, &plan, request_id)
+            })
+            .await?;
+        self.set_released(false);
+        Ok(())
+    }
+
+    pub async fn remove(self) -> SimConnectResult<()> {
+        let (object_id, request_id) = (self.object_id, self.request_id()?);
+        self.shared.lock().unwrap().objects.remove(&object_id);
+        self.simconnect
+            .call(move |simconnect| simconnect.ai_remove_object(object_id, request_id))
+            .await
+    }
+}
+
+impl Drop for AiObject {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.objects.remove(&self.object_id).is_none() {
+            return;
+        }
+        let (object_id, request_id) = (self.object_id, shared.next_request_id());
+        let _ = self
+            .simconnect
+            .client()
+            .try_send(move |simconnect| simconnect.ai_remove_object(object_id, request_id));
+    }
+}
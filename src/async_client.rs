@@ -0,0 +1,454 @@
+// Async front for `SimConnectClient`, available with the `tokio` feature.
+// Request/response calls resolve by request id, so ids passed to them must
+// not be shared with requests whose replies are handled elsewhere.
+
+use super::bindings::*;
+use super::client::{stopped, ClientOptions, Job, SimConnectClient, Sink, Subscriber};
+use super::message::*;
+use super::simconnect::SimConnect;
+use super::types::*;
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::TrySendError;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+const MAX_SUBMIT_WAIT: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub enum Facilities {
+    Airports(Vec<FacilityAirport>),
+    Waypoints(Vec<FacilityWaypoint>),
+    Ndbs(Vec<FacilityNdb>),
+    Vors(Vec<FacilityVor>),
+}
+
+enum Waiter {
+    SystemState(oneshot::Sender<SimConnectResult<SystemStateData>>),
+    Data(oneshot::Sender<SimConnectResult<ObjectData>>),
+    ObjectId(oneshot::Sender<SimConnectResult<SIMCONNECT_OBJECT_ID>>),
+    Facilities {
+        sender: oneshot::Sender<SimConnectResult<Facilities>>,
+        collected: Option<Facilities>,
+    },
+}
+
+impl Waiter {
+    fn fail(self, error: SimConnectError) {
+        match self {
+            Waiter::SystemState(sender) => {
+                let _ = sender.send(Err(error));
+            }
+            Waiter::Data(sender) => {
+                let _ = sender.send(Err(error));
+            }
+            Waiter::ObjectId(sender) => {
+                let _ = sender.send(Err(error));
+            }
+            Waiter::Facilities { sender, .. } => {
+                let _ = sender.send(Err(error));
+            }
+        }
+    }
+}
+
+struct Pending {
+    // Tells this request from later ones reusing its id.
+    token: u64,
+    // Set once the request has been sent, to match exceptions against it.
+    send_id: Option<DWORD>,
+    waiter: Waiter,
+}
+
+type PendingMap = Arc<Mutex<HashMap<SIMCONNECT_DATA_REQUEST_ID, Pending>>>;
+
+// Unregisters a request once it is answered, timed out or its future dropped.
+struct Registration {
+    pending: PendingMap,
+    request_id: SIMCONNECT_DATA_REQUEST_ID,
+    token: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending
+            .get(&self.request_id)
+            .is_some_and(|p| p.token == self.token)
+        {
+            pending.remove(&self.request_id);
+        }
+    }
+}
+
+pub struct MessageStream {
+    receiver: mpsc::Receiver<Message>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl MessageStream {
+    // Messages dropped because this stream fell behind.
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for MessageStream {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[derive(Clone)]
+pub struct AsyncSimConnect {
+    client: SimConnectClient,
+    pending: PendingMap,
+    next_token: Arc<AtomicU64>,
+}
+
+impl AsyncSimConnect {
+    pub async fn open(program_name: &str) -> SimConnectResult<Self> {
+        Self::open_with(program_name, ClientOptions::new()).await
+    }
+
+    // Must be called from within a tokio runtime.
+    pub async fn open_with(program_name: &str, options: ClientOptions) -> SimConnectResult<Self> {
+        let program_name = program_name.to_string();
+        let client = tokio::task::spawn_blocking(move || {
+            SimConnectClient::open_with(&program_name, options)
+        })
+        .await
+        .map_err(|_| SimConnectError::new("SimConnect worker panicked", None))??;
+
+        let pending: PendingMap = Arc::new(Mutex::new(HashMap::new()));
+        let messages = subscribe(&client);
+        tokio::spawn(dispatch(messages, pending.clone()));
+
+        Ok(Self {
+            client,
+            pending,
+            next_token: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn client(&self) -> &SimConnectClient {
+        &self.client
+    }
+
+    pub fn messages(&self) -> MessageStream {
+        subscribe(&self.client)
+    }
+
+    // Runs `f` on the worker thread. Waits with backoff while the call queue
+    // is full rather than blocking the runtime.
+    pub async fn call<R, F>(&self, f: F) -> SimConnectResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&SimConnect) -> SimConnectResult<R> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let mut job: Job = Box::new(move |simconnect: &SimConnect| {
+            let _ = reply.send(f(simconnect));
+        });
+
+        let mut wait = Duration::from_millis(1);
+        loop {
+            match self.client.try_submit(job) {
+                Ok(()) => break,
+                Err(TrySendError::Full(returned)) => {
+                    job = returned;
+                    tokio::time::sleep(wait).await;
+                    wait = (wait * 2).min(MAX_SUBMIT_WAIT);
+                }
+                Err(TrySendError::Disconnected(_)) => return Err(stopped()),
+            }
+        }
+
+        result.await.map_err(|_| stopped())?
+    }
+
+    pub async fn request_system_state(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        state: &str,
+        timeout: Duration,
+    ) -> SimConnectResult<SystemStateData> {
+        let (sender, result) = oneshot::channel();
+        let state = state.to_string();
+        let registration = self
+            .request(request_id, Waiter::SystemState(sender), move |simconnect| {
+                simconnect.request_system_state(request_id, &state)
+            })
+            .await?;
+        Self::wait(registration, result, timeout).await
+    }
+
+    // `request_data_on_sim_object` with `Period::Once`.
+    pub async fn request_data_once(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        timeout: Duration,
+    ) -> SimConnectResult<ObjectData> {
+        let (sender, result) = oneshot::channel();
+        let registration = self
+            .request(request_id, Waiter::Data(sender), move |simconnect| {
+                simconnect.request_data_on_sim_object(
+                    request_id,
+                    define_id,
+                    object_id,
+                    Period::Once,
+                    DataRequestFlag::Default,
+                    0,
+                    0,
+                    0,
+                )
+            })
+            .await?;
+        Self::wait(registration, result, timeout).await
+    }
+
+    // Runs one of the `ai_create_*` calls, which must pass on `request_id`,
+    // and resolves with the id the sim assigned to the new object.
+    pub async fn create_object<F>(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        create: F,
+        timeout: Duration,
+    ) -> SimConnectResult<SIMCONNECT_OBJECT_ID>
+    where
+        F: FnOnce(&SimConnect) -> SimConnectResult<()> + Send + 'static,
+    {
+        let (sender, result) = oneshot::channel();
+        let registration = self
+            .request(request_id, Waiter::ObjectId(sender), create)
+            .await?;
+        Self::wait(registration, result, timeout).await
+    }
+
+    // Resolves once every chunk of the list has arrived.
+    pub async fn request_facilities_list(
+        &self,
+        type_: FacilityListType,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        timeout: Duration,
+    ) -> SimConnectResult<Facilities> {
+        let (sender, result) = oneshot::channel();
+        let waiter = Waiter::Facilities {
+            sender,
+            collected: None,
+        };
+        let registration = self
+            .request(request_id, waiter, move |simconnect| {
+                simconnect.request_facilities_list(type_, request_id)
+            })
+            .await?;
+        Self::wait(registration, result, timeout).await
+    }
+
+    async fn request<F>(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        waiter: Waiter,
+        send: F,
+    ) -> SimConnectResult<Registration>
+    where
+        F: FnOnce(&SimConnect) -> SimConnectResult<()> + Send + 'static,
+    {
+        // Registered first, the reply may arrive before `call` returns.
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let pending = Pending {
+            token,
+            send_id: None,
+            waiter,
+        };
+        if let Some(previous) = self.pending.lock().unwrap().insert(request_id, pending) {
+            previous.waiter.fail(SimConnectError::new(
+                "Request was replaced by one with the same id",
+                None,
+            ));
+        }
+        let registration = Registration {
+            pending: self.pending.clone(),
+            request_id,
+            token,
+        };
+
+        // The send id is stored by the worker itself, before it dispatches
+        // again and could pass on an exception caused by the request.
+        let pending = self.pending.clone();
+        self.call(move |simconnect| {
+            send(simconnect)?;
+            let send_id = simconnect.get_last_sent_packet_id()?;
+            if let Some(pending) = pending.lock().unwrap().get_mut(&request_id) {
+                if pending.token == token {
+                    pending.send_id = Some(send_id);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+        Ok(registration)
+    }
+
+    async fn wait<T>(
+        registration: Registration,
+        result: oneshot::Receiver<SimConnectResult<T>>,
+        timeout: Duration,
+    ) -> SimConnectResult<T> {
+        match tokio::time::timeout(timeout, result).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(stopped()),
+            Err(_) => Err(SimConnectError::new(
+                &format!("Request {} timed out", registration.request_id),
+                None,
+            )),
+        }
+    }
+}
+
+fn subscribe(client: &SimConnectClient) -> MessageStream {
+    let (sender, receiver) = mpsc::channel(client.message_queue());
+    let lagged = Arc::new(AtomicU64::new(0));
+    client.add_subscriber(Subscriber::new(Sink::Tokio(sender), lagged.clone()));
+    MessageStream { receiver, lagged }
+}
+
+async fn dispatch(mut messages: MessageStream, pending: PendingMap) {
+    while let Some(message) = messages.receiver.recv().await {
+        resolve(&pending, message);
+    }
+
+    for (_, pending) in pending.lock().unwrap().drain() {
+        pending.waiter.fail(stopped());
+    }
+}
+
+fn resolve(pending: &PendingMap, message: Message) {
+    let mut pending = pending.lock().unwrap();
+
+    if let Message::Exception(exception) = &message {
+        let request_id = pending
+            .iter()
+            .find(|(_, p)| p.send_id == Some(exception.send_id))
+            .map(|(id, _)| *id);
+        if let Some(entry) = request_id.and_then(|id| pending.remove(&id)) {
+            entry.waiter.fail(SimConnectError::new(
+                &format!("SimConnect exception {}", exception.exception),
+                None,
+            ));
+        }
+        return;
+    }
+
+    let request_id = match &message {
+        Message::SystemState(data) => data.request_id,
+        Message::SimObjectData(data) => data.request_id,
+        Message::AssignedObjectId { request_id, .. } => *request_id,
+        Message::AirportList(list) => list.request_id,
+        Message::WaypointList(list) => list.request_id,
+        Message::NdbList(list) => list.request_id,
+        Message::VorList(list) => list.request_id,
+        _ => return,
+    };
+    let entry = match pending.remove(&request_id) {
+        Some(entry) => entry,
+        None => return,
+    };
+
+    match (entry.waiter, message) {
+        (Waiter::SystemState(sender), Message::SystemState(data)) => {
+            let _ = sender.send(Ok(data));
+        }
+        (Waiter::Data(sender), Message::SimObjectData(data)) => {
+            let _ = sender.send(Ok(data));
+        }
+        (Waiter::ObjectId(sender), Message::AssignedObjectId { object_id, .. }) => {
+            let _ = sender.send(Ok(object_id));
+        }
+        (
+            Waiter::Facilities { sender, collected },
+            message @ (Message::AirportList(_)
+            | Message::WaypointList(_)
+            | Message::NdbList(_)
+            | Message::VorList(_)),
+        ) => {
+            let (collected, done) = collect(collected, message);
+            match done {
+                true => {
+                    let _ = sender.send(collected.ok_or_else(|| {
+                        SimConnectError::new("Unexpected reply to a facilities request", None)
+                    }));
+                }
+                false => {
+                    let waiter = Waiter::Facilities { sender, collected };
+                    pending.insert(
+                        request_id,
+                        Pending {
+                            token: entry.token,
+                            send_id: entry.send_id,
+                            waiter,
+                        },
+                    );
+                }
+            }
+        }
+        // A reply of another kind reusing the id, keep waiting.
+        (waiter, _) => {
+            pending.insert(
+                request_id,
+                Pending {
+                    token: entry.token,
+                    send_id: entry.send_id,
+                    waiter,
+                },
+            );
+        }
+    }
+}
+
+// Appends a chunk of a facilities list, returning whether it was the last one.
+// A chunk of another kind than the ones before it yields `None`.
+fn collect(collected: Option<Facilities>, message: Message) -> (Option<Facilities>, bool) {
+    let (entry_number, out_of) = match &message {
+        Message::AirportList(list) => (list.entry_number, list.out_of),
+        Message::WaypointList(list) => (list.entry_number, list.out_of),
+        Message::NdbList(list) => (list.entry_number, list.out_of),
+        Message::VorList(list) => (list.entry_number, list.out_of),
+        _ => return (None, true),
+    };
+
+    let collected = match (collected, message) {
+        (None, Message::AirportList(list)) => Facilities::Airports(list.items),
+        (None, Message::WaypointList(list)) => Facilities::Waypoints(list.items),
+        (None, Message::NdbList(list)) => Facilities::Ndbs(list.items),
+        (None, Message::VorList(list)) => Facilities::Vors(list.items),
+        (Some(Facilities::Airports(mut items)), Message::AirportList(list)) => {
+            items.extend(list.items);
+            Facilities::Airports(items)
+        }
+        (Some(Facilities::Waypoints(mut items)), Message::WaypointList(list)) => {
+            items.extend(list.items);
+            Facilities::Waypoints(items)
+        }
+        (Some(Facilities::Ndbs(mut items)), Message::NdbList(list)) => {
+            items.extend(list.items);
+            Facilities::Ndbs(items)
+        }
+        (Some(Facilities::Vors(mut items)), Message::VorList(list)) => {
+            items.extend(list.items);
+            Facilities::Vors(items)
+        }
+        _ => return (None, true),
+    };
+
+    (Some(collected), entry_number + 1 >= out_of)
+}
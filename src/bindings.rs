@@ -2,5 +2,243 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 #![allow(dead_code)]
+#![allow(clippy::upper_case_acronyms)]
 
+#[cfg(feature = "sdk")]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(not(feature = "sdk"))]
+pub use portable::*;
+
+// Without the SDK there is nothing to generate bindings from or link
+// against. The types and constants of SimConnect.h that the portable modules
+// use are written out here as bindgen emits them on Windows, so messages can
+// be decoded and sessions replayed anywhere.
+#[cfg(not(feature = "sdk"))]
+mod portable {
+    pub type DWORD = u32;
+    pub type HRESULT = i32;
+    pub type HANDLE = *mut ::std::os::raw::c_void;
+    pub type HWND = *mut ::std::os::raw::c_void;
+    pub type BOOL = i32;
+    pub type LPCSTR = *const ::std::os::raw::c_char;
+
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct GUID {
+        pub Data1: u32,
+        pub Data2: u16,
+        pub Data3: u16,
+        pub Data4: [u8; 8],
+    }
+
+    pub const S_OK: HRESULT = 0;
+    pub const E_FAIL: HRESULT = -2147467259;
+    pub const MAX_PATH: u32 = 260;
+
+    pub type SIMCONNECT_OBJECT_ID = DWORD;
+    pub type SIMCONNECT_DATA_REQUEST_ID = DWORD;
+    pub type SIMCONNECT_DATA_DEFINITION_ID = DWORD;
+    pub type SIMCONNECT_CLIENT_DATA_ID = DWORD;
+    pub type SIMCONNECT_CLIENT_DATA_DEFINITION_ID = DWORD;
+    pub type SIMCONNECT_CLIENT_EVENT_ID = DWORD;
+    pub type SIMCONNECT_NOTIFICATION_GROUP_ID = DWORD;
+    pub type SIMCONNECT_INPUT_GROUP_ID = DWORD;
+
+    pub const SIMCONNECT_UNUSED: DWORD = 4294967295;
+    pub const SIMCONNECT_OBJECT_ID_USER: DWORD = 0;
+    pub const SIMCONNECT_CAMERA_IGNORE_FIELD: f32 = 3.4028235e38;
+    pub const SIMCONNECT_CLIENTDATA_MAX_SIZE: DWORD = 8192;
+    pub const SIMCONNECT_OPEN_CONFIGINDEX_LOCAL: DWORD = 4294967295;
+
+    pub const SIMCONNECT_CLIENTDATATYPE_INT8: DWORD = 4294967295;
+    pub const SIMCONNECT_CLIENTDATATYPE_INT16: DWORD = 4294967294;
+    pub const SIMCONNECT_CLIENTDATATYPE_INT32: DWORD = 4294967293;
+    pub const SIMCONNECT_CLIENTDATATYPE_INT64: DWORD = 4294967292;
+    pub const SIMCONNECT_CLIENTDATATYPE_FLOAT32: DWORD = 4294967291;
+    pub const SIMCONNECT_CLIENTDATATYPE_FLOAT64: DWORD = 4294967290;
+
+    pub const SIMCONNECT_CLIENTDATAOFFSET_AUTO: DWORD = 4294967295;
+
+    pub type SIMCONNECT_DATATYPE = ::std::os::raw::c_int;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INVALID: SIMCONNECT_DATATYPE = 0;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INT32: SIMCONNECT_DATATYPE = 1;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INT64: SIMCONNECT_DATATYPE = 2;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_FLOAT32: SIMCONNECT_DATATYPE = 3;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_FLOAT64: SIMCONNECT_DATATYPE = 4;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRING8: SIMCONNECT_DATATYPE = 5;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRING32: SIMCONNECT_DATATYPE = 6;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRING64: SIMCONNECT_DATATYPE = 7;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRING128: SIMCONNECT_DATATYPE = 8;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRING256: SIMCONNECT_DATATYPE = 9;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRING260: SIMCONNECT_DATATYPE = 10;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_STRINGV: SIMCONNECT_DATATYPE = 11;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INITPOSITION: SIMCONNECT_DATATYPE = 12;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_MARKERSTATE: SIMCONNECT_DATATYPE = 13;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_WAYPOINT: SIMCONNECT_DATATYPE = 14;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_LATLONALT: SIMCONNECT_DATATYPE = 15;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_XYZ: SIMCONNECT_DATATYPE = 16;
+    pub const SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_MAX: SIMCONNECT_DATATYPE = 17;
+
+    pub type SIMCONNECT_CREATE_CLIENT_DATA_FLAG = DWORD;
+    pub const SIMCONNECT_CREATE_CLIENT_DATA_FLAG_DEFAULT: DWORD = 0;
+    pub const SIMCONNECT_CREATE_CLIENT_DATA_FLAG_READ_ONLY: DWORD = 1;
+
+    pub type SIMCONNECT_PERIOD = ::std::os::raw::c_int;
+    pub const SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_NEVER: SIMCONNECT_PERIOD = 0;
+    pub const SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_ONCE: SIMCONNECT_PERIOD = 1;
+    pub const SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_VISUAL_FRAME: SIMCONNECT_PERIOD = 2;
+    pub const SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_SIM_FRAME: SIMCONNECT_PERIOD = 3;
+    pub const SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_SECOND: SIMCONNECT_PERIOD = 4;
+
+    pub type SIMCONNECT_DATA_REQUEST_FLAG = DWORD;
+    pub const SIMCONNECT_DATA_REQUEST_FLAG_DEFAULT: DWORD = 0;
+    pub const SIMCONNECT_DATA_REQUEST_FLAG_CHANGED: DWORD = 1;
+    pub const SIMCONNECT_DATA_REQUEST_FLAG_TAGGED: DWORD = 2;
+
+    pub type SIMCONNECT_SIMOBJECT_TYPE = ::std::os::raw::c_int;
+    pub const SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_USER: SIMCONNECT_SIMOBJECT_TYPE =
+        0;
+    pub const SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_ALL: SIMCONNECT_SIMOBJECT_TYPE =
+        1;
+    pub const SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_AIRCRAFT:
+        SIMCONNECT_SIMOBJECT_TYPE = 2;
+    pub const SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_HELICOPTER:
+        SIMCONNECT_SIMOBJECT_TYPE = 3;
+    pub const SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_BOAT: SIMCONNECT_SIMOBJECT_TYPE =
+        4;
+    pub const SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_GROUND:
+        SIMCONNECT_SIMOBJECT_TYPE = 5;
+
+    pub type SIMCONNECT_FACILITY_LIST_TYPE = ::std::os::raw::c_int;
+    pub const SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_AIRPORT:
+        SIMCONNECT_FACILITY_LIST_TYPE = 0;
+    pub const SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_WAYPOINT:
+        SIMCONNECT_FACILITY_LIST_TYPE = 1;
+    pub const SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_NDB:
+        SIMCONNECT_FACILITY_LIST_TYPE = 2;
+    pub const SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_VOR:
+        SIMCONNECT_FACILITY_LIST_TYPE = 3;
+    pub const SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_COUNT:
+        SIMCONNECT_FACILITY_LIST_TYPE = 4;
+
+    pub type SIMCONNECT_CLIENT_DATA_SET_FLAG = DWORD;
+    pub const SIMCONNECT_CLIENT_DATA_SET_FLAG_DEFAULT: DWORD = 0;
+    pub const SIMCONNECT_CLIENT_DATA_SET_FLAG_TAGGED: DWORD = 1;
+
+    pub type SIMCONNECT_DATA_SET_FLAG = DWORD;
+    pub const SIMCONNECT_DATA_SET_FLAG_DEFAULT: DWORD = 0;
+    pub const SIMCONNECT_DATA_SET_FLAG_TAGGED: DWORD = 1;
+
+    pub type SIMCONNECT_STATE = ::std::os::raw::c_int;
+    pub const SIMCONNECT_STATE_SIMCONNECT_STATE_OFF: SIMCONNECT_STATE = 0;
+    pub const SIMCONNECT_STATE_SIMCONNECT_STATE_ON: SIMCONNECT_STATE = 1;
+
+    pub type SIMCONNECT_EVENT_FLAG = DWORD;
+    pub const SIMCONNECT_EVENT_FLAG_DEFAULT: DWORD = 0;
+    pub const SIMCONNECT_EVENT_FLAG_FAST_REPEAT_TIMER: DWORD = 1;
+    pub const SIMCONNECT_EVENT_FLAG_SLOW_REPEAT_TIMER: DWORD = 2;
+    pub const SIMCONNECT_EVENT_FLAG_GROUPID_IS_PRIORITY: DWORD = 16;
+
+    pub type SIMCONNECT_CLIENT_DATA_PERIOD = ::std::os::raw::c_int;
+    pub const SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_NEVER:
+        SIMCONNECT_CLIENT_DATA_PERIOD = 0;
+    pub const SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_ONCE:
+        SIMCONNECT_CLIENT_DATA_PERIOD = 1;
+    pub const SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_VISUAL_FRAME:
+        SIMCONNECT_CLIENT_DATA_PERIOD = 2;
+    pub const SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_ON_SET:
+        SIMCONNECT_CLIENT_DATA_PERIOD = 3;
+    pub const SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_SECOND:
+        SIMCONNECT_CLIENT_DATA_PERIOD = 4;
+
+    pub type SIMCONNECT_CLIENT_DATA_REQUEST_FLAG = DWORD;
+    pub const SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_DEFAULT: DWORD = 0;
+    pub const SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_CHANGED: DWORD = 1;
+    pub const SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_TAGGED: DWORD = 2;
+
+    pub const SIMCONNECT_GROUP_PRIORITY_HIGHEST: DWORD = 1;
+    pub const SIMCONNECT_GROUP_PRIORITY_HIGHEST_MASKABLE: DWORD = 10000000;
+    pub const SIMCONNECT_GROUP_PRIORITY_STANDARD: DWORD = 1900000000;
+    pub const SIMCONNECT_GROUP_PRIORITY_DEFAULT: DWORD = 2000000000;
+    pub const SIMCONNECT_GROUP_PRIORITY_LOWEST: DWORD = 4000000000;
+
+    pub type SIMCONNECT_RECV_ID = ::std::os::raw::c_int;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_NULL: SIMCONNECT_RECV_ID = 0;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EXCEPTION: SIMCONNECT_RECV_ID = 1;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_OPEN: SIMCONNECT_RECV_ID = 2;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_QUIT: SIMCONNECT_RECV_ID = 3;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT: SIMCONNECT_RECV_ID = 4;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_OBJECT_ADDREMOVE: SIMCONNECT_RECV_ID = 5;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_FILENAME: SIMCONNECT_RECV_ID = 6;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_FRAME: SIMCONNECT_RECV_ID = 7;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SIMOBJECT_DATA: SIMCONNECT_RECV_ID = 8;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SIMOBJECT_DATA_BYTYPE: SIMCONNECT_RECV_ID = 9;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_WEATHER_OBSERVATION: SIMCONNECT_RECV_ID = 10;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_CLOUD_STATE: SIMCONNECT_RECV_ID = 11;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_ASSIGNED_OBJECT_ID: SIMCONNECT_RECV_ID = 12;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_RESERVED_KEY: SIMCONNECT_RECV_ID = 13;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_CUSTOM_ACTION: SIMCONNECT_RECV_ID = 14;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SYSTEM_STATE: SIMCONNECT_RECV_ID = 15;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_CLIENT_DATA: SIMCONNECT_RECV_ID = 16;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_WEATHER_MODE: SIMCONNECT_RECV_ID = 17;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_AIRPORT_LIST: SIMCONNECT_RECV_ID = 18;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_VOR_LIST: SIMCONNECT_RECV_ID = 19;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_NDB_LIST: SIMCONNECT_RECV_ID = 20;
+    pub const SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_WAYPOINT_LIST: SIMCONNECT_RECV_ID = 21;
+
+    pub type SIMCONNECT_EXCEPTION = ::std::os::raw::c_int;
+    pub const SIMCONNECT_EXCEPTION_SIMCONNECT_EXCEPTION_NONE: SIMCONNECT_EXCEPTION = 0;
+    pub const SIMCONNECT_EXCEPTION_SIMCONNECT_EXCEPTION_ERROR: SIMCONNECT_EXCEPTION = 1;
+    pub const SIMCONNECT_EXCEPTION_SIMCONNECT_EXCEPTION_UNRECOGNIZED_ID: SIMCONNECT_EXCEPTION = 3;
+    pub const SIMCONNECT_EXCEPTION_SIMCONNECT_EXCEPTION_NAME_UNRECOGNIZED: SIMCONNECT_EXCEPTION = 7;
+    pub const SIMCONNECT_EXCEPTION_SIMCONNECT_EXCEPTION_DATA_ERROR: SIMCONNECT_EXCEPTION = 20;
+
+    #[repr(C, packed)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct SIMCONNECT_RECV {
+        pub dwSize: DWORD,
+        pub dwVersion: DWORD,
+        pub dwID: DWORD,
+    }
+
+    #[repr(C, packed)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct SIMCONNECT_DATA_INITPOSITION {
+        pub Latitude: f64,
+        pub Longitude: f64,
+        pub Altitude: f64,
+        pub Pitch: f64,
+        pub Bank: f64,
+        pub Heading: f64,
+        pub OnGround: DWORD,
+        pub Airspeed: DWORD,
+    }
+
+    #[repr(C, packed)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct SIMCONNECT_DATA_LATLONALT {
+        pub Latitude: f64,
+        pub Longitude: f64,
+        pub Altitude: f64,
+    }
+
+    #[repr(C, packed)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct SIMCONNECT_DATA_WAYPOINT {
+        pub Latitude: f64,
+        pub Longitude: f64,
+        pub Altitude: f64,
+        pub Flags: DWORD,
+        pub ktsSpeed: f64,
+        pub percentThrottle: f64,
+    }
+
+    #[repr(C, packed)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct SIMCONNECT_DATA_XYZ {
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+    }
+}
@@ -0,0 +1,86 @@
+// What the SDK documentation says about common simvars. Only used to catch
+// mistakes early, simvars missing from the list are passed on to SimConnect.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimVarInfo {
+    pub name: &'static str,
+    pub units: &'static str,
+    pub settable: bool,
+}
+
+const fn var(name: &'static str, units: &'static str, settable: bool) -> SimVarInfo {
+    SimVarInfo {
+        name,
+        units,
+        settable,
+    }
+}
+
+const SIMVARS: &[SimVarInfo] = &[
+    var("ABSOLUTE TIME", "seconds", false),
+    var("AI CURRENT WAYPOINT", "number", true),
+    var("AI DESIRED HEADING", "degrees", true),
+    var("AI DESIRED SPEED", "knots", true),
+    var("AI GROUNDCRUISESPEED", "knots", true),
+    var("AI GROUNDTURNSPEED", "knots", true),
+    var("AI GROUNDTURNTIME", "seconds", true),
+    var("AI TRAFFIC CURRENT AIRPORT", "string", false),
+    var("AI TRAFFIC STATE", "string", false),
+    var("AI WAYPOINT LIST", "", true),
+    var("AIRSPEED TRUE", "knots", true),
+    var("AMBIENT PRESSURE", "inHg", false),
+    var("AMBIENT TEMPERATURE", "celsius", false),
+    var("AMBIENT WIND DIRECTION", "degrees", false),
+    var("AMBIENT WIND VELOCITY", "knots", false),
+    var("ATC AIRLINE", "string", true),
+    var("ATC FLIGHT NUMBER", "string", true),
+    var("ATC ID", "string", true),
+    var("ATC MODEL", "string", false),
+    var("ATC TYPE", "string", false),
+    var("BRAKE PARKING POSITION", "bool", true),
+    var("CAMERA STATE", "enum", true),
+    var("CATEGORY", "string", false),
+    var("ELEVATOR TRIM POSITION", "radians", true),
+    var("FLAPS HANDLE INDEX", "number", true),
+    var("FUEL TOTAL CAPACITY", "gallons", false),
+    var("FUEL TOTAL QUANTITY", "gallons", false),
+    var("GEAR HANDLE POSITION", "bool", true),
+    var("GENERAL ENG THROTTLE LEVER POSITION", "percent", true),
+    var("GROUND ALTITUDE", "feet", false),
+    var("GROUND VELOCITY", "knots", false),
+    var("INDICATED ALTITUDE", "feet", true),
+    var("LOCAL TIME", "seconds", false),
+    var("NUMBER OF ENGINES", "number", false),
+    var("PLANE ALTITUDE", "feet", true),
+    var("PLANE BANK DEGREES", "radians", true),
+    var("PLANE HEADING DEGREES MAGNETIC", "radians", true),
+    var("PLANE HEADING DEGREES TRUE", "radians", true),
+    var("PLANE LATITUDE", "radians", true),
+    var("PLANE LONGITUDE", "radians", true),
+    var("PLANE PITCH DEGREES", "radians", true),
+    var("ROTATION VELOCITY BODY X", "feet per second", true),
+    var("ROTATION VELOCITY BODY Y", "feet per second", true),
+    var("ROTATION VELOCITY BODY Z", "feet per second", true),
+    var("SIM ON GROUND", "bool", false),
+    var("SIMULATION RATE", "number", false),
+    var("STRUCT LATLONALT", "", false),
+    var("TITLE", "string", false),
+    var("VELOCITY BODY X", "feet per second", true),
+    var("VELOCITY BODY Y", "feet per second", true),
+    var("VELOCITY BODY Z", "feet per second", true),
+    var("VELOCITY WORLD X", "feet per second", true),
+    var("VELOCITY WORLD Y", "feet per second", true),
+    var("VELOCITY WORLD Z", "feet per second", true),
+    var("VERTICAL SPEED", "feet per second", true),
+    var("ZULU TIME", "seconds", false),
+];
+
+// Case insensitive, an index suffix such as `:1` is ignored.
+pub fn lookup(name: &str) -> Option<&'static SimVarInfo> {
+    let name = name.split(':').next().unwrap_or(name).trim();
+    SIMVARS.iter().find(|v| v.name.eq_ignore_ascii_case(name))
+}
+
+pub fn is_read_only(name: &str) -> bool {
+    lookup(name).is_some_and(|v| !v.settable)
+}
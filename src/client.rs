@@ -0,0 +1,324 @@
+// A cloneable, thread-safe front for `SimConnect`. The connection lives on a
+// worker thread which runs queued calls and polls for messages.
+//
+// Backpressure: calls go through a bounded queue, so `call` blocks while the
+// worker is behind. Messages are never allowed to stall the worker, a
+// subscriber whose queue is full misses the message and has it counted in
+// `Subscription::lagged`.
+
+use super::message::Message;
+use super::simconnect::SimConnect;
+use super::types::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub(crate) type Job = Box<dyn FnOnce(&SimConnect) + Send>;
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    open: OpenOptions,
+    call_queue: usize,
+    message_queue: usize,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+}
+
+impl ClientOptions {
+    pub fn new() -> Self {
+        Self {
+            open: OpenOptions::new(),
+            call_queue: 64,
+            message_queue: 256,
+            poll_interval: Duration::from_millis(1),
+            max_poll_interval: Duration::from_millis(50),
+        }
+    }
+
+    pub fn open_options(mut self, options: OpenOptions) -> Self {
+        self.open = options;
+        self
+    }
+
+    pub fn call_queue(mut self, size: usize) -> Self {
+        self.call_queue = size.max(1);
+        self
+    }
+
+    // Per subscriber.
+    pub fn message_queue(mut self, size: usize) -> Self {
+        self.message_queue = size.max(1);
+        self
+    }
+
+    // The worker polls every `min` while messages arrive and doubles the wait
+    // up to `max` while the queue stays empty. Calls always wake it at once.
+    pub fn poll_interval(mut self, min: Duration, max: Duration) -> Self {
+        self.poll_interval = min;
+        self.max_poll_interval = max.max(min);
+        self
+    }
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions::new()
+    }
+}
+
+pub(crate) enum Sink {
+    Sync(SyncSender<Message>),
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::sync::mpsc::Sender<Message>),
+}
+
+pub(crate) struct Subscriber {
+    sink: Sink,
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscriber {
+    pub(crate) fn new(sink: Sink, lagged: Arc<AtomicU64>) -> Self {
+        Self { sink, lagged }
+    }
+
+    // Returns false once the receiving side has gone away.
+    fn offer(&self, message: &Message) -> bool {
+        let full = match &self.sink {
+            Sink::Sync(sender) => match sender.try_send(message.clone()) {
+                Ok(()) => false,
+                Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => return false,
+            },
+            #[cfg(feature = "tokio")]
+            Sink::Tokio(sender) => {
+                use tokio::sync::mpsc::error::TrySendError;
+                match sender.try_send(message.clone()) {
+                    Ok(()) => false,
+                    Err(TrySendError::Full(_)) => true,
+                    Err(TrySendError::Closed(_)) => return false,
+                }
+            }
+        };
+        if full {
+            self.lagged.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+pub struct Subscription {
+    receiver: Receiver<Message>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    pub fn recv(&self) -> Option<Message> {
+        self.receiver.recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Message> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    pub fn try_recv(&self) -> Option<Message> {
+        self.receiver.try_recv().ok()
+    }
+
+    // Messages dropped because this subscription fell behind.
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+pub struct SimConnectClient {
+    jobs: SyncSender<Job>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    message_queue: usize,
+    closed: Arc<AtomicBool>,
+    worker: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl SimConnectClient {
+    pub fn open(program_name: &str) -> SimConnectResult<Self> {
+        Self::open_with(program_name, ClientOptions::new())
+    }
+
+    pub fn open_with(program_name: &str, options: ClientOptions) -> SimConnectResult<Self> {
+        let (jobs, incoming) = mpsc::sync_channel::<Job>(options.call_queue);
+        let (opened, result) = mpsc::channel();
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let worker = {
+            let subscribers = subscribers.clone();
+            let closed = closed.clone();
+            let program_name = program_name.to_string();
+            let open = options.open.clone();
+            let poll_interval = (options.poll_interval, options.max_poll_interval);
+
+            thread::spawn(move || {
+                let mut simconnect = SimConnect::new();
+                let open = simconnect.open_with(&program_name, &open);
+                let failed = open.is_err();
+                let _ = opened.send(open);
+                if failed {
+                    return;
+                }
+
+                run(&simconnect, &incoming, &subscribers, &closed, poll_interval);
+                let _ = simconnect.close();
+
+                // Ends every subscription, including ones racing with shutdown.
+                let mut subscribers = subscribers.lock().unwrap();
+                closed.store(true, Ordering::Relaxed);
+                subscribers.clear();
+            })
+        };
+
+        match result.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(SimConnectError::new("SimConnect worker panicked", None)),
+        }
+
+        Ok(Self {
+            jobs,
+            subscribers,
+            message_queue: options.message_queue,
+            closed,
+            worker: Arc::new(Mutex::new(Some(worker))),
+        })
+    }
+
+    // Runs `f` on the worker thread and waits for its result.
+    pub fn call<R, F>(&self, f: F) -> SimConnectResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&SimConnect) -> SimConnectResult<R> + Send + 'static,
+    {
+        let (reply, result) = mpsc::sync_channel(1);
+        self.jobs
+            .send(Box::new(move |simconnect: &SimConnect| {
+                let _ = reply.send(f(simconnect));
+            }))
+            .map_err(|_| stopped())?;
+        result.recv().map_err(|_| stopped())?
+    }
+
+    // Queues `f` without waiting, failing instead of blocking when the queue
+    // is full. Errors from `f` are dropped.
+    pub fn try_send<F>(&self, f: F) -> SimConnectResult<()>
+    where
+        F: FnOnce(&SimConnect) -> SimConnectResult<()> + Send + 'static,
+    {
+        let job: Job = Box::new(move |simconnect: &SimConnect| {
+            let _ = f(simconnect);
+        });
+        match self.jobs.try_send(job) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(SimConnectError::new("Call queue is full", None)),
+            Err(TrySendError::Disconnected(_)) => Err(stopped()),
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn try_submit(&self, job: Job) -> Result<(), TrySendError<Job>> {
+        self.jobs.try_send(job)
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(self.message_queue);
+        let lagged = Arc::new(AtomicU64::new(0));
+        self.add_subscriber(Subscriber::new(Sink::Sync(sender), lagged.clone()));
+        Subscription { receiver, lagged }
+    }
+
+    pub(crate) fn add_subscriber(&self, subscriber: Subscriber) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if !self.closed.load(Ordering::Relaxed) {
+            subscribers.push(subscriber);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn message_queue(&self) -> usize {
+        self.message_queue
+    }
+
+    pub fn server_info(&self) -> SimConnectResult<Option<ServerInfo>> {
+        self.call(|simconnect| Ok(simconnect.server_info()))
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.worker
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|w| !w.is_finished())
+    }
+
+    // Closes the connection for every clone of this client. Subscriptions
+    // end once the queued messages have been received.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(
+    simconnect: &SimConnect,
+    jobs: &Receiver<Job>,
+    subscribers: &Mutex<Vec<Subscriber>>,
+    closed: &AtomicBool,
+    (min_interval, max_interval): (Duration, Duration),
+) {
+    let mut interval = min_interval;
+    while !closed.load(Ordering::Relaxed) {
+        loop {
+            match jobs.try_recv() {
+                Ok(job) => job(simconnect),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        let mut idle = true;
+        while let Ok(Some(message)) = simconnect.get_next_message() {
+            idle = false;
+            let quit = matches!(message, Message::Quit);
+            broadcast(subscribers, message);
+            if quit {
+                return;
+            }
+        }
+
+        interval = match idle {
+            true => (interval * 2).min(max_interval),
+            false => min_interval,
+        };
+
+        match jobs.recv_timeout(interval) {
+            Ok(job) => {
+                job(simconnect);
+                interval = min_interval;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+fn broadcast(subscribers: &Mutex<Vec<Subscriber>>, message: Message) {
+    let mut subscribers = subscribers.lock().unwrap();
+    subscribers.retain(|s| s.offer(&message));
+}
+
+pub(crate) fn stopped() -> SimConnectError {
+    SimConnectError::new("SimConnect worker has stopped", None)
+}
@@ -0,0 +1,349 @@
+// Reads and writes SimConnect.cfg, the client side file that tells SimConnect
+// where to find a (possibly remote) simulator. `[SimConnect]` is entry 0 and
+// `[SimConnect.N]` is entry N, selected with `OpenOptions::config_index`.
+
+use super::types::*;
+use std::fmt;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+const SECTION: &str = "SimConnect";
+
+// Keys in `[SimConnect]` that configure logging rather than a connection.
+const GLOBAL_KEYS: [&str; 7] = [
+    "level",
+    "console",
+    "redirectstdouttoconsole",
+    "outputdebugstring",
+    "file",
+    "file_next_index",
+    "file_max_index",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Ipv4,
+    Ipv6,
+    Pipe,
+}
+
+impl Protocol {
+    fn parse(value: &str) -> SimConnectResult<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "ipv4" => Ok(Protocol::Ipv4),
+            "ipv6" => Ok(Protocol::Ipv6),
+            "pipe" => Ok(Protocol::Pipe),
+            _ => Err(SimConnectError::new(
+                &format!("Unknown protocol '{}'", value),
+                None,
+            )),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Ipv4 => "IPv4",
+            Protocol::Ipv6 => "IPv6",
+            Protocol::Pipe => "Pipe",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigEntry {
+    pub index: u32,
+    pub protocol: Protocol,
+    pub address: Option<String>,
+    // A port number for IPv4 and IPv6, a pipe name for Pipe.
+    pub port: Option<String>,
+    pub max_receive_size: Option<u32>,
+    pub disable_nagle: Option<bool>,
+    pub other: Vec<(String, String)>,
+}
+
+impl ConfigEntry {
+    pub fn ipv4(index: u32, address: Ipv4Addr, port: u16) -> Self {
+        Self::new(index, Protocol::Ipv4, address.to_string(), port.to_string())
+    }
+
+    pub fn ipv6(index: u32, address: Ipv6Addr, port: u16) -> Self {
+        Self::new(index, Protocol::Ipv6, address.to_string(), port.to_string())
+    }
+
+    pub fn pipe(index: u32, address: &str, pipe_name: &str) -> Self {
+        Self::new(
+            index,
+            Protocol::Pipe,
+            address.to_string(),
+            pipe_name.to_string(),
+        )
+    }
+
+    fn new(index: u32, protocol: Protocol, address: String, port: String) -> Self {
+        Self {
+            index,
+            protocol,
+            address: Some(address),
+            port: Some(port),
+            max_receive_size: None,
+            disable_nagle: None,
+            other: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> SimConnectResult<()> {
+        let fail = |what: &str| {
+            Err(SimConnectError::new(
+                &format!("[{}] {}", section_name(self.index), what),
+                None,
+            ))
+        };
+
+        match self.protocol {
+            Protocol::Ipv4 | Protocol::Ipv6 => {
+                let address = match self.address.as_deref() {
+                    Some(address) if !address.is_empty() => address,
+                    _ => return fail("Address is required"),
+                };
+                let valid = match self.protocol {
+                    Protocol::Ipv6 => address.parse::<Ipv6Addr>().is_ok(),
+                    _ => address.parse::<Ipv4Addr>().is_ok() || is_host_name(address),
+                };
+                if !valid {
+                    return fail("Address is not valid for the protocol");
+                }
+
+                match self.port.as_deref().map(str::parse::<u16>) {
+                    Some(Ok(port)) if port > 0 => {}
+                    _ => return fail("Port must be a number between 1 and 65535"),
+                }
+            }
+            Protocol::Pipe => {
+                if self.port.as_deref().is_none_or(str::is_empty) {
+                    return fail("Port must name the pipe");
+                }
+            }
+        }
+
+        if self.max_receive_size == Some(0) {
+            return fail("MaxReceiveSize must be greater than 0");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimConnectConfig {
+    pub global: Vec<(String, String)>,
+    pub entries: Vec<ConfigEntry>,
+}
+
+impl SimConnectConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| SimConnectError::new(&format!("Failed to read config: {}", e), None))?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> SimConnectResult<()> {
+        self.validate()?;
+        fs::write(path, self.to_string())
+            .map_err(|e| SimConnectError::new(&format!("Failed to write config: {}", e), None))
+    }
+
+    pub fn entry(&self, index: u32) -> Option<&ConfigEntry> {
+        self.entries.iter().find(|e| e.index == index)
+    }
+
+    pub fn set_entry(&mut self, entry: ConfigEntry) {
+        self.entries.retain(|e| e.index != entry.index);
+        self.entries.push(entry);
+        self.entries.sort_by_key(|e| e.index);
+    }
+
+    pub fn validate(&self) -> SimConnectResult<()> {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if self.entries[..i].iter().any(|e| e.index == entry.index) {
+                return Err(SimConnectError::new(
+                    &format!("Duplicate section [{}]", section_name(entry.index)),
+                    None,
+                ));
+            }
+            entry.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> SimConnectResult<Self> {
+        let mut config = Self::new();
+        let mut sections: Vec<(u32, Vec<(String, String)>)> = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let index = parse_section_name(name.trim()).ok_or_else(|| {
+                    SimConnectError::new(
+                        &format!("Unknown section [{}] on line {}", name, number + 1),
+                        None,
+                    )
+                })?;
+                sections.push((index, Vec::new()));
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                SimConnectError::new(&format!("Expected key=value on line {}", number + 1), None)
+            })?;
+            let section = sections.last_mut().ok_or_else(|| {
+                SimConnectError::new(
+                    &format!("Key outside of a section on line {}", number + 1),
+                    None,
+                )
+            })?;
+            section
+                .1
+                .push((key.trim().to_string(), value.trim().to_string()));
+        }
+
+        for (index, keys) in sections {
+            let mut protocol = None;
+            let mut entry = ConfigEntry {
+                index,
+                protocol: Protocol::Ipv4,
+                address: None,
+                port: None,
+                max_receive_size: None,
+                disable_nagle: None,
+                other: Vec::new(),
+            };
+
+            for (key, value) in keys {
+                match key.to_ascii_lowercase().as_str() {
+                    "protocol" => protocol = Some(Protocol::parse(&value)?),
+                    "address" => entry.address = Some(value),
+                    "port" => entry.port = Some(value),
+                    "maxreceivesize" => {
+                        entry.max_receive_size = Some(value.parse().map_err(|_| {
+                            SimConnectError::new("MaxReceiveSize must be a number", None)
+                        })?)
+                    }
+                    "disablenagle" => entry.disable_nagle = Some(parse_bool(&value)?),
+                    k if index == 0 && GLOBAL_KEYS.contains(&k) => config.global.push((key, value)),
+                    _ => entry.other.push((key, value)),
+                }
+            }
+
+            match protocol {
+                Some(protocol) => {
+                    entry.protocol = protocol;
+                    config.set_entry(entry);
+                }
+                // A bare [SimConnect] section may only hold logging settings.
+                None if entry.address.is_none() && entry.port.is_none() => {
+                    config.global.extend(entry.other)
+                }
+                None => {
+                    return Err(SimConnectError::new(
+                        &format!("[{}] Protocol is required", section_name(index)),
+                        None,
+                    ))
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl fmt::Display for SimConnectConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let has_entry_zero = self.entry(0).is_some();
+        if !self.global.is_empty() && !has_entry_zero {
+            writeln!(f, "[{}]", SECTION)?;
+            for (key, value) in &self.global {
+                writeln!(f, "{}={}", key, value)?;
+            }
+            writeln!(f)?;
+        }
+
+        for entry in &self.entries {
+            writeln!(f, "[{}]", section_name(entry.index))?;
+            if entry.index == 0 {
+                for (key, value) in &self.global {
+                    writeln!(f, "{}={}", key, value)?;
+                }
+            }
+            writeln!(f, "Protocol={}", entry.protocol.as_str())?;
+            if let Some(address) = &entry.address {
+                writeln!(f, "Address={}", address)?;
+            }
+            if let Some(port) = &entry.port {
+                writeln!(f, "Port={}", port)?;
+            }
+            if let Some(size) = entry.max_receive_size {
+                writeln!(f, "MaxReceiveSize={}", size)?;
+            }
+            if let Some(disable) = entry.disable_nagle {
+                writeln!(f, "DisableNagle={}", disable as u8)?;
+            }
+            for (key, value) in &entry.other {
+                writeln!(f, "{}={}", key, value)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn section_name(index: u32) -> String {
+    match index {
+        0 => SECTION.to_string(),
+        n => format!("{}.{}", SECTION, n),
+    }
+}
+
+fn parse_section_name(name: &str) -> Option<u32> {
+    if name.eq_ignore_ascii_case(SECTION) {
+        return Some(0);
+    }
+    let (prefix, index) = name.split_once('.')?;
+    match prefix.eq_ignore_ascii_case(SECTION) {
+        true => index.parse().ok(),
+        false => None,
+    }
+}
+
+fn parse_bool(value: &str) -> SimConnectResult<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "yes" | "true" => Ok(true),
+        "0" | "no" | "false" => Ok(false),
+        _ => Err(SimConnectError::new(
+            &format!("Expected a boolean, got '{}'", value),
+            None,
+        )),
+    }
+}
+
+fn is_host_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && !name.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
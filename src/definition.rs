@@ -0,0 +1,145 @@
+// Typed writes through a registered data definition. The definition knows the
+// size of every datum, so `set` and `set_array` can check `T` against it
+// instead of trusting a caller supplied size.
+
+use super::bindings::*;
+use super::catalogue;
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw;
+
+/// Implemented by types whose memory layout matches a data definition, that is
+/// `#[repr(C, packed)]` structs with one field per datum in definition order.
+///
+/// # Safety
+/// The type must not contain padding, pointers or references.
+pub unsafe trait DataStruct: Copy {}
+
+unsafe impl DataStruct for i32 {}
+unsafe impl DataStruct for i64 {}
+unsafe impl DataStruct for f32 {}
+unsafe impl DataStruct for f64 {}
+unsafe impl<const N: usize> DataStruct for [u8; N] {}
+unsafe impl DataStruct for SIMCONNECT_DATA_INITPOSITION {}
+unsafe impl DataStruct for SIMCONNECT_DATA_LATLONALT {}
+unsafe impl DataStruct for SIMCONNECT_DATA_WAYPOINT {}
+unsafe impl DataStruct for SIMCONNECT_DATA_XYZ {}
+
+struct Datum {
+    name: String,
+    size: usize,
+}
+
+pub struct DataDefinition<T> {
+    define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    datums: Vec<Datum>,
+    _data: PhantomData<T>,
+}
+
+impl<T: DataStruct> DataDefinition<T> {
+    pub fn new(define_id: SIMCONNECT_DATA_DEFINITION_ID) -> Self {
+        Self {
+            define_id,
+            datums: Vec::new(),
+            _data: PhantomData,
+        }
+    }
+
+    pub fn define_id(&self) -> SIMCONNECT_DATA_DEFINITION_ID {
+        self.define_id
+    }
+
+    pub fn add(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        datum_name: &str,
+        units_name: &str,
+        datum_type: DataType,
+        epsilon: f32,
+    ) -> SimConnectResult<()> {
+        let size = datum_type.size().ok_or_else(|| {
+            SimConnectError::new(
+                &format!("{:?} cannot be used in a typed definition", datum_type),
+                None,
+            )
+        })?;
+
+        simconnect.add_to_data_definition(
+            self.define_id,
+            datum_name,
+            units_name,
+            datum_type,
+            epsilon,
+            SIMCONNECT_UNUSED,
+        )?;
+        self.datums.push(Datum {
+            name: datum_name.to_string(),
+            size,
+        });
+        Ok(())
+    }
+
+    pub fn set(
+        &self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+        data: &T,
+    ) -> SimConnectResult<()> {
+        self.set_array(simconnect, object_id, std::slice::from_ref(data))
+    }
+
+    // Sets `data.len()` consecutive elements, as needed for lists such as
+    // `AI WAYPOINT LIST`.
+    pub fn set_array(
+        &self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+        data: &[T],
+    ) -> SimConnectResult<()> {
+        self.check()?;
+        if data.is_empty() {
+            return Err(SimConnectError::new("Nothing to set", None));
+        }
+
+        // SimConnect copies the data before returning, borrowing `data` for
+        // the duration of the call keeps it alive long enough.
+        simconnect.set_data_on_sim_object(
+            self.define_id,
+            object_id,
+            DataSetFlag::Default,
+            data.len() as DWORD,
+            mem::size_of::<T>() as DWORD,
+            data.as_ptr() as *mut raw::c_void,
+        )
+    }
+
+    fn check(&self) -> SimConnectResult<()> {
+        if let Some(datum) = self
+            .datums
+            .iter()
+            .find(|d| catalogue::is_read_only(&d.name))
+        {
+            return Err(SimConnectError::new(
+                &format!("{} is read-only", datum.name),
+                None,
+            ));
+        }
+
+        let defined: usize = self.datums.iter().map(|d| d.size).sum();
+        if defined != mem::size_of::<T>() {
+            return Err(SimConnectError::new(
+                &format!(
+                    "Definition {} holds {} bytes but the type has {}",
+                    self.define_id,
+                    defined,
+                    mem::size_of::<T>()
+                ),
+                None,
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+// Writes simvar telemetry as tables, one row per sample and one column per
+// simvar, with a leading `time` column in seconds. Rows come from `StateStore`
+// snapshots, which works the same on a live connection and on a `Replay`.
+// Units are part of the CSV header and of the Parquet field metadata.
+
+use super::state::{Snapshot, StateVar};
+use super::types::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    // A row whenever a value changes.
+    Changes,
+    // A row every interval holding the latest values at that time.
+    FixedRate(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    format: Format,
+    sampling: Sampling,
+    max_rows: Option<u64>,
+    max_duration: Option<Duration>,
+}
+
+impl ExportOptions {
+    pub fn new() -> Self {
+        Self {
+            format: Format::Csv,
+            sampling: Sampling::Changes,
+            max_rows: None,
+            max_duration: None,
+        }
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = match sampling {
+            Sampling::FixedRate(interval) if interval.is_zero() => Sampling::Changes,
+            sampling => sampling,
+        };
+        self
+    }
+
+    // Starts a new file once the current one holds `rows` rows.
+    pub fn rotate_rows(mut self, rows: u64) -> Self {
+        self.max_rows = Some(rows.max(1));
+        self
+    }
+
+    // Starts a new file once the current one spans `duration`.
+    pub fn rotate_after(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    fn rotates(&self) -> bool {
+        self.max_rows.is_some() || self.max_duration.is_some()
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions::new()
+    }
+}
+
+trait Sink {
+    fn write_row(&mut self, time: Duration, values: &[Option<SimVarValue>])
+        -> SimConnectResult<()>;
+    fn finish(self: Box<Self>) -> SimConnectResult<()>;
+}
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> SimConnectError {
+    SimConnectError::new(&format!("Failed to write {}: {}", path.display(), e), None)
+}
+
+struct CsvSink {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl CsvSink {
+    fn create(path: &Path, columns: &[StateVar]) -> SimConnectResult<Self> {
+        let file = File::create(path).map_err(|e| io_error(path, e))?;
+        let mut sink = Self {
+            path: path.to_path_buf(),
+            out: BufWriter::new(file),
+        };
+
+        let mut header = vec!["time (seconds)".to_string()];
+        header.extend(
+            columns
+                .iter()
+                .map(|c| csv_field(&format!("{} ({})", c.name, c.units))),
+        );
+        writeln!(sink.out, "{}", header.join(",")).map_err(|e| io_error(path, e))?;
+        Ok(sink)
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+impl Sink for CsvSink {
+    fn write_row(
+        &mut self,
+        time: Duration,
+        values: &[Option<SimVarValue>],
+    ) -> SimConnectResult<()> {
+        let mut row = vec![format!("{:.6}", time.as_secs_f64())];
+        row.extend(values.iter().map(|v| match v {
+            Some(SimVarValue::Number(n)) => n.to_string(),
+            Some(SimVarValue::String(s)) => csv_field(s),
+            None => String::new(),
+        }));
+        writeln!(self.out, "{}", row.join(",")).map_err(|e| io_error(&self.path, e))
+    }
+
+    fn finish(mut self: Box<Self>) -> SimConnectResult<()> {
+        self.out.flush().map_err(|e| io_error(&self.path, e))
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use super::*;
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+    use arrow_schema::{DataType as ArrowType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    // Rows are buffered and written as one row group per batch.
+    const BATCH_ROWS: usize = 8192;
+
+    pub(super) struct ParquetSink {
+        path: PathBuf,
+        schema: Arc<Schema>,
+        writer: ArrowWriter<File>,
+        strings: Vec<bool>,
+        rows: Vec<(Duration, Vec<Option<SimVarValue>>)>,
+    }
+
+    impl ParquetSink {
+        pub(super) fn create(path: &Path, columns: &[StateVar]) -> SimConnectResult<Self> {
+            let field = |name: &str, units: &str, data_type| {
+                Field::new(name, data_type, true)
+                    .with_metadata(HashMap::from([("units".to_string(), units.to_string())]))
+            };
+
+            let mut fields =
+                vec![field("time", "seconds", ArrowType::Float64).with_nullable(false)];
+            fields.extend(columns.iter().map(|c| {
+                let data_type = match c.is_string() {
+                    true => ArrowType::Utf8,
+                    false => ArrowType::Float64,
+                };
+                field(&c.name, &c.units, data_type)
+            }));
+            let schema = Arc::new(Schema::new(fields));
+
+            let file = File::create(path).map_err(|e| io_error(path, e))?;
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))
+                .map_err(|e| io_error(path, e))?;
+
+            Ok(Self {
+                path: path.to_path_buf(),
+                schema,
+                writer,
+                strings: columns.iter().map(|c| c.is_string()).collect(),
+                rows: Vec::new(),
+            })
+        }
+
+        fn flush(&mut self) -> SimConnectResult<()> {
+            if self.rows.is_empty() {
+                return Ok(());
+            }
+
+            let mut arrays: Vec<ArrayRef> = vec![Arc::new(Float64Array::from_iter_values(
+                self.rows.iter().map(|(time, _)| time.as_secs_f64()),
+            ))];
+            for (index, string) in self.strings.iter().enumerate() {
+                let values = self.rows.iter().map(|(_, values)| &values[index]);
+                let array: ArrayRef = match string {
+                    true => Arc::new(StringArray::from_iter(values.map(|v| match v {
+                        Some(SimVarValue::String(s)) => Some(s.clone()),
+                        _ => None,
+                    }))),
+                    false => Arc::new(Float64Array::from_iter(values.map(|v| match v {
+                        Some(SimVarValue::Number(n)) => Some(*n),
+                        _ => None,
+                    }))),
+                };
+                arrays.push(array);
+            }
+
+            let batch = RecordBatch::try_new(self.schema.clone(), arrays)
+                .map_err(|e| io_error(&self.path, e))?;
+            self.writer
+                .write(&batch)
+                .map_err(|e| io_error(&self.path, e))?;
+            self.rows.clear();
+            Ok(())
+        }
+    }
+
+    impl Sink for ParquetSink {
+        fn write_row(
+            &mut self,
+            time: Duration,
+            values: &[Option<SimVarValue>],
+        ) -> SimConnectResult<()> {
+            self.rows.push((time, values.to_vec()));
+            match self.rows.len() >= BATCH_ROWS {
+                true => self.flush(),
+                false => Ok(()),
+            }
+        }
+
+        fn finish(mut self: Box<Self>) -> SimConnectResult<()> {
+            self.flush()?;
+            self.writer
+                .close()
+                .map(|_| ())
+                .map_err(|e| io_error(&self.path, e))
+        }
+    }
+}
+
+pub struct Exporter {
+    path: PathBuf,
+    columns: Vec<StateVar>,
+    options: ExportOptions,
+    sink: Option<Box<dyn Sink>>,
+    files: Vec<PathBuf>,
+    file_rows: u64,
+    file_started: Duration,
+    current: Vec<Option<SimVarValue>>,
+    written: Option<Vec<Option<SimVarValue>>>,
+    next_tick: Option<Duration>,
+}
+
+impl Exporter {
+    // With rotation enabled files are numbered, `flight.csv` becomes
+    // `flight-001.csv`, `flight-002.csv` and so on.
+    pub fn new(
+        path: impl AsRef<Path>,
+        columns: &[StateVar],
+        options: ExportOptions,
+    ) -> SimConnectResult<Self> {
+        if columns.is_empty() {
+            return Err(SimConnectError::new("No simvars to export", None));
+        }
+
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            columns: columns.to_vec(),
+            options,
+            sink: None,
+            files: Vec::new(),
+            file_rows: 0,
+            file_started: Duration::ZERO,
+            current: vec![None; columns.len()],
+            written: None,
+            next_tick: None,
+        })
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    // `time` counts from the start of the export, or of the session when
+    // exporting a recording. Simvars missing from the snapshot are left
+    // empty.
+    pub fn record(&mut self, time: Duration, snapshot: &Snapshot) -> SimConnectResult<()> {
+        let values: Vec<_> = self
+            .columns
+            .iter()
+            .map(|c| snapshot.get(&c.name).cloned())
+            .collect();
+        self.record_values(time, values)
+    }
+
+    // One value per column, in the order they were given to `new`.
+    pub fn record_values(
+        &mut self,
+        time: Duration,
+        values: Vec<Option<SimVarValue>>,
+    ) -> SimConnectResult<()> {
+        if values.len() != self.columns.len() {
+            return Err(SimConnectError::new(
+                &format!(
+                    "Expected {} values, got {}",
+                    self.columns.len(),
+                    values.len()
+                ),
+                None,
+            ));
+        }
+
+        match self.options.sampling {
+            Sampling::Changes => {
+                self.current = values;
+                if self.written.as_ref() != Some(&self.current) {
+                    self.write_current(time)?;
+                }
+            }
+            Sampling::FixedRate(interval) => {
+                let mut tick = self.next_tick.unwrap_or_else(|| {
+                    let ticks = time.as_nanos().div_ceil(interval.as_nanos());
+                    Duration::from_nanos((ticks * interval.as_nanos()) as u64)
+                });
+                // Ticks before `time` hold the values from before this update.
+                while tick < time && self.next_tick.is_some() {
+                    self.write_current(tick)?;
+                    tick += interval;
+                }
+                tick = tick.max(time);
+                self.current = values;
+                if tick == time {
+                    self.write_current(tick)?;
+                    tick += interval;
+                }
+                self.next_tick = Some(tick);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_current(&mut self, time: Duration) -> SimConnectResult<()> {
+        let rotate = self.sink.is_some()
+            && (self
+                .options
+                .max_rows
+                .is_some_and(|max| self.file_rows >= max)
+                || self
+                    .options
+                    .max_duration
+                    .is_some_and(|max| time.saturating_sub(self.file_started) >= max));
+        if rotate {
+            if let Some(sink) = self.sink.take() {
+                sink.finish()?;
+            }
+        }
+
+        if self.sink.is_none() {
+            let path = self.file_path(self.files.len() + 1);
+            self.sink = Some(match self.options.format {
+                Format::Csv => Box::new(CsvSink::create(&path, &self.columns)?),
+                #[cfg(feature = "parquet")]
+                Format::Parquet => {
+                    Box::new(parquet_sink::ParquetSink::create(&path, &self.columns)?)
+                }
+            });
+            self.files.push(path);
+            self.file_rows = 0;
+            self.file_started = time;
+        }
+
+        if let Some(sink) = self.sink.as_mut() {
+            sink.write_row(time, &self.current)?;
+        }
+        self.file_rows += 1;
+        self.written = Some(self.current.clone());
+        Ok(())
+    }
+
+    fn file_path(&self, number: usize) -> PathBuf {
+        if !self.options.rotates() {
+            return self.path.clone();
+        }
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = match self.path.extension() {
+            Some(extension) => format!("{}-{:03}.{}", stem, number, extension.to_string_lossy()),
+            None => format!("{}-{:03}", stem, number),
+        };
+        self.path.with_file_name(name)
+    }
+
+    // Returns the files written, in order.
+    pub fn finish(mut self) -> SimConnectResult<Vec<PathBuf>> {
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+        Ok(self.files)
+    }
+}
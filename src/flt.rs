@@ -0,0 +1,618 @@
+// Flight files as written by `flight_save` and read by `flight_load`, INI
+// style documents describing the whole situation. The document keeps every
+// line, so sections, keys and comments the typed views do not cover are
+// written back as they were. Names of sections and keys are case-insensitive.
+
+use super::pln::{format_angle, parse_angle};
+#[cfg(feature = "sdk")]
+use super::simconnect::SimConnect;
+use super::types::*;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "sdk")]
+use std::path::PathBuf;
+#[cfg(feature = "sdk")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Blank,
+    // The whole line, indentation and the leading `;` or `//` included.
+    Comment(String),
+    // `raw` is the line as read, written back while it still holds the same
+    // key and value.
+    Entry {
+        key: String,
+        value: String,
+        raw: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub lines: Vec<Line>,
+    // The header line as read, which may carry a comment.
+    header: Option<String>,
+}
+
+impl Section {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            lines: Vec::new(),
+            header: None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Entry { key: k, value, .. } if k.eq_ignore_ascii_case(key) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    // Replaces the value in place, or adds the key after the last entry.
+    pub fn set(&mut self, key: &str, value: &str) {
+        for line in self.lines.iter_mut() {
+            if let Line::Entry {
+                key: k, value: v, ..
+            } = line
+            {
+                if k.eq_ignore_ascii_case(key) {
+                    *v = value.to_string();
+                    return;
+                }
+            }
+        }
+
+        let index = self
+            .lines
+            .iter()
+            .rposition(|l| matches!(l, Line::Entry { .. }))
+            .map_or(0, |i| i + 1);
+        self.lines.insert(
+            index,
+            Line::Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+                raw: None,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self
+            .lines
+            .iter()
+            .position(|l| matches!(l, Line::Entry { key: k, .. } if k.eq_ignore_ascii_case(key)))?;
+        match self.lines.remove(index) {
+            Line::Entry { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((key.as_str(), value.as_str())),
+            _ => None,
+        })
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, key: &str) -> SimConnectResult<Option<T>> {
+        match self.get(key) {
+            Some(value) => value.trim().parse().map(Some).map_err(|_| {
+                invalid(&format!(
+                    "bad value {:?} for {} in [{}]",
+                    value, key, self.name
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn flag(&self, key: &str) -> SimConnectResult<Option<bool>> {
+        match self.get(key).map(str::trim) {
+            Some(value) if value.eq_ignore_ascii_case("true") || value == "1" => Ok(Some(true)),
+            Some(value) if value.eq_ignore_ascii_case("false") || value == "0" => Ok(Some(false)),
+            Some(value) => Err(invalid(&format!(
+                "bad flag {:?} for {} in [{}]",
+                value, key, self.name
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn set_option(&mut self, key: &str, value: Option<impl fmt::Display>) {
+        if let Some(value) = value {
+            self.set(key, &value.to_string());
+        }
+    }
+
+    fn set_flag(&mut self, key: &str, value: Option<bool>) {
+        self.set_option(key, value.map(|v| if v { "True" } else { "False" }));
+    }
+}
+
+fn invalid(what: &str) -> SimConnectError {
+    SimConnectError::new(&format!("Invalid flight file: {}", what), None)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FlightFile {
+    // Lines before the first section.
+    pub preamble: Vec<Line>,
+    pub sections: Vec<Section>,
+    crlf: bool,
+    bom: bool,
+}
+
+impl FlightFile {
+    pub fn new() -> Self {
+        Self {
+            crlf: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+        })?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> SimConnectResult<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).map_err(|e| {
+            SimConnectError::new(&format!("Failed to write {}: {}", path.display(), e), None)
+        })
+    }
+
+    // Saves to a temporary file and asks the sim to load it, returning the
+    // path so the caller can remove it once the load has completed.
+    #[cfg(feature = "sdk")]
+    pub fn load_into(&self, simconnect: &SimConnect) -> SimConnectResult<PathBuf> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "simply-simconnect-{}-{}.flt",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.save(&path)?;
+        simconnect.flight_load(&path.to_string_lossy())?;
+        Ok(path)
+    }
+
+    pub fn parse(text: &str) -> SimConnectResult<Self> {
+        let bom = text.starts_with('\u{feff}');
+        let text = text.trim_start_matches('\u{feff}');
+        let mut file = Self {
+            crlf: text.contains("\r\n"),
+            bom,
+            ..Self::default()
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            let parsed = if trimmed.is_empty() {
+                Line::Blank
+            } else if is_comment(trimmed) {
+                Line::Comment(line.to_string())
+            } else if let Some(name) = parse_header(trimmed) {
+                let mut section = Section::new(name);
+                section.header = Some(line.to_string());
+                file.sections.push(section);
+                continue;
+            } else {
+                let (key, value) = parse_entry(trimmed).ok_or_else(|| {
+                    invalid(&format!("expected key=value on line {}", number + 1))
+                })?;
+                Line::Entry {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    raw: Some(line.to_string()),
+                }
+            };
+
+            match file.sections.last_mut() {
+                Some(section) => section.lines.push(parsed),
+                None if matches!(parsed, Line::Entry { .. }) => {
+                    return Err(invalid(&format!(
+                        "key outside of a section on line {}",
+                        number + 1
+                    )))
+                }
+                None => file.preamble.push(parsed),
+            }
+        }
+        Ok(file)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn section_mut(&mut self, name: &str) -> Option<&mut Section> {
+        self.sections
+            .iter_mut()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    // Appends an empty section if there is none called `name`.
+    pub fn section_or_insert(&mut self, name: &str) -> &mut Section {
+        let index = match self
+            .sections
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(name))
+        {
+            Some(index) => index,
+            None => {
+                if let Some(last) = self.sections.last_mut() {
+                    if !matches!(last.lines.last(), Some(Line::Blank)) {
+                        last.lines.push(Line::Blank);
+                    }
+                }
+                self.sections.push(Section::new(name));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index]
+    }
+
+    pub fn remove_section(&mut self, name: &str) -> Option<Section> {
+        let index = self
+            .sections
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(name))?;
+        Some(self.sections.remove(index))
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)?.get(key)
+    }
+
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        self.section_or_insert(section).set(key, value);
+    }
+
+    fn typed<T>(
+        &self,
+        name: &str,
+        read: impl FnOnce(&Section) -> SimConnectResult<T>,
+    ) -> SimConnectResult<Option<T>> {
+        self.section(name).map(read).transpose()
+    }
+
+    pub fn sim_vars(&self, index: u32) -> SimConnectResult<Option<SimVars>> {
+        self.typed(&format!("SimVars.{}", index), SimVars::read)
+    }
+
+    pub fn set_sim_vars(&mut self, index: u32, vars: &SimVars) {
+        vars.write(self.section_or_insert(&format!("SimVars.{}", index)));
+    }
+
+    pub fn freeze(&self) -> SimConnectResult<Option<Freeze>> {
+        self.typed("Freeze", Freeze::read)
+    }
+
+    pub fn set_freeze(&mut self, freeze: &Freeze) {
+        freeze.write(self.section_or_insert("Freeze"));
+    }
+
+    pub fn date_time(&self) -> SimConnectResult<Option<DateTimeSeason>> {
+        self.typed("DateTimeSeason", DateTimeSeason::read)
+    }
+
+    pub fn set_date_time(&mut self, date_time: &DateTimeSeason) {
+        date_time.write(self.section_or_insert("DateTimeSeason"));
+    }
+
+    pub fn weather(&self) -> SimConnectResult<Option<Weather>> {
+        self.typed("Weather", Weather::read)
+    }
+
+    pub fn set_weather(&mut self, weather: &Weather) {
+        weather.write(self.section_or_insert("Weather"));
+    }
+
+    pub fn atc_aircraft(&self, index: u32) -> SimConnectResult<Option<AtcAircraft>> {
+        self.typed(&format!("ATC_Aircraft.{}", index), AtcAircraft::read)
+    }
+
+    pub fn set_atc_aircraft(&mut self, index: u32, aircraft: &AtcAircraft) {
+        aircraft.write(self.section_or_insert(&format!("ATC_Aircraft.{}", index)));
+    }
+}
+
+fn is_comment(line: &str) -> bool {
+    line.starts_with(';') || line.starts_with("//")
+}
+
+// `[name]`, optionally followed by a comment.
+fn parse_header(line: &str) -> Option<&str> {
+    let (name, rest) = line.strip_prefix('[')?.split_once(']')?;
+    let rest = rest.trim_start();
+    match rest.is_empty() || is_comment(rest) {
+        true => Some(name.trim()),
+        false => None,
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.trim().split_once('=')?;
+    Some((key.trim(), value.trim()))
+}
+
+fn write_lines(f: &mut fmt::Formatter, lines: &[Line], newline: &str) -> fmt::Result {
+    for line in lines {
+        match line {
+            Line::Blank => write!(f, "{}", newline)?,
+            Line::Comment(comment) => write!(f, "{}{}", comment, newline)?,
+            Line::Entry {
+                key,
+                value,
+                raw: Some(raw),
+            } if parse_entry(raw) == Some((key, value)) => write!(f, "{}{}", raw, newline)?,
+            Line::Entry { key, value, .. } => write!(f, "{}={}{}", key, value, newline)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for FlightFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let newline = match self.crlf {
+            true => "\r\n",
+            false => "\n",
+        };
+        if self.bom {
+            write!(f, "\u{feff}")?;
+        }
+        write_lines(f, &self.preamble, newline)?;
+        for section in &self.sections {
+            match &section.header {
+                Some(header) if parse_header(header.trim()) == Some(&section.name) => {
+                    write!(f, "{}{}", header, newline)?
+                }
+                _ => write!(f, "[{}]{}", section.name, newline)?,
+            }
+            write_lines(f, &section.lines, newline)?;
+        }
+        Ok(())
+    }
+}
+
+// The position and attitude of a sim object, `[SimVars.0]` being the user
+// aircraft. Angles are in degrees.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimVars {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+    pub pitch: Option<f64>,
+    pub bank: Option<f64>,
+    pub heading: Option<f64>,
+    pub on_ground: Option<bool>,
+}
+
+impl SimVars {
+    fn read(section: &Section) -> SimConnectResult<Self> {
+        let angle = |key: &str, hemispheres| {
+            let value = section
+                .get(key)
+                .ok_or_else(|| invalid(&format!("missing {} in [{}]", key, section.name)))?;
+            parse_angle(value, hemispheres)
+                .ok_or_else(|| invalid(&format!("bad {} {:?}", key, value)))
+        };
+
+        Ok(Self {
+            latitude: angle("Latitude", ['N', 'S'])?,
+            longitude: angle("Longitude", ['E', 'W'])?,
+            altitude_ft: section.parsed("Altitude")?.unwrap_or(0.0),
+            pitch: section.parsed("Pitch")?,
+            bank: section.parsed("Bank")?,
+            heading: section.parsed("Heading")?,
+            on_ground: section.flag("SimOnGround")?,
+        })
+    }
+
+    fn write(&self, section: &mut Section) {
+        let sign = match self.altitude_ft < 0.0 {
+            true => '-',
+            false => '+',
+        };
+        section.set("Latitude", &format_angle(self.latitude, ['N', 'S']));
+        section.set("Longitude", &format_angle(self.longitude, ['E', 'W']));
+        section.set(
+            "Altitude",
+            &format!("{}{:09.2}", sign, self.altitude_ft.abs()),
+        );
+        section.set_option("Pitch", self.pitch);
+        section.set_option("Bank", self.bank);
+        section.set_option("Heading", self.heading);
+        section.set_flag("SimOnGround", self.on_ground);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Freeze {
+    pub location: bool,
+    pub altitude: bool,
+    pub attitude: bool,
+}
+
+impl Freeze {
+    fn read(section: &Section) -> SimConnectResult<Self> {
+        Ok(Self {
+            location: section.flag("Location")?.unwrap_or(false),
+            altitude: section.flag("Altitude")?.unwrap_or(false),
+            attitude: section.flag("Attitude")?.unwrap_or(false),
+        })
+    }
+
+    fn write(&self, section: &mut Section) {
+        section.set_flag("Location", Some(self.location));
+        section.set_flag("Altitude", Some(self.altitude));
+        section.set_flag("Attitude", Some(self.attitude));
+    }
+}
+
+// Local sim time, `day` is the day of the year starting at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateTimeSeason {
+    pub season: Option<String>,
+    pub year: i32,
+    pub day: u32,
+    pub hours: u32,
+    pub minutes: u32,
+    pub seconds: u32,
+}
+
+impl DateTimeSeason {
+    fn read(section: &Section) -> SimConnectResult<Self> {
+        let required = |key: &str| {
+            section
+                .parsed::<u32>(key)?
+                .ok_or_else(|| invalid(&format!("missing {} in [{}]", key, section.name)))
+        };
+
+        Ok(Self {
+            season: section.get("Season").map(str::to_string),
+            year: section
+                .parsed("Year")?
+                .ok_or_else(|| invalid("missing Year in [DateTimeSeason]"))?,
+            day: required("Day")?,
+            hours: required("Hours")?,
+            minutes: required("Minutes")?,
+            seconds: section.parsed("Seconds")?.unwrap_or(0),
+        })
+    }
+
+    fn write(&self, section: &mut Section) {
+        section.set_option("Season", self.season.as_ref());
+        section.set("Year", &self.year.to_string());
+        section.set("Day", &self.day.to_string());
+        section.set("Hours", &self.hours.to_string());
+        section.set("Minutes", &self.minutes.to_string());
+        section.set("Seconds", &self.seconds.to_string());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Weather {
+    pub use_live_weather: Option<bool>,
+    pub weather_can_be_live: Option<bool>,
+    pub use_weather_file: Option<bool>,
+    pub preset_file: Option<String>,
+}
+
+impl Weather {
+    fn read(section: &Section) -> SimConnectResult<Self> {
+        Ok(Self {
+            use_live_weather: section.flag("UseLiveWeather")?,
+            weather_can_be_live: section.flag("WeatherCanBeLive")?,
+            use_weather_file: section.flag("UseWeatherFile")?,
+            preset_file: section.get("WeatherPresetFile").map(str::to_string),
+        })
+    }
+
+    fn write(&self, section: &mut Section) {
+        section.set_flag("UseLiveWeather", self.use_live_weather);
+        section.set_flag("WeatherCanBeLive", self.weather_can_be_live);
+        section.set_flag("UseWeatherFile", self.use_weather_file);
+        section.set_option("WeatherPresetFile", self.preset_file.as_ref());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AtcAircraft {
+    pub active_flight_plan: Option<bool>,
+    pub requested_flight_plan: Option<bool>,
+    // The plan file, as passed to `flight_plan_load`.
+    pub flight_plan: Option<String>,
+}
+
+impl AtcAircraft {
+    fn read(section: &Section) -> SimConnectResult<Self> {
+        Ok(Self {
+            active_flight_plan: section.flag("ActiveFlightPlan")?,
+            requested_flight_plan: section.flag("RequestedFlightPlan")?,
+            flight_plan: section.get("FlightPlan").map(str::to_string),
+        })
+    }
+
+    fn write(&self, section: &mut Section) {
+        section.set_flag("ActiveFlightPlan", self.active_flight_plan);
+        section.set_flag("RequestedFlightPlan", self.requested_flight_plan);
+        section.set_option("FlightPlan", self.flight_plan.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLIGHT: &str = "\u{feff}; saved by the sim\r\n\
+        \r\n\
+        [Main] ; the header\r\n\
+        Title = Test flight\r\n\
+        \x20 // indented note\r\n\
+        \r\n\
+        [SimVars.0]\r\n\
+        Latitude=N47° 27.48'\r\n\
+        Longitude=W122° 18.50'\r\n\
+        Altitude=+000433.00\r\n\
+        Heading = 90.0\r\n\
+        \r\n\
+        [Freeze]\r\n\
+        Location=False\r\n\
+        Altitude=False\r\n\
+        Attitude=False\r\n\
+        \r\n\
+        [Unknown.Section]\r\n\
+        Anything=goes here\r\n";
+
+    #[test]
+    fn writes_back_what_it_read() {
+        let file = FlightFile::parse(FLIGHT).unwrap();
+        assert_eq!(file.get("main", "title"), Some("Test flight"));
+        assert_eq!(file.get("Unknown.Section", "Anything"), Some("goes here"));
+        assert_eq!(file.to_string(), FLIGHT);
+    }
+
+    #[test]
+    fn only_rewrites_edited_lines() {
+        let mut file = FlightFile::parse(FLIGHT).unwrap();
+        file.set_freeze(&Freeze {
+            location: true,
+            ..Freeze::default()
+        });
+        file.set("Main", "Title", "Edited");
+
+        let text = file.to_string();
+        assert!(text.contains("[Main] ; the header\r\nTitle=Edited\r\n  // indented note\r\n"));
+        assert!(text.contains("Location=True\r\nAltitude=False\r\n"));
+        assert!(text.contains("Heading = 90.0\r\n"));
+        let freeze = FlightFile::parse(&text).unwrap().freeze().unwrap();
+        assert_eq!(
+            freeze,
+            Some(Freeze {
+                location: true,
+                ..Freeze::default()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_text_after_a_header() {
+        assert!(FlightFile::parse("[Main] Title=x\n").is_err());
+    }
+}
@@ -0,0 +1,596 @@
+// IGC flight logs, following the FAI IGC technical specification (appendix
+// A). Fixes come from `StateStore` snapshots of `igc_vars`. The sim is not an
+// approved recorder, so the file carries an `X` manufacturer code and no
+// G record. Every line is checked by `Validator` before it is written.
+
+use super::pln::FlightPlan;
+use super::state::{Snapshot, StateVar};
+use super::track::UtcTime;
+use super::types::*;
+use std::io::Write;
+use std::time::Duration;
+
+const FEET_TO_METERS: f64 = 0.3048;
+const MAX_LINE: usize = 76;
+const RESERVED: &[char] = &['$', '*', '!', '\\', '^', '~'];
+
+pub fn igc_vars() -> Vec<StateVar> {
+    vec![
+        StateVar::new("PLANE LATITUDE", "degrees"),
+        StateVar::new("PLANE LONGITUDE", "degrees"),
+        StateVar::new("PRESSURE ALTITUDE", "meters"),
+        StateVar::new("PLANE ALTITUDE", "feet"),
+        StateVar::new("TITLE", "string"),
+        StateVar::new("ATC ID", "string"),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskPoint {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// A declared task, from the departure to the destination. The first point
+// is written as takeoff and start, the last as finish and landing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgcTask {
+    pub name: String,
+    pub points: Vec<TaskPoint>,
+}
+
+impl IgcTask {
+    pub fn from_plan(plan: &FlightPlan) -> Self {
+        Self {
+            name: plan.title.clone(),
+            points: plan
+                .waypoints
+                .iter()
+                .map(|w| TaskPoint {
+                    name: w.id.clone(),
+                    latitude: w.position.latitude,
+                    longitude: w.position.longitude,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IgcOptions {
+    interval: Duration,
+    pilot: String,
+    serial: String,
+    task: Option<IgcTask>,
+}
+
+impl IgcOptions {
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            pilot: String::new(),
+            serial: "SIM".to_string(),
+            task: None,
+        }
+    }
+
+    // Minimum time between two B records.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn pilot(mut self, pilot: &str) -> Self {
+        self.pilot = pilot.to_string();
+        self
+    }
+
+    // Three letters or digits identifying the recorder in the A record.
+    pub fn serial(mut self, serial: &str) -> Self {
+        self.serial = serial.to_string();
+        self
+    }
+
+    pub fn task(mut self, task: IgcTask) -> Self {
+        self.task = Some(task);
+        self
+    }
+}
+
+impl Default for IgcOptions {
+    fn default() -> Self {
+        IgcOptions::new()
+    }
+}
+
+pub struct IgcWriter<W: Write> {
+    out: W,
+    options: IgcOptions,
+    started_at_ms: u64,
+    validator: Validator,
+    last_fix: Option<Duration>,
+}
+
+impl<W: Write> IgcWriter<W> {
+    // `started_at_ms` is the wall clock time of the start of the flight, in
+    // milliseconds since the epoch. Fix times are taken relative to it.
+    pub fn new(out: W, started_at_ms: u64, options: IgcOptions) -> SimConnectResult<Self> {
+        let serial = options.serial.to_ascii_uppercase();
+        if serial.len() != 3 || !serial.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(SimConnectError::new(
+                &format!("Invalid IGC recorder serial {:?}", options.serial),
+                None,
+            ));
+        }
+        let task_points = options.task.as_ref().map_or(2, |t| t.points.len());
+        if !(2..=101).contains(&task_points) {
+            return Err(SimConnectError::new(
+                "An IGC task needs 2 to 101 points",
+                None,
+            ));
+        }
+
+        Ok(Self {
+            out,
+            options: IgcOptions { serial, ..options },
+            started_at_ms,
+            validator: Validator::new(),
+            last_fix: None,
+        })
+    }
+
+    fn line(&mut self, line: &str) -> SimConnectResult<()> {
+        self.validator.line(line)?;
+        write!(self.out, "{}\r\n", line)
+            .map_err(|e| SimConnectError::new(&format!("Failed to write IGC: {}", e), None))
+    }
+
+    fn utc(&self, time: Duration) -> UtcTime {
+        UtcTime::from_unix_ms(self.started_at_ms + time.as_millis() as u64)
+    }
+
+    // Writes the A, H and C records, `title` and `atc_id` are the aircraft's
+    // `TITLE` and `ATC ID`.
+    fn header(&mut self, time: Duration, title: &str, atc_id: &str) -> SimConnectResult<()> {
+        let date = self.utc(time);
+        let ddmmyy = format!(
+            "{:02}{:02}{:02}",
+            date.day,
+            date.month,
+            date.year.rem_euclid(100)
+        );
+
+        self.line(&format!("AXSS{}SIMPLY SIMCONNECT", self.options.serial))?;
+        self.line(&format!("HFDTEDATE:{},01", ddmmyy))?;
+        let pilot = sanitize(&self.options.pilot);
+        self.line(&format!("HFPLTPILOTINCHARGE:{}", pilot))?;
+        self.line(&format!("HFGTYGLIDERTYPE:{}", sanitize(title)))?;
+        self.line(&format!("HFGIDGLIDERID:{}", sanitize(atc_id)))?;
+        self.line("HFDTMGPSDATUM:WGS84")?;
+        self.line("HFFTYFRTYPE:SIMULATOR,SIMCONNECT")?;
+        self.line("HFGPSRECEIVER:SIMULATED")?;
+        self.line("HFPRSPRESSALTSENSOR:SIMULATED")?;
+        self.line("HFALGALTGPS:GEO")?;
+        self.line("HFALPALTPRESSURE:ISA")?;
+
+        if let Some(task) = self.options.task.clone() {
+            let (first, last) = (&task.points[0], &task.points[task.points.len() - 1]);
+            let turnpoints = &task.points[1..task.points.len() - 1];
+            self.line(&format!(
+                "C{}{:02}{:02}{:02}{}0001{:02}{}",
+                ddmmyy,
+                date.hour,
+                date.minute,
+                date.second,
+                ddmmyy,
+                turnpoints.len(),
+                sanitize(&task.name)
+            ))?;
+            self.line(&task_line(first))?;
+            self.line(&task_line(first))?;
+            for point in turnpoints {
+                self.line(&task_line(point))?;
+            }
+            self.line(&task_line(last))?;
+            self.line(&task_line(last))?;
+        }
+        Ok(())
+    }
+
+    // Skipped until the snapshot holds a position, and while the previous
+    // fix is less than the interval ago.
+    pub fn push_snapshot(&mut self, time: Duration, snapshot: &Snapshot) -> SimConnectResult<()> {
+        let number = |name| match snapshot.get(name) {
+            Some(SimVarValue::Number(n)) => Some(*n),
+            _ => None,
+        };
+        let string = |name| match snapshot.get(name) {
+            Some(SimVarValue::String(s)) => s.clone(),
+            _ => String::new(),
+        };
+
+        let (latitude, longitude) = match (number("PLANE LATITUDE"), number("PLANE LONGITUDE")) {
+            (Some(latitude), Some(longitude)) => (latitude, longitude),
+            _ => return Ok(()),
+        };
+        let gnss_altitude = number("PLANE ALTITUDE").map(|ft| ft * FEET_TO_METERS);
+        let pressure_altitude = number("PRESSURE ALTITUDE");
+
+        match self.last_fix {
+            None => self.header(time, &string("TITLE"), &string("ATC ID"))?,
+            Some(last) if time.saturating_sub(last) < self.options.interval => return Ok(()),
+            Some(_) => {}
+        }
+        self.fix(time, latitude, longitude, pressure_altitude, gnss_altitude)
+    }
+
+    fn fix(
+        &mut self,
+        time: Duration,
+        latitude: f64,
+        longitude: f64,
+        pressure_altitude: Option<f64>,
+        gnss_altitude: Option<f64>,
+    ) -> SimConnectResult<()> {
+        let utc = self.utc(time);
+        // `V` marks a fix without a GNSS altitude.
+        let validity = match gnss_altitude {
+            Some(_) => 'A',
+            None => 'V',
+        };
+        let line = format!(
+            "B{:02}{:02}{:02}{}{}{}{}{}",
+            utc.hour,
+            utc.minute,
+            utc.second,
+            coordinate(latitude, 2, ['N', 'S']),
+            coordinate(longitude, 3, ['E', 'W']),
+            validity,
+            altitude(pressure_altitude.unwrap_or(0.0)),
+            altitude(gnss_altitude.unwrap_or(0.0))
+        );
+        self.line(&line)?;
+        self.last_fix = Some(time);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> SimConnectResult<W> {
+        self.out
+            .flush()
+            .map_err(|e| SimConnectError::new(&format!("Failed to write IGC: {}", e), None))?;
+        Ok(self.out)
+    }
+}
+
+fn task_line(point: &TaskPoint) -> String {
+    format!(
+        "C{}{}{}",
+        coordinate(point.latitude, 2, ['N', 'S']),
+        coordinate(point.longitude, 3, ['E', 'W']),
+        sanitize(&point.name)
+    )
+}
+
+// Degrees and thousandths of minutes, `DDMMmmmN` or `DDDMMmmmE`.
+fn coordinate(value: f64, degree_digits: usize, hemispheres: [char; 2]) -> String {
+    let hemisphere = match value < 0.0 {
+        true => hemispheres[1],
+        false => hemispheres[0],
+    };
+    let millis = (value.abs() * 60_000.0).round() as u64;
+    format!(
+        "{:0width$}{:05}{}",
+        millis / 60_000,
+        millis % 60_000,
+        hemisphere,
+        width = degree_digits
+    )
+}
+
+fn altitude(meters: f64) -> String {
+    let meters = meters.round().clamp(-9999.0, 99999.0) as i64;
+    match meters < 0 {
+        true => format!("-{:04}", -meters),
+        false => format!("{:05}", meters),
+    }
+}
+
+// Drops characters the specification reserves and keeps the line short
+// enough for the longest H record prefix.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| (' '..='~').contains(c) && !RESERVED.contains(c))
+        .take(MAX_LINE - 25)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    Start,
+    Header,
+    Task,
+    Fixes,
+    Security,
+}
+
+// Checks lines against the record grammar of the specification: record
+// layouts, field ranges and the order A, H, I/J, C, then fixes and the rest,
+// with G records last.
+pub struct Validator {
+    section: Section,
+    task_lines: usize,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self {
+            section: Section::Start,
+            task_lines: 0,
+        }
+    }
+
+    pub fn line(&mut self, line: &str) -> SimConnectResult<()> {
+        let invalid = |what: &str| {
+            SimConnectError::new(&format!("Invalid IGC line {:?}: {}", line, what), None)
+        };
+
+        if line.is_empty() || line.len() > MAX_LINE {
+            return Err(invalid("bad length"));
+        }
+        if !line.chars().all(|c| (' '..='~').contains(&c)) {
+            return Err(invalid("not printable ASCII"));
+        }
+        let body = &line[1..];
+        let record = line.as_bytes()[0];
+        if record != b'G' && body.contains(RESERVED) {
+            return Err(invalid("reserved character"));
+        }
+
+        if self.section == Section::Start {
+            let valid = record == b'A'
+                && body.len() >= 6
+                && body.bytes().take(6).all(|b| b.is_ascii_alphanumeric());
+            if !valid {
+                return Err(invalid("files start with an A record"));
+            }
+            self.section = Section::Header;
+            return Ok(());
+        }
+
+        let section = match record {
+            b'H' => {
+                let b = body.as_bytes();
+                if b.len() < 4
+                    || !matches!(b[0], b'F' | b'O' | b'P')
+                    || !b[1..4].iter().all(|b| b.is_ascii_alphanumeric())
+                {
+                    return Err(invalid("bad header code"));
+                }
+                if &body[1..4] == "DTE" && !valid_date(date_field(body)) {
+                    return Err(invalid("bad date"));
+                }
+                Section::Header
+            }
+            b'I' | b'J' => {
+                let count = digits(body, 0, 2).ok_or_else(|| invalid("bad extension count"))?;
+                if body.len() != 2 + 7 * count as usize {
+                    return Err(invalid("bad extension list"));
+                }
+                Section::Header
+            }
+            b'C' => {
+                let valid = match self.task_lines {
+                    0 => {
+                        body.len() >= 24
+                            && valid_date(&body[0..6])
+                            && valid_time(&body[6..12])
+                            && digits(body, 12, 12).is_some()
+                    }
+                    _ => valid_position(body),
+                };
+                if !valid {
+                    return Err(invalid("bad task record"));
+                }
+                self.task_lines += 1;
+                Section::Task
+            }
+            b'B' => {
+                let valid = body.len() >= 34
+                    && valid_time(&body[0..6])
+                    && valid_position(&body[6..])
+                    && matches!(&body[23..24], "A" | "V")
+                    && valid_altitude(&body[24..29])
+                    && valid_altitude(&body[29..34]);
+                if !valid {
+                    return Err(invalid("bad fix"));
+                }
+                Section::Fixes
+            }
+            // Events, satellites, data and comments go anywhere before the
+            // security records.
+            b'D' | b'E' | b'F' | b'K' | b'L' => self.section,
+            b'G' => Section::Security,
+            b'A' => return Err(invalid("second A record")),
+            _ => return Err(invalid("unknown record type")),
+        };
+
+        if section < self.section || (self.section == Section::Security && record != b'G') {
+            return Err(invalid("record out of order"));
+        }
+        self.section = section;
+        Ok(())
+    }
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator::new()
+    }
+}
+
+// Validates a complete file.
+pub fn validate(text: &str) -> SimConnectResult<()> {
+    let mut validator = Validator::new();
+    let mut fixes = 0;
+    for line in text.lines() {
+        validator.line(line)?;
+        fixes += usize::from(line.starts_with('B'));
+    }
+    match fixes {
+        0 => Err(SimConnectError::new("IGC file holds no fixes", None)),
+        _ => Ok(()),
+    }
+}
+
+fn digits(text: &str, start: usize, len: usize) -> Option<u64> {
+    let field = text.get(start..start + len)?;
+    match field.bytes().all(|b| b.is_ascii_digit()) {
+        true => field.parse().ok(),
+        false => None,
+    }
+}
+
+// `HFDTEDATE:DDMMYY,NN` or the older `HFDTEDDMMYY`.
+fn date_field(body: &str) -> &str {
+    let value = body[4..].rsplit(':').next().unwrap_or("");
+    value.get(..6).unwrap_or(value)
+}
+
+fn valid_date(text: &str) -> bool {
+    match (digits(text, 0, 2), digits(text, 2, 2), digits(text, 4, 2)) {
+        (Some(day), Some(month), Some(_)) => (1..=31).contains(&day) && (1..=12).contains(&month),
+        _ => false,
+    }
+}
+
+fn valid_time(text: &str) -> bool {
+    match (digits(text, 0, 2), digits(text, 2, 2), digits(text, 4, 2)) {
+        (Some(hour), Some(minute), Some(second)) => hour < 24 && minute < 60 && second < 60,
+        _ => false,
+    }
+}
+
+// `DDMMmmmNDDDMMmmmE`.
+fn valid_position(text: &str) -> bool {
+    let (lat_degrees, lat_minutes) = (digits(text, 0, 2), digits(text, 2, 5));
+    let (lon_degrees, lon_minutes) = (digits(text, 8, 3), digits(text, 11, 5));
+    match (lat_degrees, lat_minutes, lon_degrees, lon_minutes) {
+        (Some(lat), Some(lat_min), Some(lon), Some(lon_min)) => {
+            lat <= 90
+                && lat_min < 60_000
+                && lon <= 180
+                && lon_min < 60_000
+                && matches!(text.get(7..8), Some("N" | "S"))
+                && matches!(text.get(16..17), Some("E" | "W"))
+        }
+        _ => false,
+    }
+}
+
+fn valid_altitude(text: &str) -> bool {
+    match text.strip_prefix('-') {
+        Some(rest) => digits(rest, 0, 4).is_some() && rest.len() == 4,
+        None => digits(text, 0, 5).is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Sample;
+    use std::time::Instant;
+
+    fn snapshot(latitude: f64, longitude: f64, altitude_ft: f64) -> Snapshot {
+        let updated = Instant::now();
+        let sample = |value| Some(Sample { value, updated });
+        Snapshot {
+            object_id: 0,
+            revision: 1,
+            updated: Some(updated),
+            values: vec![
+                (
+                    "PLANE LATITUDE".into(),
+                    sample(SimVarValue::Number(latitude)),
+                ),
+                (
+                    "PLANE LONGITUDE".into(),
+                    sample(SimVarValue::Number(longitude)),
+                ),
+                (
+                    "PRESSURE ALTITUDE".into(),
+                    sample(SimVarValue::Number(-20.0)),
+                ),
+                (
+                    "PLANE ALTITUDE".into(),
+                    sample(SimVarValue::Number(altitude_ft)),
+                ),
+                (
+                    "TITLE".into(),
+                    sample(SimVarValue::String("ASK 21 $glider~".into())),
+                ),
+                (
+                    "ATC ID".into(),
+                    sample(SimVarValue::String("D-1234".into())),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn writes_files_the_validator_accepts() {
+        let task = IgcTask {
+            name: "Out and return".to_string(),
+            points: vec![
+                TaskPoint {
+                    name: "Start".to_string(),
+                    latitude: -33.95,
+                    longitude: -70.25,
+                },
+                TaskPoint {
+                    name: "Turn*point".to_string(),
+                    latitude: -34.5,
+                    longitude: -70.9,
+                },
+                TaskPoint {
+                    name: "Finish".to_string(),
+                    latitude: -33.95,
+                    longitude: -70.25,
+                },
+            ],
+        };
+        let options = IgcOptions::new()
+            .pilot("A. Pilot\\")
+            .serial("x1z")
+            .task(task);
+        // 2024-02-29 23:59:58 UTC, so the fixes cross midnight.
+        let mut writer = IgcWriter::new(Vec::new(), 1_709_251_198_000, options).unwrap();
+        for second in 0..5 {
+            let time = Duration::from_millis(second * 600);
+            let snapshot = snapshot(-33.95 - second as f64 * 0.01, -70.25, 1500.0);
+            writer.push_snapshot(time, &snapshot).unwrap();
+        }
+
+        let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+        validate(&text).unwrap();
+        assert!(text.starts_with("AXSSX1ZSIMPLY SIMCONNECT\r\nHFDTEDATE:290224,01\r\n"));
+        assert!(text.contains("HFGTYGLIDERTYPE:ASK 21 glider\r\n"));
+        assert!(text.contains("C3357000S07015000WStart\r\n"));
+        let fixes: Vec<&str> = text.lines().filter(|l| l.starts_with('B')).collect();
+        assert_eq!(
+            fixes,
+            vec![
+                "B2359583357000S07015000WA-002000457",
+                "B2359593358200S07015000WA-002000457",
+                "B0000003359400S07015000WA-002000457",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_fixes_before_the_header() {
+        assert!(validate("B2359583357000S07015000WA-002000457\r\n").is_err());
+    }
+}
@@ -0,0 +1,627 @@
+// Smooth motion for objects driven from low rate sources such as the network
+// or recordings. Samples are buffered per object and drawn on every `Frame`
+// event at a point a little behind the newest sample, so there is usually a
+// sample on each side to interpolate between. Past the newest sample the
+// object keeps its last velocity for a capped time.
+//
+// The delay adapts per object to the sample interval and the arrival
+// jitter: the source clock is mapped to ours through the quickest arrival
+// seen, and the delay covers the mean interval plus a multiple of the mean
+// lateness of the others. The drawn time eases towards changes in either.
+// Altitudes are kept above the ground the sim reports under the object, as
+// `PLANE ALTITUDE` less `PLANE ALT ABOVE GROUND`.
+
+use super::bindings::*;
+use super::definition::{DataDefinition, DataStruct};
+use super::message::Message;
+use super::simconnect::SimConnectApi;
+use super::tagged::{DatumValue, TaggedDefinition};
+use super::types::*;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+const MAX_SAMPLES: usize = 64;
+
+// How fast the clock offset may drift up, in seconds per second, so a single
+// early arrival does not pin it forever.
+const OFFSET_RELAX: f64 = 0.01;
+
+// Weight of a new observation in the interval and jitter averages.
+const SMOOTHING: f64 = 0.1;
+
+// How fast the drawn time may drift from our clock towards where the delay
+// and offset put it, in seconds per second. Changes to either show as
+// playback running up to 10% slower or faster rather than as jumps.
+const CLOCK_SLEW: f64 = 0.1;
+
+const ALTITUDE_DATUM: DWORD = 0;
+const ABOVE_GROUND_DATUM: DWORD = 1;
+
+// Angles in degrees with the sim's signs, where nose up and right wing down
+// are negative.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseSample {
+    // Seconds on the source's clock.
+    pub time: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+    pub pitch: f64,
+    pub bank: f64,
+    pub heading: f64,
+    pub on_ground: bool,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct Pose {
+    latitude: f64,
+    longitude: f64,
+    altitude_ft: f64,
+    pitch: f64,
+    bank: f64,
+    heading: f64,
+}
+
+unsafe impl DataStruct for Pose {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Linear,
+    // Cubic Hermite with Catmull-Rom tangents, passing through every sample.
+    Hermite,
+}
+
+#[derive(Debug, Clone)]
+pub struct InterpolationOptions {
+    pub method: Method,
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter_factor: f64,
+    pub max_extrapolation: Duration,
+    pub ground_clamp: bool,
+}
+
+impl InterpolationOptions {
+    pub fn new() -> Self {
+        Self {
+            method: Method::Hermite,
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            jitter_factor: 2.0,
+            max_extrapolation: Duration::from_secs(2),
+            ground_clamp: true,
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    // Bounds of the adaptive delay, 100 ms to 5 s by default. Equal bounds
+    // fix the delay.
+    pub fn delay(mut self, min: Duration, max: Duration) -> Self {
+        self.min_delay = min;
+        self.max_delay = max.max(min);
+        self
+    }
+
+    // How many mean latenesses the delay covers beyond the sample interval,
+    // 2 by default. Higher values trade latency for fewer extrapolations.
+    pub fn jitter_factor(mut self, factor: f64) -> Self {
+        self.jitter_factor = factor;
+        self
+    }
+
+    // 2 seconds by default, after which the object stops where it got to.
+    pub fn max_extrapolation(mut self, limit: Duration) -> Self {
+        self.max_extrapolation = limit;
+        self
+    }
+
+    pub fn ground_clamp(mut self, enabled: bool) -> Self {
+        self.ground_clamp = enabled;
+        self
+    }
+}
+
+impl Default for InterpolationOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Driven {
+    samples: VecDeque<PoseSample>,
+    request_id: Option<SIMCONNECT_DATA_REQUEST_ID>,
+    // Our clock less the source's, in seconds.
+    offset: Option<f64>,
+    offset_at: f64,
+    interval: Option<f64>,
+    jitter: f64,
+    // Our time and the source time of the last frame, kept from the first
+    // frame drawn with a known interval.
+    drawn: Option<(f64, f64)>,
+    ground_ft: Option<f64>,
+    ground_offset_ft: f64,
+}
+
+impl Driven {
+    fn wanted_delay(&self, options: &InterpolationOptions) -> f64 {
+        let wanted = self.interval.unwrap_or(0.0) + options.jitter_factor * self.jitter;
+        wanted.clamp(
+            options.min_delay.as_secs_f64(),
+            options.max_delay.as_secs_f64(),
+        )
+    }
+
+    // The source time to draw at, or None before the first sample.
+    fn draw_time(&mut self, local: f64, options: &InterpolationOptions) -> Option<f64> {
+        let due = local - self.offset? - self.wanted_delay(options);
+        let time = match self.drawn {
+            Some((at, time)) => {
+                let elapsed = local - at;
+                let step = CLOCK_SLEW * elapsed;
+                time + elapsed + (due - time - elapsed).clamp(-step, step)
+            }
+            // Carries on from the first sample the object was held at.
+            None => due.min(self.samples.front()?.time),
+        };
+        if self.interval.is_some() {
+            self.drawn = Some((local, time));
+        }
+        Some(time)
+    }
+
+    fn push(&mut self, sample: PoseSample, local: f64) {
+        if let Some(last) = self.samples.back() {
+            if sample.time <= last.time {
+                return;
+            }
+            let interval = sample.time - last.time;
+            self.interval = Some(match self.interval {
+                Some(mean) => mean + SMOOTHING * (interval - mean),
+                None => interval,
+            });
+        }
+
+        let observed = local - sample.time;
+        let offset = match self.offset {
+            Some(offset) => observed.min(offset + OFFSET_RELAX * (local - self.offset_at)),
+            None => observed,
+        };
+        self.jitter += SMOOTHING * ((observed - offset) - self.jitter);
+        self.offset = Some(offset);
+        self.offset_at = local;
+
+        self.samples.push_back(sample);
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    fn pose(&self, time: f64, options: &InterpolationOptions) -> Option<Pose> {
+        let first = self.samples.front()?;
+        let last = self.samples.back()?;
+
+        let (mut pose, on_ground) = if time <= first.time {
+            (to_pose(first), first.on_ground)
+        } else if time >= last.time {
+            let dt = (time - last.time).min(options.max_extrapolation.as_secs_f64());
+            let mut pose = to_pose(last);
+            if let Some(before) = self.samples.iter().rev().nth(1) {
+                let span = last.time - before.time;
+                pose.latitude += (last.latitude - before.latitude) / span * dt;
+                pose.longitude += wrap_degrees(last.longitude - before.longitude) / span * dt;
+                pose.altitude_ft += (last.altitude_ft - before.altitude_ft) / span * dt;
+                pose.heading += wrap_degrees(last.heading - before.heading) / span * dt;
+            }
+            (pose, last.on_ground)
+        } else {
+            let index = self.samples.iter().position(|s| s.time > time)? - 1;
+            (
+                self.interpolate(index, time, options.method),
+                self.samples[index].on_ground && self.samples[index + 1].on_ground,
+            )
+        };
+
+        pose.longitude = wrap_degrees(pose.longitude);
+        pose.heading = pose.heading.rem_euclid(360.0);
+        if let (true, Some(ground_ft)) = (options.ground_clamp, self.ground_ft) {
+            let floor = ground_ft + self.ground_offset_ft;
+            pose.altitude_ft = match on_ground {
+                true => floor,
+                false => pose.altitude_ft.max(floor),
+            };
+        }
+        Some(pose)
+    }
+
+    // Between samples `index` and `index + 1`.
+    fn interpolate(&self, index: usize, time: f64, method: Method) -> Pose {
+        let (a, b) = (&self.samples[index], &self.samples[index + 1]);
+        let span = b.time - a.time;
+        let u = (time - a.time) / span;
+
+        // Every channel as an offset from `a`, unwrapped for angles.
+        let channels = |s: &PoseSample| {
+            [
+                s.latitude - a.latitude,
+                wrap_degrees(s.longitude - a.longitude),
+                s.altitude_ft - a.altitude_ft,
+                wrap_degrees(s.pitch - a.pitch),
+                wrap_degrees(s.bank - a.bank),
+                wrap_degrees(s.heading - a.heading),
+            ]
+        };
+        let pb = channels(b);
+        let values: [f64; 6] = match method {
+            Method::Linear => pb.map(|p| p * u),
+            Method::Hermite => {
+                let before = index.checked_sub(1).map(|i| &self.samples[i]);
+                let after = self.samples.get(index + 2);
+                // Catmull-Rom tangents for uneven spacing, one sided at the
+                // ends of the buffer.
+                let tangent = |prev: Option<&PoseSample>, next: Option<&PoseSample>, at: usize| {
+                    let (p0, t0) = match prev {
+                        Some(s) => (channels(s), s.time),
+                        None => (channels(&self.samples[at]), self.samples[at].time),
+                    };
+                    let (p1, t1) = match next {
+                        Some(s) => (channels(s), s.time),
+                        None => (channels(&self.samples[at]), self.samples[at].time),
+                    };
+                    let dt = t1 - t0;
+                    let mut m = [0.0; 6];
+                    for (i, m) in m.iter_mut().enumerate() {
+                        *m = (p1[i] - p0[i]) / dt * span;
+                    }
+                    m
+                };
+                let m0 = tangent(before, Some(b), index);
+                let m1 = tangent(Some(a), after, index + 1);
+
+                let (u2, u3) = (u * u, u * u * u);
+                let h10 = u3 - 2.0 * u2 + u;
+                let h01 = -2.0 * u3 + 3.0 * u2;
+                let h11 = u3 - u2;
+                let mut values = [0.0; 6];
+                for (i, v) in values.iter_mut().enumerate() {
+                    *v = h10 * m0[i] + h01 * pb[i] + h11 * m1[i];
+                }
+                values
+            }
+        };
+
+        Pose {
+            latitude: a.latitude + values[0],
+            longitude: a.longitude + values[1],
+            altitude_ft: a.altitude_ft + values[2],
+            pitch: wrap_degrees(a.pitch + values[3]),
+            bank: wrap_degrees(a.bank + values[4]),
+            heading: a.heading + values[5],
+        }
+    }
+}
+
+fn to_pose(sample: &PoseSample) -> Pose {
+    Pose {
+        latitude: sample.latitude,
+        longitude: sample.longitude,
+        altitude_ft: sample.altitude_ft,
+        pitch: sample.pitch,
+        bank: sample.bank,
+        heading: sample.heading,
+    }
+}
+
+fn wrap_degrees(delta: f64) -> f64 {
+    (delta + 180.0).rem_euclid(360.0) - 180.0
+}
+
+pub struct Interpolator {
+    options: InterpolationOptions,
+    frame_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    pose: DataDefinition<Pose>,
+    ground: TaggedDefinition,
+    free_request_ids: Vec<SIMCONNECT_DATA_REQUEST_ID>,
+    objects: HashMap<SIMCONNECT_OBJECT_ID, Driven>,
+    epoch: Instant,
+}
+
+impl Interpolator {
+    // Registers the pose under `pose_define_id` and the ground readings under
+    // `ground_define_id`, and subscribes `Frame` as `frame_event_id`. Each
+    // object takes one of `request_ids` for its ground readings.
+    pub fn new(
+        simconnect: &impl SimConnectApi,
+        pose_define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        ground_define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        frame_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        request_ids: Range<SIMCONNECT_DATA_REQUEST_ID>,
+        options: InterpolationOptions,
+        now: Instant,
+    ) -> SimConnectResult<Self> {
+        let mut pose = DataDefinition::new(pose_define_id);
+        for (name, units) in [
+            ("PLANE LATITUDE", "degrees"),
+            ("PLANE LONGITUDE", "degrees"),
+            ("PLANE ALTITUDE", "feet"),
+            ("PLANE PITCH DEGREES", "degrees"),
+            ("PLANE BANK DEGREES", "degrees"),
+            ("PLANE HEADING DEGREES TRUE", "degrees"),
+        ] {
+            pose.add(simconnect, name, units, DataType::Float64, 0.0)?;
+        }
+
+        let mut ground = TaggedDefinition::new(ground_define_id);
+        if options.ground_clamp {
+            ground.add(
+                simconnect,
+                ALTITUDE_DATUM,
+                "PLANE ALTITUDE",
+                "feet",
+                DataType::Float64,
+                0.0,
+            )?;
+            ground.add(
+                simconnect,
+                ABOVE_GROUND_DATUM,
+                "PLANE ALT ABOVE GROUND",
+                "feet",
+                DataType::Float64,
+                0.0,
+            )?;
+        }
+        simconnect.subscribe_to_system_event(frame_event_id, "Frame")?;
+
+        Ok(Self {
+            options,
+            frame_event_id,
+            pose,
+            ground,
+            free_request_ids: request_ids.rev().collect(),
+            objects: HashMap::new(),
+            epoch: now,
+        })
+    }
+
+    pub fn options(&self) -> &InterpolationOptions {
+        &self.options
+    }
+
+    // Starts driving the object. With ground clamping this asks for the
+    // ground under it once a second.
+    pub fn add(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+    ) -> SimConnectResult<()> {
+        if self.objects.contains_key(&object_id) {
+            return Ok(());
+        }
+
+        let request_id = match self.options.ground_clamp {
+            true => {
+                let request_id = self.free_request_ids.pop().ok_or_else(|| {
+                    SimConnectError::new("Interpolator is out of request ids", None)
+                })?;
+                if let Err(e) =
+                    self.ground
+                        .request(simconnect, request_id, object_id, Period::Second, false)
+                {
+                    self.free_request_ids.push(request_id);
+                    return Err(e);
+                }
+                Some(request_id)
+            }
+            false => None,
+        };
+
+        self.objects.insert(
+            object_id,
+            Driven {
+                samples: VecDeque::new(),
+                request_id,
+                offset: None,
+                offset_at: 0.0,
+                interval: None,
+                jitter: 0.0,
+                drawn: None,
+                ground_ft: None,
+                ground_offset_ft: 0.0,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+    ) -> SimConnectResult<()> {
+        let Some(driven) = self.objects.remove(&object_id) else {
+            return Ok(());
+        };
+        if let Some(request_id) = driven.request_id {
+            self.free_request_ids.push(request_id);
+            self.ground
+                .request(simconnect, request_id, object_id, Period::Never, false)?;
+        }
+        Ok(())
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = SIMCONNECT_OBJECT_ID> + '_ {
+        self.objects.keys().copied()
+    }
+
+    // Height of the object's reference point above its wheels, so objects on
+    // the ground do not sink into it.
+    pub fn set_ground_offset(&mut self, object_id: SIMCONNECT_OBJECT_ID, offset_ft: f64) {
+        if let Some(driven) = self.objects.get_mut(&object_id) {
+            driven.ground_offset_ft = offset_ft;
+        }
+    }
+
+    // Samples older than the newest one of the object are dropped.
+    pub fn push(&mut self, object_id: SIMCONNECT_OBJECT_ID, sample: PoseSample, now: Instant) {
+        let local = self.local(now);
+        if let Some(driven) = self.objects.get_mut(&object_id) {
+            driven.push(sample, local);
+        }
+    }
+
+    // The delay the object is currently drawn with.
+    pub fn delay(&self, object_id: SIMCONNECT_OBJECT_ID) -> Option<Duration> {
+        let driven = self.objects.get(&object_id)?;
+        let delay = match (driven.drawn, driven.offset) {
+            (Some((at, time)), Some(offset)) => (at - offset - time).max(0.0),
+            _ => driven.wanted_delay(&self.options),
+        };
+        Some(Duration::from_secs_f64(delay))
+    }
+
+    // Draws on `Frame` events and takes ground readings, ignoring anything
+    // else.
+    pub fn handle_message(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        message: &Message,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        match message {
+            Message::EventFrame { event, .. } if event.event_id == self.frame_event_id => {
+                self.frame(simconnect, now)
+            }
+            Message::SimObjectData(data) => {
+                let Some(driven) = self
+                    .objects
+                    .values_mut()
+                    .find(|d| d.request_id == Some(data.request_id))
+                else {
+                    return Ok(());
+                };
+                let values = self.ground.decode(data)?;
+                let value = |datum| {
+                    values.iter().find_map(|(id, value)| match value {
+                        DatumValue::Float64(v) if *id == datum => Some(*v),
+                        _ => None,
+                    })
+                };
+                if let (Some(altitude), Some(above_ground)) =
+                    (value(ALTITUDE_DATUM), value(ABOVE_GROUND_DATUM))
+                {
+                    driven.ground_ft = Some(altitude - above_ground);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Moves every object with samples to where it is due at `now`.
+    pub fn frame(&mut self, simconnect: &impl SimConnectApi, now: Instant) -> SimConnectResult<()> {
+        let local = self.local(now);
+        for (object_id, driven) in self.objects.iter_mut() {
+            let Some(time) = driven.draw_time(local, &self.options) else {
+                continue;
+            };
+            let Some(pose) = driven.pose(time, &self.options) else {
+                continue;
+            };
+            self.pose.set(simconnect, *object_id, &pose)?;
+
+            // One sample before the current pair is kept for the tangent.
+            while driven.samples.len() > 3 && driven.samples[2].time <= time {
+                driven.samples.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    // Stops every ground request and the `Frame` subscription.
+    pub fn shutdown(&mut self, simconnect: &impl SimConnectApi) -> SimConnectResult<()> {
+        let objects: Vec<SIMCONNECT_OBJECT_ID> = self.objects.keys().copied().collect();
+        for object_id in objects {
+            self.remove(simconnect, object_id)?;
+        }
+        simconnect.unsubscribe_from_system_event(self.frame_event_id)
+    }
+
+    fn local(&self, now: Instant) -> f64 {
+        now.saturating_duration_since(self.epoch).as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Climbing at 100 ft/s² from 1000 ft, heading east across the
+    // antimeridian at a degree a second.
+    fn driven(times: &[f64]) -> Driven {
+        let mut driven = Driven {
+            samples: VecDeque::new(),
+            request_id: None,
+            offset: None,
+            offset_at: 0.0,
+            interval: None,
+            jitter: 0.0,
+            drawn: None,
+            ground_ft: None,
+            ground_offset_ft: 0.0,
+        };
+        for &time in times {
+            let sample = PoseSample {
+                time,
+                latitude: 10.0,
+                longitude: wrap_degrees(178.0 + time),
+                altitude_ft: 1000.0 + 50.0 * time * time,
+                pitch: 0.0,
+                bank: 0.0,
+                heading: 90.0,
+                on_ground: false,
+            };
+            driven.push(sample, time);
+        }
+        driven
+    }
+
+    #[test]
+    fn interpolates_through_the_samples() {
+        let driven = driven(&[0.0, 1.0, 2.0, 3.0]);
+        let options = InterpolationOptions::new().ground_clamp(false);
+
+        let pose = driven.pose(2.0, &options).unwrap();
+        assert_eq!(({ pose.altitude_ft }, { pose.longitude }), (1200.0, -180.0));
+
+        // Catmull-Rom tangents are exact for a quadratic between evenly
+        // spaced samples, where linear interpolation cuts the corner.
+        let pose = driven.pose(1.5, &options).unwrap();
+        assert!(({ pose.altitude_ft } - 1112.5).abs() < 1e-9);
+        assert!(({ pose.longitude } - 179.5).abs() < 1e-9);
+        let pose = driven
+            .pose(1.5, &options.clone().method(Method::Linear))
+            .unwrap();
+        assert!(({ pose.altitude_ft } - 1125.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stops_extrapolating_after_the_limit() {
+        let driven = driven(&[0.0, 1.0, 2.0]);
+        let options = InterpolationOptions::new()
+            .max_extrapolation(Duration::from_secs(2))
+            .ground_clamp(false);
+
+        // 150 ft/s over the last interval, carried on for two seconds.
+        let pose = driven.pose(3.0, &options).unwrap();
+        assert!(({ pose.altitude_ft } - 1350.0).abs() < 1e-9);
+        for time in [4.0, 10.0] {
+            let pose = driven.pose(time, &options).unwrap();
+            assert!(({ pose.altitude_ft } - 1500.0).abs() < 1e-9);
+            assert!(({ pose.longitude } + 178.0).abs() < 1e-9);
+        }
+    }
+}
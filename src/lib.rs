@@ -1,3 +1,35 @@
+pub mod adsb;
+#[cfg(all(feature = "sdk", feature = "tokio"))]
+pub mod ai;
+#[cfg(all(feature = "sdk", feature = "tokio"))]
+pub mod async_client;
 mod bindings;
+pub mod catalogue;
+#[cfg(feature = "sdk")]
+pub mod client;
+pub mod config;
+pub mod definition;
+pub mod export;
+pub mod flt;
+pub mod igc;
+pub mod interp;
+pub mod message;
+#[cfg(feature = "sdk")]
+pub mod mobiflight;
+pub mod multiplexer;
+pub mod pln;
+pub mod replay;
+pub mod route;
+pub mod rpc;
+pub mod server;
+pub mod session;
+pub mod simbrief;
 pub mod simconnect;
+pub mod state;
+#[cfg(feature = "sdk")]
+pub mod supervisor;
+pub mod sweep;
+pub mod tagged;
+pub mod track;
 pub mod types;
+pub mod websocket;
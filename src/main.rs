@@ -1,6 +1,26 @@
+use simply_simconnect::server::Server;
 use simply_simconnect::simconnect::SimConnect;
+use simply_simconnect::websocket;
+use std::env;
+use std::sync::mpsc;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8765";
 
 fn main() {
-    let s = &mut SimConnect::new();
-    s.open("test").unwrap();
+    let address = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+
+    let mut simconnect = SimConnect::new();
+    simconnect
+        .open("simply-simconnect")
+        .expect("Unable to connect to the simulator");
+
+    let (commands, incoming) = mpsc::channel();
+    websocket::listen(&address, commands).expect("Unable to start websocket server");
+    println!("Listening on ws://{}", address);
+
+    Server::new(simconnect)
+        .run(incoming)
+        .expect("Lost connection to the simulator");
 }
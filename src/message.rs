@@ -0,0 +1,446 @@
+use super::bindings::*;
+use super::types::*;
+
+#[derive(Debug, Clone)]
+pub struct EventData {
+    pub group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+    pub event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    pub data: DWORD,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectData {
+    pub request_id: SIMCONNECT_DATA_REQUEST_ID,
+    pub object_id: SIMCONNECT_OBJECT_ID,
+    pub define_id: DWORD,
+    pub flags: DWORD,
+    pub entry_number: DWORD,
+    pub out_of: DWORD,
+    pub define_count: DWORD,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExceptionData {
+    pub exception: DWORD,
+    pub send_id: DWORD,
+    pub index: DWORD,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenData {
+    pub application_name: String,
+    pub application_version_major: DWORD,
+    pub application_version_minor: DWORD,
+    pub application_build_major: DWORD,
+    pub application_build_minor: DWORD,
+    pub simconnect_version_major: DWORD,
+    pub simconnect_version_minor: DWORD,
+    pub simconnect_build_major: DWORD,
+    pub simconnect_build_minor: DWORD,
+}
+
+impl OpenData {
+    pub fn server_info(&self) -> ServerInfo {
+        ServerInfo {
+            application_name: self.application_name.clone(),
+            application_version: (
+                self.application_version_major,
+                self.application_version_minor,
+            ),
+            application_build: (self.application_build_major, self.application_build_minor),
+            simconnect_version: (self.simconnect_version_major, self.simconnect_version_minor),
+            simconnect_build: (self.simconnect_build_major, self.simconnect_build_minor),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SystemStateData {
+    pub request_id: SIMCONNECT_DATA_REQUEST_ID,
+    pub integer: DWORD,
+    pub float: f32,
+    pub string: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FacilityAirport {
+    pub icao: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FacilityWaypoint {
+    pub icao: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub mag_var: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FacilityNdb {
+    pub icao: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub mag_var: f32,
+    pub frequency: DWORD,
+}
+
+#[derive(Debug, Clone)]
+pub struct FacilityVor {
+    pub icao: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+    pub mag_var: f32,
+    pub frequency: DWORD,
+    pub flags: DWORD,
+    pub localizer: f32,
+    pub glide_lat: f64,
+    pub glide_lon: f64,
+    pub glide_alt: f64,
+    pub glide_slope_angle: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FacilityList<T> {
+    pub request_id: SIMCONNECT_DATA_REQUEST_ID,
+    pub entry_number: DWORD,
+    pub out_of: DWORD,
+    pub items: Vec<T>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Null,
+    Exception(ExceptionData),
+    Open(OpenData),
+    Quit,
+    Event(EventData),
+    EventObjectAddRemove {
+        event: EventData,
+        object_type: DWORD,
+    },
+    EventFilename {
+        event: EventData,
+        file_name: String,
+        flags: DWORD,
+    },
+    EventFrame {
+        event: EventData,
+        frame_rate: f32,
+        sim_speed: f32,
+    },
+    SimObjectData(ObjectData),
+    SimObjectDataByType(ObjectData),
+    ClientData(ObjectData),
+    AssignedObjectId {
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+    },
+    SystemState(SystemStateData),
+    AirportList(FacilityList<FacilityAirport>),
+    WaypointList(FacilityList<FacilityWaypoint>),
+    NdbList(FacilityList<FacilityNdb>),
+    VorList(FacilityList<FacilityVor>),
+    Unknown {
+        id: DWORD,
+        data: Vec<u8>,
+    },
+}
+
+// Offsets below follow the packed SIMCONNECT_RECV_* layouts from SimConnect.h.
+const RECV_HEADER_SIZE: usize = 12;
+pub(crate) const OBJECT_DATA_OFFSET: usize = 40;
+const OPEN_NAME_SIZE: usize = 256;
+const FACILITY_LIST_OFFSET: usize = 28;
+const ICAO_SIZE: usize = 9;
+const AIRPORT_SIZE: usize = ICAO_SIZE + 24;
+const WAYPOINT_SIZE: usize = AIRPORT_SIZE + 4;
+const NDB_SIZE: usize = WAYPOINT_SIZE + 4;
+const VOR_SIZE: usize = NDB_SIZE + 36;
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> SimConnectResult<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| SimConnectError::new("Message is truncated", None))
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> SimConnectResult<u16> {
+        let b = self.slice(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> SimConnectResult<u32> {
+        let b = self.slice(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&self, offset: usize) -> SimConnectResult<u64> {
+        let b = self.slice(offset, 8)?;
+        let mut raw = [0; 8];
+        raw.copy_from_slice(b);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    pub(crate) fn f32(&self, offset: usize) -> SimConnectResult<f32> {
+        Ok(f32::from_bits(self.u32(offset)?))
+    }
+
+    pub(crate) fn f64(&self, offset: usize) -> SimConnectResult<f64> {
+        Ok(f64::from_bits(self.u64(offset)?))
+    }
+
+    pub(crate) fn string(&self, offset: usize, len: usize) -> SimConnectResult<String> {
+        Ok(c_string_from_bytes(self.slice(offset, len)?))
+    }
+
+    pub(crate) fn rest(&self, offset: usize) -> &'a [u8] {
+        self.bytes.get(offset..).unwrap_or(&[])
+    }
+}
+
+pub(crate) fn c_string_from_bytes(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+impl Message {
+    pub fn from_bytes(bytes: &[u8]) -> SimConnectResult<Self> {
+        let r = Reader::new(bytes);
+        let id = r.u32(8)?;
+
+        let message = match id as SIMCONNECT_RECV_ID {
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_NULL => Message::Null,
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EXCEPTION => Message::Exception(ExceptionData {
+                exception: r.u32(12)?,
+                send_id: r.u32(16)?,
+                index: r.u32(20)?,
+            }),
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_OPEN => {
+                let v = RECV_HEADER_SIZE + OPEN_NAME_SIZE;
+                Message::Open(OpenData {
+                    application_name: r.string(RECV_HEADER_SIZE, OPEN_NAME_SIZE)?,
+                    application_version_major: r.u32(v)?,
+                    application_version_minor: r.u32(v + 4)?,
+                    application_build_major: r.u32(v + 8)?,
+                    application_build_minor: r.u32(v + 12)?,
+                    simconnect_version_major: r.u32(v + 16)?,
+                    simconnect_version_minor: r.u32(v + 20)?,
+                    simconnect_build_major: r.u32(v + 24)?,
+                    simconnect_build_minor: r.u32(v + 28)?,
+                })
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_QUIT => Message::Quit,
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT => Message::Event(read_event(&r)?),
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_OBJECT_ADDREMOVE => {
+                Message::EventObjectAddRemove {
+                    event: read_event(&r)?,
+                    object_type: r.u32(24)?,
+                }
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_FILENAME => Message::EventFilename {
+                event: read_event(&r)?,
+                file_name: r.string(24, MAX_PATH as usize)?,
+                flags: r.u32(24 + MAX_PATH as usize)?,
+            },
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT_FRAME => Message::EventFrame {
+                event: read_event(&r)?,
+                frame_rate: r.f32(24)?,
+                sim_speed: r.f32(28)?,
+            },
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SIMOBJECT_DATA => {
+                Message::SimObjectData(read_object_data(&r)?)
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SIMOBJECT_DATA_BYTYPE => {
+                Message::SimObjectDataByType(read_object_data(&r)?)
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_CLIENT_DATA => {
+                Message::ClientData(read_object_data(&r)?)
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_ASSIGNED_OBJECT_ID => Message::AssignedObjectId {
+                request_id: r.u32(12)?,
+                object_id: r.u32(16)?,
+            },
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SYSTEM_STATE => {
+                Message::SystemState(SystemStateData {
+                    request_id: r.u32(12)?,
+                    integer: r.u32(16)?,
+                    float: r.f32(20)?,
+                    string: r.string(24, MAX_PATH as usize)?,
+                })
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_AIRPORT_LIST => {
+                Message::AirportList(read_facility_list(&r, AIRPORT_SIZE, |r, o| {
+                    Ok(FacilityAirport {
+                        icao: r.string(o, ICAO_SIZE)?,
+                        latitude: r.f64(o + ICAO_SIZE)?,
+                        longitude: r.f64(o + ICAO_SIZE + 8)?,
+                        altitude: r.f64(o + ICAO_SIZE + 16)?,
+                    })
+                })?)
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_WAYPOINT_LIST => {
+                Message::WaypointList(read_facility_list(&r, WAYPOINT_SIZE, |r, o| {
+                    Ok(FacilityWaypoint {
+                        icao: r.string(o, ICAO_SIZE)?,
+                        latitude: r.f64(o + ICAO_SIZE)?,
+                        longitude: r.f64(o + ICAO_SIZE + 8)?,
+                        altitude: r.f64(o + ICAO_SIZE + 16)?,
+                        mag_var: r.f32(o + AIRPORT_SIZE)?,
+                    })
+                })?)
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_NDB_LIST => {
+                Message::NdbList(read_facility_list(&r, NDB_SIZE, |r, o| {
+                    Ok(FacilityNdb {
+                        icao: r.string(o, ICAO_SIZE)?,
+                        latitude: r.f64(o + ICAO_SIZE)?,
+                        longitude: r.f64(o + ICAO_SIZE + 8)?,
+                        altitude: r.f64(o + ICAO_SIZE + 16)?,
+                        mag_var: r.f32(o + AIRPORT_SIZE)?,
+                        frequency: r.u32(o + WAYPOINT_SIZE)?,
+                    })
+                })?)
+            }
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_VOR_LIST => {
+                Message::VorList(read_facility_list(&r, VOR_SIZE, |r, o| {
+                    Ok(FacilityVor {
+                        icao: r.string(o, ICAO_SIZE)?,
+                        latitude: r.f64(o + ICAO_SIZE)?,
+                        longitude: r.f64(o + ICAO_SIZE + 8)?,
+                        altitude: r.f64(o + ICAO_SIZE + 16)?,
+                        mag_var: r.f32(o + AIRPORT_SIZE)?,
+                        frequency: r.u32(o + WAYPOINT_SIZE)?,
+                        flags: r.u32(o + NDB_SIZE)?,
+                        localizer: r.f32(o + NDB_SIZE + 4)?,
+                        glide_lat: r.f64(o + NDB_SIZE + 8)?,
+                        glide_lon: r.f64(o + NDB_SIZE + 16)?,
+                        glide_alt: r.f64(o + NDB_SIZE + 24)?,
+                        glide_slope_angle: r.f32(o + NDB_SIZE + 32)?,
+                    })
+                })?)
+            }
+            _ => Message::Unknown {
+                id,
+                data: bytes.to_vec(),
+            },
+        };
+
+        Ok(message)
+    }
+}
+
+fn read_event(r: &Reader) -> SimConnectResult<EventData> {
+    Ok(EventData {
+        group_id: r.u32(12)?,
+        event_id: r.u32(16)?,
+        data: r.u32(20)?,
+    })
+}
+
+fn read_object_data(r: &Reader) -> SimConnectResult<ObjectData> {
+    Ok(ObjectData {
+        request_id: r.u32(12)?,
+        object_id: r.u32(16)?,
+        define_id: r.u32(20)?,
+        flags: r.u32(24)?,
+        entry_number: r.u32(28)?,
+        out_of: r.u32(32)?,
+        define_count: r.u32(36)?,
+        data: r.rest(OBJECT_DATA_OFFSET).to_vec(),
+    })
+}
+
+fn read_facility_list<T>(
+    r: &Reader,
+    item_size: usize,
+    read_item: impl Fn(&Reader, usize) -> SimConnectResult<T>,
+) -> SimConnectResult<FacilityList<T>> {
+    let count = r.u32(16)? as usize;
+    let items = (0..count)
+        .map(|i| read_item(r, FACILITY_LIST_OFFSET + i * item_size))
+        .collect::<SimConnectResult<Vec<T>>>()?;
+
+    Ok(FacilityList {
+        request_id: r.u32(12)?,
+        entry_number: r.u32(20)?,
+        out_of: r.u32(24)?,
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vor(icao: &str, latitude: f64, frequency: u32, glide_slope_angle: f32) -> Vec<u8> {
+        let mut bytes = icao.as_bytes().to_vec();
+        bytes.resize(ICAO_SIZE, 0);
+        for v in [latitude, 8.5, 1200.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes.extend_from_slice(&2.5f32.to_le_bytes());
+        bytes.extend_from_slice(&frequency.to_le_bytes());
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(&90.0f32.to_le_bytes());
+        for v in [latitude + 0.01, 8.51, 1210.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes.extend_from_slice(&glide_slope_angle.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn reads_every_entry_of_a_vor_list() {
+        let entries = [
+            vor("FRA", 50.05, 114_200_000, 3.0),
+            vor("TAU", 50.2, 116_700_000, 0.0),
+        ];
+        let mut bytes = Vec::new();
+        let size = FACILITY_LIST_OFFSET + entries.iter().map(Vec::len).sum::<usize>();
+        for v in [
+            size as u32,
+            4,
+            SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_VOR_LIST as u32,
+            9,
+            2,
+            0,
+            1,
+        ] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        for entry in &entries {
+            assert_eq!(entry.len(), VOR_SIZE);
+            bytes.extend_from_slice(entry);
+        }
+
+        let Message::VorList(list) = Message::from_bytes(&bytes).unwrap() else {
+            panic!("not a VOR list");
+        };
+        assert_eq!((list.request_id, list.entry_number, list.out_of), (9, 0, 1));
+        assert_eq!(list.items.len(), 2);
+        let second = &list.items[1];
+        assert_eq!(second.icao, "TAU");
+        assert_eq!((second.latitude, second.longitude), (50.2, 8.5));
+        assert_eq!(second.frequency, 116_700_000);
+        assert_eq!(second.flags, 7);
+        assert_eq!(second.glide_lat, 50.21);
+        assert_eq!(second.glide_slope_angle, 0.0);
+        assert_eq!(list.items[0].glide_slope_angle, 3.0);
+    }
+}
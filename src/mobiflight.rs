@@ -0,0 +1,265 @@
+// Client for the MobiFlight WASM module client data protocol.
+// See https://github.com/MobiFlight/MobiFlight-WASM-Module for the module side.
+
+use super::bindings::*;
+use super::message::{c_string_from_bytes, Message};
+use super::simconnect::SimConnect;
+use super::types::*;
+
+pub const MESSAGE_SIZE: usize = 1024;
+pub const DEFAULT_CHANNEL: &str = "MobiFlight";
+
+const DUMMY_COMMAND: &str = "MF.DummyCmd";
+const FLOAT_SIZE: DWORD = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MobiFlightIds {
+    pub lvars_area: SIMCONNECT_CLIENT_DATA_ID,
+    pub command_area: SIMCONNECT_CLIENT_DATA_ID,
+    pub response_area: SIMCONNECT_CLIENT_DATA_ID,
+    pub command_define: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+    pub response_define: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+    pub response_request: SIMCONNECT_DATA_REQUEST_ID,
+    // Each registered variable uses `first_var_define + index` and
+    // `first_var_request + index`, so leave room after these ids.
+    pub first_var_define: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+    pub first_var_request: SIMCONNECT_DATA_REQUEST_ID,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MobiFlightEvent {
+    Pong,
+    ClientAdded(String),
+    VarChanged {
+        index: usize,
+        name: String,
+        value: f32,
+    },
+    LVarList(Vec<String>),
+    Response(String),
+}
+
+pub struct MobiFlightChannel {
+    name: String,
+    ids: MobiFlightIds,
+    vars: Vec<String>,
+    lvar_list: Option<Vec<String>>,
+}
+
+impl MobiFlightChannel {
+    pub fn new(name: &str, ids: MobiFlightIds) -> Self {
+        Self {
+            name: name.to_string(),
+            ids,
+            vars: Vec::new(),
+            lvar_list: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    pub fn connect(&self, simconnect: &SimConnect) -> SimConnectResult<()> {
+        let ids = &self.ids;
+        simconnect.map_client_data_name_to_id(&self.area_name("LVars"), ids.lvars_area)?;
+        simconnect.map_client_data_name_to_id(&self.area_name("Command"), ids.command_area)?;
+        simconnect.map_client_data_name_to_id(&self.area_name("Response"), ids.response_area)?;
+
+        simconnect.add_to_client_data_definition(
+            ids.command_define,
+            0,
+            MESSAGE_SIZE as DWORD,
+            0.0,
+            0,
+        )?;
+        simconnect.add_to_client_data_definition(
+            ids.response_define,
+            0,
+            MESSAGE_SIZE as DWORD,
+            0.0,
+            0,
+        )?;
+
+        simconnect.request_client_data(
+            ids.response_area,
+            ids.response_request,
+            ids.response_define,
+            ClientDataPeriod::OnSet,
+            ClientDataRequestFlag::Default,
+            0,
+            0,
+            0,
+        )
+    }
+
+    pub fn send_command(&self, simconnect: &SimConnect, command: &str) -> SimConnectResult<()> {
+        self.write_command(simconnect, command)?;
+        // The module only sees changed command strings, so repeating a command
+        // would be ignored without something else written in between.
+        self.write_command(simconnect, DUMMY_COMMAND)
+    }
+
+    pub fn ping(&self, simconnect: &SimConnect) -> SimConnectResult<()> {
+        self.send_command(simconnect, "MF.Ping")
+    }
+
+    // Asks the module to create the `<client_name>.*` areas. Once
+    // `MobiFlightEvent::ClientAdded` arrives, connect a new channel by that name.
+    pub fn add_client(&self, simconnect: &SimConnect, client_name: &str) -> SimConnectResult<()> {
+        self.send_command(simconnect, &format!("MF.Clients.Add.{}", client_name))
+    }
+
+    pub fn register_var(
+        &mut self,
+        simconnect: &SimConnect,
+        expression: &str,
+    ) -> SimConnectResult<usize> {
+        let index = self.vars.len();
+        let define_id = self.ids.first_var_define + index as DWORD;
+
+        simconnect.add_to_client_data_definition(
+            define_id,
+            index as DWORD * FLOAT_SIZE,
+            FLOAT_SIZE,
+            0.0,
+            0,
+        )?;
+        simconnect.request_client_data(
+            self.ids.lvars_area,
+            self.ids.first_var_request + index as DWORD,
+            define_id,
+            ClientDataPeriod::OnSet,
+            ClientDataRequestFlag::Changed,
+            0,
+            0,
+            0,
+        )?;
+        self.send_command(simconnect, &format!("MF.SimVars.Add.{}", expression))?;
+
+        self.vars.push(expression.to_string());
+        Ok(index)
+    }
+
+    pub fn register_lvar(
+        &mut self,
+        simconnect: &SimConnect,
+        name: &str,
+    ) -> SimConnectResult<usize> {
+        self.register_var(simconnect, &format!("(L:{})", name))
+    }
+
+    pub fn clear_vars(&mut self, simconnect: &SimConnect) -> SimConnectResult<()> {
+        for index in 0..self.vars.len() {
+            let define_id = self.ids.first_var_define + index as DWORD;
+            simconnect.request_client_data(
+                self.ids.lvars_area,
+                self.ids.first_var_request + index as DWORD,
+                define_id,
+                ClientDataPeriod::Never,
+                ClientDataRequestFlag::Default,
+                0,
+                0,
+                0,
+            )?;
+            simconnect.clear_client_data_definition(define_id)?;
+        }
+
+        self.vars.clear();
+        self.send_command(simconnect, "MF.SimVars.Clear")
+    }
+
+    pub fn execute_calculator_code(
+        &self,
+        simconnect: &SimConnect,
+        code: &str,
+    ) -> SimConnectResult<()> {
+        self.send_command(simconnect, &format!("MF.SimVars.Set.{}", code))
+    }
+
+    pub fn set_lvar(
+        &self,
+        simconnect: &SimConnect,
+        name: &str,
+        value: f64,
+    ) -> SimConnectResult<()> {
+        self.execute_calculator_code(simconnect, &format!("{} (>L:{})", value, name))
+    }
+
+    pub fn list_lvars(&self, simconnect: &SimConnect) -> SimConnectResult<()> {
+        self.send_command(simconnect, "MF.LVars.List")
+    }
+
+    pub fn handle_message(&mut self, message: &Message) -> Option<MobiFlightEvent> {
+        let data = match message {
+            Message::ClientData(data) => data,
+            _ => return None,
+        };
+
+        if data.request_id == self.ids.response_request {
+            return self.handle_response(c_string_from_bytes(&data.data));
+        }
+
+        let index = data.request_id.checked_sub(self.ids.first_var_request)? as usize;
+        let name = self.vars.get(index)?.clone();
+        let value = data.data.get(0..4)?;
+        Some(MobiFlightEvent::VarChanged {
+            index,
+            name,
+            value: f32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+        })
+    }
+
+    fn handle_response(&mut self, response: String) -> Option<MobiFlightEvent> {
+        match response.as_str() {
+            "MF.Pong" => return Some(MobiFlightEvent::Pong),
+            "MF.LVars.List.Start" => {
+                self.lvar_list = Some(Vec::new());
+                return None;
+            }
+            "MF.LVars.List.End" => {
+                return self.lvar_list.take().map(MobiFlightEvent::LVarList);
+            }
+            _ => {}
+        }
+
+        if let Some(list) = self.lvar_list.as_mut() {
+            list.push(response);
+            return None;
+        }
+
+        if let Some(client) = response
+            .strip_prefix("MF.Clients.Add.")
+            .and_then(|rest| rest.strip_suffix(".Finished"))
+        {
+            return Some(MobiFlightEvent::ClientAdded(client.to_string()));
+        }
+
+        Some(MobiFlightEvent::Response(response))
+    }
+
+    fn area_name(&self, area: &str) -> String {
+        format!("{}.{}", self.name, area)
+    }
+
+    fn write_command(&self, simconnect: &SimConnect, command: &str) -> SimConnectResult<()> {
+        if command.len() >= MESSAGE_SIZE {
+            return Err(SimConnectError::new("MobiFlight command is too long", None));
+        }
+
+        let mut buffer = command.as_bytes().to_vec();
+        buffer.resize(MESSAGE_SIZE, 0);
+        simconnect.set_client_data(
+            self.ids.command_area,
+            self.ids.command_define,
+            ClientDataSetFlag::Default,
+            0,
+            MESSAGE_SIZE as DWORD,
+            buffer.as_mut_ptr() as *mut _,
+        )
+    }
+}
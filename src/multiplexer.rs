@@ -0,0 +1,462 @@
+// Shares one data definition and request between every consumer of the same
+// simvar. The shared request runs at the fastest period and smallest epsilon
+// asked for, and each subscriber is throttled back to what it requested.
+// One-off subscribers joining a running request get a one-shot request of
+// their own on the shared definition.
+
+use super::bindings::*;
+use super::message::{c_string_from_bytes, Message};
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const STRING_SIZE: usize = 256;
+// Replies queued before a request was stopped can still be dispatched, so a
+// released id is only reused once this long has passed while dispatching.
+const ID_QUARANTINE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriberId(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimVarKey {
+    pub name: String,
+    pub units: String,
+    pub object_id: SIMCONNECT_OBJECT_ID,
+    pub is_string: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub subscriber: SubscriberId,
+    pub object_id: SIMCONNECT_OBJECT_ID,
+    pub value: SimVarValue,
+}
+
+struct Subscriber {
+    period: Period,
+    // `None` asks for every update, `Some` only for changes larger than it.
+    epsilon: Option<f32>,
+    last_value: Option<SimVarValue>,
+    last_sent: Option<Instant>,
+}
+
+struct Shared {
+    request_id: SIMCONNECT_DATA_REQUEST_ID,
+    // Serves `Period::Once` subscribers that joined a running request.
+    once_request_id: Option<SIMCONNECT_DATA_REQUEST_ID>,
+    period: Period,
+    epsilon: Option<f32>,
+    subscribers: HashMap<SubscriberId, Subscriber>,
+}
+
+pub struct Multiplexer {
+    next_request_id: SIMCONNECT_DATA_REQUEST_ID,
+    free_request_ids: Vec<SIMCONNECT_DATA_REQUEST_ID>,
+    // Released ids, stamped by the first dispatch that sees them.
+    quarantined: Vec<(SIMCONNECT_DATA_REQUEST_ID, Option<Instant>)>,
+    next_subscriber: u64,
+    shared: HashMap<SimVarKey, Shared>,
+    by_request: HashMap<SIMCONNECT_DATA_REQUEST_ID, SimVarKey>,
+    by_subscriber: HashMap<SubscriberId, SimVarKey>,
+}
+
+fn period_rank(period: Period) -> u8 {
+    match period {
+        Period::Never => 0,
+        Period::Once => 1,
+        Period::Second => 2,
+        Period::VisualFrame => 3,
+        Period::SimFrame => 4,
+    }
+}
+
+fn min_interval(period: Period) -> Option<Duration> {
+    match period {
+        Period::Second => Some(Duration::from_secs(1)),
+        _ => None,
+    }
+}
+
+impl Multiplexer {
+    // Definition and request ids are both taken from `first_id` upwards, so
+    // the caller should reserve that range for the multiplexer.
+    pub fn new(first_id: u32) -> Self {
+        Self {
+            next_request_id: first_id,
+            free_request_ids: Vec::new(),
+            quarantined: Vec::new(),
+            next_subscriber: 0,
+            shared: HashMap::new(),
+            by_request: HashMap::new(),
+            by_subscriber: HashMap::new(),
+        }
+    }
+
+    pub fn subscribe(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        key: SimVarKey,
+        period: Period,
+        epsilon: Option<f32>,
+    ) -> SimConnectResult<SubscriberId> {
+        if period == Period::Never {
+            return Err(SimConnectError::new(
+                "Cannot subscribe with Period::Never",
+                None,
+            ));
+        }
+
+        self.next_subscriber += 1;
+        let subscriber_id = SubscriberId(self.next_subscriber);
+        let subscriber = Subscriber {
+            period,
+            epsilon,
+            last_value: None,
+            last_sent: None,
+        };
+
+        if !self.shared.contains_key(&key) {
+            let request_id = self.allocate_request_id();
+            let mut shared = Shared {
+                request_id,
+                once_request_id: None,
+                period,
+                epsilon: epsilon.filter(|_| period != Period::Once),
+                subscribers: HashMap::new(),
+            };
+            shared.subscribers.insert(subscriber_id, subscriber);
+
+            if let Err(e) = Self::apply(simconnect, &key, &shared, true) {
+                self.release_request_id(request_id);
+                return Err(e);
+            }
+
+            self.by_request.insert(request_id, key.clone());
+            self.by_subscriber.insert(subscriber_id, key.clone());
+            self.shared.insert(key, shared);
+            return Ok(subscriber_id);
+        }
+
+        let shared = &self.shared[&key];
+        // A running request may not send again for a while, or at all with
+        // the changed flag, so a one-off subscriber gets its own request.
+        if period == Period::Once
+            && shared.period != Period::Once
+            && shared.once_request_id.is_none()
+        {
+            let (define_id, object_id) = (shared.request_id, key.object_id);
+            let request_id = self.allocate_request_id();
+            if let Err(e) = simconnect.request_data_on_sim_object(
+                request_id,
+                define_id,
+                object_id,
+                Period::Once,
+                DataRequestFlag::Default,
+                0,
+                0,
+                0,
+            ) {
+                self.release_request_id(request_id);
+                return Err(e);
+            }
+            self.by_request.insert(request_id, key.clone());
+            self.shared.get_mut(&key).unwrap().once_request_id = Some(request_id);
+        }
+
+        self.shared
+            .get_mut(&key)
+            .unwrap()
+            .subscribers
+            .insert(subscriber_id, subscriber);
+        self.by_subscriber.insert(subscriber_id, key.clone());
+        if let Err(e) = self.refresh(simconnect, &key) {
+            self.by_subscriber.remove(&subscriber_id);
+            if let Some(shared) = self.shared.get_mut(&key) {
+                shared.subscribers.remove(&subscriber_id);
+            }
+            return Err(e);
+        }
+        Ok(subscriber_id)
+    }
+
+    pub fn unsubscribe(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        subscriber: SubscriberId,
+    ) -> SimConnectResult<()> {
+        let key = self
+            .by_subscriber
+            .remove(&subscriber)
+            .ok_or_else(|| SimConnectError::new("Unknown subscriber", None))?;
+
+        let shared = match self.shared.get_mut(&key) {
+            Some(shared) => shared,
+            None => return Ok(()),
+        };
+        shared.subscribers.remove(&subscriber);
+
+        if !shared.subscribers.is_empty() {
+            return self.refresh(simconnect, &key);
+        }
+
+        let request_id = shared.request_id;
+        self.remove(&key);
+        simconnect.request_data_on_sim_object(
+            request_id,
+            request_id,
+            key.object_id,
+            Period::Never,
+            DataRequestFlag::Default,
+            0,
+            0,
+            0,
+        )?;
+        simconnect.clear_data_definition(request_id)
+    }
+
+    pub fn subscribers(&self, key: &SimVarKey) -> usize {
+        self.shared.get(key).map_or(0, |s| s.subscribers.len())
+    }
+
+    pub fn key(&self, subscriber: SubscriberId) -> Option<&SimVarKey> {
+        self.by_subscriber.get(&subscriber)
+    }
+
+    pub fn handle_message(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        message: &Message,
+        now: Instant,
+    ) -> SimConnectResult<Vec<Update>> {
+        self.expire_quarantine(now);
+        let data = match message {
+            Message::SimObjectData(data) => data,
+            _ => return Ok(Vec::new()),
+        };
+        let key = match self.by_request.get(&data.request_id) {
+            Some(key) => key.clone(),
+            None => return Ok(Vec::new()),
+        };
+
+        let shared = self.shared.get_mut(&key).unwrap();
+        let once_only = shared.once_request_id == Some(data.request_id);
+        if once_only {
+            shared.once_request_id = None;
+            self.by_request.remove(&data.request_id);
+            self.release_request_id(data.request_id);
+        }
+
+        let value = match decode(&key, &data.data) {
+            Some(value) => value,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut updates = Vec::new();
+        let mut finished = Vec::new();
+        let shared = self.shared.get_mut(&key).unwrap();
+        for (id, subscriber) in shared.subscribers.iter_mut() {
+            if once_only && subscriber.period != Period::Once {
+                continue;
+            }
+            if !wants(subscriber, &value, now) {
+                continue;
+            }
+
+            subscriber.last_value = Some(value.clone());
+            subscriber.last_sent = Some(now);
+            updates.push(Update {
+                subscriber: *id,
+                object_id: data.object_id,
+                value: value.clone(),
+            });
+
+            if subscriber.period == Period::Once {
+                finished.push(*id);
+            }
+        }
+
+        for id in finished {
+            self.unsubscribe(simconnect, id)?;
+        }
+
+        Ok(updates)
+    }
+
+    fn allocate_request_id(&mut self) -> SIMCONNECT_DATA_REQUEST_ID {
+        self.free_request_ids.pop().unwrap_or_else(|| {
+            let id = self.next_request_id;
+            self.next_request_id += 1;
+            id
+        })
+    }
+
+    fn release_request_id(&mut self, request_id: SIMCONNECT_DATA_REQUEST_ID) {
+        self.quarantined.push((request_id, None));
+    }
+
+    fn expire_quarantine(&mut self, now: Instant) {
+        let free = &mut self.free_request_ids;
+        self.quarantined
+            .retain_mut(|(request_id, since)| match since {
+                None => {
+                    *since = Some(now);
+                    true
+                }
+                Some(since) if now.duration_since(*since) >= ID_QUARANTINE => {
+                    free.push(*request_id);
+                    false
+                }
+                Some(_) => true,
+            });
+    }
+
+    // Forgets the key and its subscribers, the request itself is left alone.
+    fn remove(&mut self, key: &SimVarKey) {
+        if let Some(shared) = self.shared.remove(key) {
+            for id in shared.subscribers.keys() {
+                self.by_subscriber.remove(id);
+            }
+            for request_id in [Some(shared.request_id), shared.once_request_id]
+                .into_iter()
+                .flatten()
+            {
+                self.by_request.remove(&request_id);
+                self.release_request_id(request_id);
+            }
+        }
+    }
+
+    fn refresh(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        key: &SimVarKey,
+    ) -> SimConnectResult<()> {
+        let shared = match self.shared.get_mut(key) {
+            Some(shared) => shared,
+            None => return Ok(()),
+        };
+
+        // One-off subscribers are served by a one-shot request and leave the
+        // running one as it is.
+        let running = || {
+            shared
+                .subscribers
+                .values()
+                .filter(|s| s.period != Period::Once)
+        };
+        let period = match running().map(|s| s.period).max_by_key(|p| period_rank(*p)) {
+            Some(period) => period,
+            // Once subscribers left on their own are served by the next
+            // update of the request, or by the pending one-shot request.
+            None => return Ok(()),
+        };
+        let epsilon = running()
+            .map(|s| s.epsilon)
+            .reduce(|a, b| match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                _ => None,
+            })
+            .flatten();
+
+        let redefine = epsilon != shared.epsilon;
+        if period == shared.period && !redefine {
+            return Ok(());
+        }
+
+        if redefine {
+            // SimConnect keeps sending with the old definition until the
+            // request is stopped.
+            simconnect.request_data_on_sim_object(
+                shared.request_id,
+                shared.request_id,
+                key.object_id,
+                Period::Never,
+                DataRequestFlag::Default,
+                0,
+                0,
+                0,
+            )?;
+        }
+
+        shared.period = period;
+        shared.epsilon = epsilon;
+        let result = Self::apply(simconnect, key, shared, redefine);
+        if result.is_err() && redefine {
+            // The old definition is gone, nothing can be served for the key.
+            self.remove(key);
+        }
+        result
+    }
+
+    fn apply(
+        simconnect: &impl SimConnectApi,
+        key: &SimVarKey,
+        shared: &Shared,
+        define: bool,
+    ) -> SimConnectResult<()> {
+        let define_id = shared.request_id;
+        if define {
+            let _ = simconnect.clear_data_definition(define_id);
+            let (units, datum_type) = match key.is_string {
+                true => ("", DataType::String256),
+                false => (key.units.as_str(), DataType::Float64),
+            };
+            simconnect.add_to_data_definition(
+                define_id,
+                &key.name,
+                units,
+                datum_type,
+                shared.epsilon.unwrap_or(0.0),
+                SIMCONNECT_UNUSED,
+            )?;
+        }
+
+        let flags = match shared.epsilon {
+            Some(_) => DataRequestFlag::Changed,
+            None => DataRequestFlag::Default,
+        };
+        simconnect.request_data_on_sim_object(
+            shared.request_id,
+            define_id,
+            key.object_id,
+            shared.period,
+            flags,
+            0,
+            0,
+            0,
+        )
+    }
+}
+
+fn decode(key: &SimVarKey, data: &[u8]) -> Option<SimVarValue> {
+    if key.is_string {
+        return Some(SimVarValue::String(c_string_from_bytes(
+            data.get(..STRING_SIZE.min(data.len()))?,
+        )));
+    }
+
+    let b = data.get(0..8)?;
+    let mut raw = [0; 8];
+    raw.copy_from_slice(b);
+    Some(SimVarValue::Number(f64::from_le_bytes(raw)))
+}
+
+fn wants(subscriber: &Subscriber, value: &SimVarValue, now: Instant) -> bool {
+    if let (Some(interval), Some(last_sent)) =
+        (min_interval(subscriber.period), subscriber.last_sent)
+    {
+        if now.duration_since(last_sent) < interval {
+            return false;
+        }
+    }
+
+    match (subscriber.epsilon, &subscriber.last_value, value) {
+        (Some(epsilon), Some(SimVarValue::Number(last)), SimVarValue::Number(value)) => {
+            (value - last).abs() > epsilon as f64
+        }
+        (Some(_), Some(last), value) => last != value,
+        _ => true,
+    }
+}
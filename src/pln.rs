@@ -0,0 +1,625 @@
+// Flight plans in the `.PLN` format read by `flight_plan_load` and
+// `ai_set_aircraft_flight_plan`, an AceXML document holding one
+// `FlightPlan.FlightPlan` element. Elements the model does not know are kept
+// and written back, so plans saved by the sim survive a round trip.
+
+#[cfg(feature = "sdk")]
+use super::simconnect::SimConnect;
+use super::track::xml_escape;
+use super::types::*;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "sdk")]
+use std::path::PathBuf;
+use std::str::FromStr;
+#[cfg(feature = "sdk")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    fn from_node(node: roxmltree::Node) -> Self {
+        Self {
+            name: node.tag_name().name().to_string(),
+            attributes: node
+                .attributes()
+                .map(|a| (a.name().to_string(), a.value().to_string()))
+                .collect(),
+            text: node
+                .children()
+                .filter(|n| n.is_text())
+                .filter_map(|n| n.text())
+                .collect::<String>()
+                .trim()
+                .to_string(),
+            children: node
+                .children()
+                .filter(|n| n.is_element())
+                .map(Element::from_node)
+                .collect(),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        write!(f, "{:indent$}<{}", "", self.name, indent = indent)?;
+        for (name, value) in &self.attributes {
+            write!(f, " {}=\"{}\"", name, xml_escape(value))?;
+        }
+        if self.children.is_empty() {
+            // Quotes stay literal in text, as in the positions the sim writes.
+            let text = self
+                .text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            return writeln!(f, ">{}</{}>", text, self.name);
+        }
+        writeln!(f, ">")?;
+        for child in &self.children {
+            child.write(f, indent + 4)?;
+        }
+        writeln!(f, "{:indent$}</{}>", "", self.name, indent = indent)
+    }
+}
+
+fn leaf(name: &str, text: &str) -> Element {
+    Element {
+        name: name.to_string(),
+        attributes: Vec::new(),
+        text: text.to_string(),
+        children: Vec::new(),
+    }
+}
+
+fn child_text<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+    element
+        .children
+        .iter()
+        .find(|c| c.name == name)
+        .map(|c| c.text.as_str())
+}
+
+fn invalid(what: &str) -> SimConnectError {
+    SimConnectError::new(&format!("Invalid flight plan: {}", what), None)
+}
+
+// Latitude, longitude and altitude, written `N47° 27' 28.20",E8° 32'
+// 54.90",+001416.00` in plans.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+}
+
+impl Position {
+    pub fn new(latitude: f64, longitude: f64, altitude_ft: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude_ft,
+        }
+    }
+}
+
+pub(crate) fn parse_angle(text: &str, hemispheres: [char; 2]) -> Option<f64> {
+    let text = text.trim();
+    let hemisphere = text.chars().next()?;
+    let sign = match hemisphere {
+        h if h == hemispheres[0] => 1.0,
+        h if h == hemispheres[1] => -1.0,
+        _ => return None,
+    };
+
+    let (degrees, rest) = text[1..].split_once('°')?;
+    let (minutes, rest) = rest.split_once('\'').unwrap_or((rest, ""));
+    let seconds = rest.trim().trim_end_matches('"');
+    let seconds: f64 = match seconds.is_empty() {
+        true => 0.0,
+        false => seconds.parse().ok()?,
+    };
+    let minutes: f64 = match minutes.trim().is_empty() {
+        true => 0.0,
+        false => minutes.trim().parse().ok()?,
+    };
+    let degrees: f64 = degrees.trim().parse().ok()?;
+    Some(sign * (degrees + minutes / 60.0 + seconds / 3600.0))
+}
+
+pub(crate) fn format_angle(value: f64, hemispheres: [char; 2]) -> String {
+    let hemisphere = match value < 0.0 {
+        true => hemispheres[1],
+        false => hemispheres[0],
+    };
+    // In hundredths of a second, so rounding carries into minutes and degrees.
+    let total = (value.abs() * 360_000.0).round() as u64;
+    format!(
+        "{}{}° {}' {}.{:02}\"",
+        hemisphere,
+        total / 360_000,
+        total / 6000 % 60,
+        total / 100 % 60,
+        total % 100
+    )
+}
+
+impl FromStr for Position {
+    type Err = SimConnectError;
+
+    fn from_str(text: &str) -> SimConnectResult<Self> {
+        let parts: Vec<&str> = text.split(',').collect();
+        let error = || invalid(&format!("bad position {:?}", text));
+        if parts.len() != 3 {
+            return Err(error());
+        }
+
+        Ok(Self {
+            latitude: parse_angle(parts[0], ['N', 'S']).ok_or_else(error)?,
+            longitude: parse_angle(parts[1], ['E', 'W']).ok_or_else(error)?,
+            altitude_ft: parts[2].trim().parse().map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = match self.altitude_ft < 0.0 {
+            true => '-',
+            false => '+',
+        };
+        write!(
+            f,
+            "{},{},{}{:09.2}",
+            format_angle(self.latitude, ['N', 'S']),
+            format_angle(self.longitude, ['E', 'W']),
+            sign,
+            self.altitude_ft.abs()
+        )
+    }
+}
+
+macro_rules! plan_enum {
+    ($name: ident, $what: expr, { $($variant: ident => $text: expr),* $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl FromStr for $name {
+            type Err = SimConnectError;
+
+            fn from_str(text: &str) -> SimConnectResult<Self> {
+                match text.trim() {
+                    $(t if t.eq_ignore_ascii_case($text) => Ok($name::$variant),)*
+                    t => Err(invalid(&format!("unknown {} {:?}", $what, t))),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(match self {
+                    $($name::$variant => $text),*
+                })
+            }
+        }
+    };
+}
+
+plan_enum!(PlanType, "plan type", {
+    Vfr => "VFR",
+    Ifr => "IFR",
+});
+
+plan_enum!(RouteType, "route type", {
+    Direct => "Direct",
+    Vor => "VOR",
+    LowAlt => "LowAlt",
+    HighAlt => "HighAlt",
+});
+
+plan_enum!(WaypointType, "waypoint type", {
+    Airport => "Airport",
+    Intersection => "Intersection",
+    Vor => "VOR",
+    Ndb => "NDB",
+    User => "User",
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Icao {
+    pub region: Option<String>,
+    pub ident: String,
+    // The airport a terminal waypoint belongs to.
+    pub airport: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub id: String,
+    pub waypoint_type: WaypointType,
+    pub position: Position,
+    // The airway flown to reach this waypoint.
+    pub airway: Option<String>,
+    pub icao: Option<Icao>,
+    pub other: Vec<Element>,
+}
+
+impl Waypoint {
+    pub fn new(id: &str, waypoint_type: WaypointType, position: Position) -> Self {
+        Self {
+            id: id.to_string(),
+            waypoint_type,
+            position,
+            airway: None,
+            icao: match waypoint_type {
+                WaypointType::User => None,
+                _ => Some(Icao {
+                    ident: id.to_string(),
+                    ..Icao::default()
+                }),
+            },
+            other: Vec::new(),
+        }
+    }
+
+    pub fn airport(ident: &str, position: Position) -> Self {
+        Self::new(ident, WaypointType::Airport, position)
+    }
+
+    pub fn airway(mut self, airway: &str) -> Self {
+        self.airway = Some(airway.to_string());
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> Self {
+        if let Some(icao) = self.icao.as_mut() {
+            icao.region = Some(region.to_string());
+        }
+        self
+    }
+
+    fn from_element(element: &Element) -> SimConnectResult<Self> {
+        let id = element
+            .attributes
+            .iter()
+            .find(|(name, _)| name == "id")
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| invalid("waypoint without id"))?;
+
+        let mut waypoint = Self {
+            id,
+            waypoint_type: WaypointType::User,
+            position: Position::new(0.0, 0.0, 0.0),
+            airway: None,
+            icao: None,
+            other: Vec::new(),
+        };
+        let mut position = None;
+        for child in &element.children {
+            match child.name.as_str() {
+                "ATCWaypointType" => waypoint.waypoint_type = child.text.parse()?,
+                "WorldPosition" => position = Some(child.text.parse()?),
+                "ATCAirway" => waypoint.airway = Some(child.text.clone()),
+                "ICAO" => {
+                    waypoint.icao = Some(Icao {
+                        region: child_text(child, "ICAORegion").map(str::to_string),
+                        ident: child_text(child, "ICAOIdent")
+                            .unwrap_or_default()
+                            .to_string(),
+                        airport: child_text(child, "ICAOAirport").map(str::to_string),
+                    })
+                }
+                _ => waypoint.other.push(child.clone()),
+            }
+        }
+        waypoint.position = position
+            .ok_or_else(|| invalid(&format!("waypoint {} without position", waypoint.id)))?;
+        Ok(waypoint)
+    }
+
+    fn to_element(&self) -> Element {
+        let mut children = vec![
+            leaf("ATCWaypointType", &self.waypoint_type.to_string()),
+            leaf("WorldPosition", &self.position.to_string()),
+        ];
+        if let Some(airway) = &self.airway {
+            children.push(leaf("ATCAirway", airway));
+        }
+        if let Some(icao) = &self.icao {
+            let mut fields = Vec::new();
+            if let Some(region) = &icao.region {
+                fields.push(leaf("ICAORegion", region));
+            }
+            fields.push(leaf("ICAOIdent", &icao.ident));
+            if let Some(airport) = &icao.airport {
+                fields.push(leaf("ICAOAirport", airport));
+            }
+            children.push(Element {
+                children: fields,
+                ..leaf("ICAO", "")
+            });
+        }
+        children.extend(self.other.iter().cloned());
+
+        Element {
+            name: "ATCWaypoint".to_string(),
+            attributes: vec![("id".to_string(), self.id.clone())],
+            text: String::new(),
+            children,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightPlan {
+    pub title: String,
+    pub description: String,
+    pub plan_type: PlanType,
+    pub route_type: RouteType,
+    pub cruising_altitude_ft: f64,
+    pub departure_id: String,
+    pub departure_position: Position,
+    pub departure_name: Option<String>,
+    // The runway or parking spot the flight starts from.
+    pub departure_spot: Option<String>,
+    pub destination_id: String,
+    pub destination_position: Position,
+    pub destination_name: Option<String>,
+    // In flying order, including the departure and destination airports.
+    pub waypoints: Vec<Waypoint>,
+    pub other: Vec<Element>,
+}
+
+impl FlightPlan {
+    pub fn new(departure: Waypoint, destination: Waypoint) -> Self {
+        Self {
+            title: format!("{} to {}", departure.id, destination.id),
+            description: format!("{}, {}", departure.id, destination.id),
+            plan_type: PlanType::Ifr,
+            route_type: RouteType::HighAlt,
+            cruising_altitude_ft: 10000.0,
+            departure_id: departure.id.clone(),
+            departure_position: departure.position,
+            departure_name: None,
+            departure_spot: None,
+            destination_id: destination.id.clone(),
+            destination_position: destination.position,
+            destination_name: None,
+            waypoints: vec![departure, destination],
+            other: Vec::new(),
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn plan_type(mut self, plan_type: PlanType) -> Self {
+        self.plan_type = plan_type;
+        self
+    }
+
+    pub fn route_type(mut self, route_type: RouteType) -> Self {
+        self.route_type = route_type;
+        self
+    }
+
+    pub fn cruising_altitude(mut self, altitude_ft: f64) -> Self {
+        self.cruising_altitude_ft = altitude_ft;
+        self
+    }
+
+    // Adds an en route waypoint in front of the destination.
+    pub fn via(mut self, waypoint: Waypoint) -> Self {
+        let index = self.waypoints.len().saturating_sub(1);
+        self.waypoints.insert(index, waypoint);
+        self
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+        })?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> SimConnectResult<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).map_err(|e| {
+            SimConnectError::new(&format!("Failed to write {}: {}", path.display(), e), None)
+        })
+    }
+
+    pub fn parse(text: &str) -> SimConnectResult<Self> {
+        // Plans written by some tools start with a byte order mark.
+        let text = text.trim_start_matches('\u{feff}');
+        let document = roxmltree::Document::parse(text).map_err(|e| invalid(&e.to_string()))?;
+        let node = document
+            .descendants()
+            .find(|n| n.has_tag_name("FlightPlan.FlightPlan"))
+            .ok_or_else(|| invalid("no FlightPlan.FlightPlan element"))?;
+        let element = Element::from_node(node);
+
+        let required = |name: &str| {
+            child_text(&element, name).ok_or_else(|| invalid(&format!("missing {}", name)))
+        };
+        let mut plan = Self {
+            title: child_text(&element, "Title")
+                .unwrap_or_default()
+                .to_string(),
+            description: child_text(&element, "Descr")
+                .unwrap_or_default()
+                .to_string(),
+            plan_type: required("FPType")?.parse()?,
+            route_type: match child_text(&element, "RouteType") {
+                Some(route_type) => route_type.parse()?,
+                None => RouteType::Direct,
+            },
+            cruising_altitude_ft: match child_text(&element, "CruisingAlt") {
+                Some(altitude) => altitude
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("bad CruisingAlt"))?,
+                None => 0.0,
+            },
+            departure_id: required("DepartureID")?.to_string(),
+            departure_position: required("DepartureLLA")?.parse()?,
+            departure_name: child_text(&element, "DepartureName").map(str::to_string),
+            departure_spot: child_text(&element, "DeparturePosition").map(str::to_string),
+            destination_id: required("DestinationID")?.to_string(),
+            destination_position: required("DestinationLLA")?.parse()?,
+            destination_name: child_text(&element, "DestinationName").map(str::to_string),
+            waypoints: Vec::new(),
+            other: Vec::new(),
+        };
+
+        for child in &element.children {
+            match child.name.as_str() {
+                "ATCWaypoint" => plan.waypoints.push(Waypoint::from_element(child)?),
+                "Title" | "Descr" | "FPType" | "RouteType" | "CruisingAlt" | "DepartureID"
+                | "DepartureLLA" | "DepartureName" | "DeparturePosition" | "DestinationID"
+                | "DestinationLLA" | "DestinationName" => {}
+                _ => plan.other.push(child.clone()),
+            }
+        }
+        Ok(plan)
+    }
+
+    fn to_element(&self) -> Element {
+        let mut children = vec![
+            leaf("Title", &self.title),
+            leaf("FPType", &self.plan_type.to_string()),
+            leaf("RouteType", &self.route_type.to_string()),
+            leaf("CruisingAlt", &format!("{:.3}", self.cruising_altitude_ft)),
+            leaf("DepartureID", &self.departure_id),
+            leaf("DepartureLLA", &self.departure_position.to_string()),
+            leaf("DestinationID", &self.destination_id),
+            leaf("DestinationLLA", &self.destination_position.to_string()),
+            leaf("Descr", &self.description),
+        ];
+        if let Some(spot) = &self.departure_spot {
+            children.push(leaf("DeparturePosition", spot));
+        }
+        if let Some(name) = &self.departure_name {
+            children.push(leaf("DepartureName", name));
+        }
+        if let Some(name) = &self.destination_name {
+            children.push(leaf("DestinationName", name));
+        }
+        children.extend(self.other.iter().cloned());
+        children.extend(self.waypoints.iter().map(Waypoint::to_element));
+
+        Element {
+            children,
+            ..leaf("FlightPlan.FlightPlan", "")
+        }
+    }
+
+    // Writes the plan to a new file in the temporary directory and loads it.
+    // The file is left in place as the sim reads it after the call returns.
+    #[cfg(feature = "sdk")]
+    pub fn load_into(&self, simconnect: &SimConnect) -> SimConnectResult<PathBuf> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "simply-simconnect-{}-{}.pln",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.save(&path)?;
+        // SimConnect adds the extension itself.
+        simconnect.flight_plan_load(&path.with_extension("").to_string_lossy())?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for FlightPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(f, "<SimBase.Document Type=\"AceXML\" version=\"1,0\">")?;
+        writeln!(f, "    <Descr>AceXML Document</Descr>")?;
+        self.to_element().write(f, 4)?;
+        writeln!(f, "</SimBase.Document>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN: &str = "\u{feff}<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<SimBase.Document Type=\"AceXML\" version=\"1,0\">
+    <Descr>AceXML Document</Descr>
+    <FlightPlan.FlightPlan>
+        <Title>KSEA to KPDX</Title>
+        <FPType>IFR</FPType>
+        <RouteType>LowAlt</RouteType>
+        <CruisingAlt>9000</CruisingAlt>
+        <DepartureID>KSEA</DepartureID>
+        <DepartureLLA>N47° 26' 58.00\",W122° 18' 34.00\",+000433.00</DepartureLLA>
+        <DestinationID>KPDX</DestinationID>
+        <DestinationLLA>N45° 35' 19.00\",W122° 35' 50.00\",+000031.00</DestinationLLA>
+        <Descr>KSEA, KPDX &amp; back</Descr>
+        <DeparturePosition>16L</DeparturePosition>
+        <AppVersion>
+            <AppVersionMajor>11</AppVersionMajor>
+            <AppVersionBuild build=\"282174\"/>
+        </AppVersion>
+        <ATCWaypoint id=\"KSEA\">
+            <ATCWaypointType>Airport</ATCWaypointType>
+            <WorldPosition>N47° 26' 58.00\",W122° 18' 34.00\",+000433.00</WorldPosition>
+            <ICAO>
+                <ICAOIdent>KSEA</ICAOIdent>
+            </ICAO>
+        </ATCWaypoint>
+        <ATCWaypoint id=\"OLM\">
+            <ATCWaypointType>VOR</ATCWaypointType>
+            <WorldPosition>N46° 58' 16.00\",W122° 54' 6.00\",+009000.00</WorldPosition>
+            <ATCAirway>V165</ATCAirway>
+            <SpeedMaxFP>250</SpeedMaxFP>
+            <ICAO>
+                <ICAORegion>K1</ICAORegion>
+                <ICAOIdent>OLM</ICAOIdent>
+            </ICAO>
+        </ATCWaypoint>
+        <ATCWaypoint id=\"KPDX\">
+            <ATCWaypointType>Airport</ATCWaypointType>
+            <WorldPosition>N45° 35' 19.00\",W122° 35' 50.00\",+000031.00</WorldPosition>
+            <ICAO>
+                <ICAOIdent>KPDX</ICAOIdent>
+            </ICAO>
+        </ATCWaypoint>
+    </FlightPlan.FlightPlan>
+</SimBase.Document>
+";
+
+    #[test]
+    fn keeps_unknown_elements_through_a_round_trip() {
+        let plan = FlightPlan::parse(PLAN).unwrap();
+        assert_eq!(plan.description, "KSEA, KPDX & back");
+        assert_eq!(plan.departure_spot.as_deref(), Some("16L"));
+        assert_eq!(plan.other.len(), 1);
+        assert_eq!(plan.other[0].name, "AppVersion");
+        assert_eq!(
+            plan.other[0].children[1].attributes,
+            vec![("build".to_string(), "282174".to_string())]
+        );
+        assert_eq!(plan.waypoints.len(), 3);
+        assert_eq!(plan.waypoints[1].airway.as_deref(), Some("V165"));
+        assert_eq!(plan.waypoints[1].other[0].name, "SpeedMaxFP");
+
+        let written = plan.to_string();
+        assert!(written.contains("<SpeedMaxFP>250</SpeedMaxFP>"));
+        assert_eq!(FlightPlan::parse(&written).unwrap(), plan);
+    }
+}
@@ -0,0 +1,639 @@
+// Plays a recorded session back through `SimConnectApi`, standing in for a
+// simulator. Recorded messages are matched to requests by request and event
+// id, so the session should be replayed into the program that recorded it, or
+// one that registers the same ids. Messages nobody asked for are dropped,
+// like SimConnect would never have sent them, and calls that would change the
+// simulation are accepted and ignored. The recorded open reply comes first
+// and `Quit` follows the last recorded message.
+
+use super::bindings::*;
+use super::message::{FacilityList, Message};
+use super::session::{Record, SessionMetadata, SessionReader};
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Seek};
+use std::os::raw;
+use std::time::{Duration, Instant};
+
+struct Playback<R: Read + Seek> {
+    reader: SessionReader<R>,
+    server_info: Option<ServerInfo>,
+    next_block: usize,
+    pending: VecDeque<Record>,
+    outbox: VecDeque<Message>,
+    // Playback position `anchor.1` was reached at `anchor.0`.
+    anchor: (Instant, Duration),
+    // Recording time of the last message delivered.
+    delivered: Duration,
+    speed: f64,
+    paused: bool,
+    finished: bool,
+    requests: HashMap<SIMCONNECT_DATA_REQUEST_ID, Period>,
+    // By-type requests, true once the first object of a sweep came through.
+    sweeps: HashMap<SIMCONNECT_DATA_REQUEST_ID, bool>,
+    events: HashSet<SIMCONNECT_CLIENT_EVENT_ID>,
+    state_requests: HashSet<SIMCONNECT_DATA_REQUEST_ID>,
+    states: HashMap<SIMCONNECT_DATA_REQUEST_ID, Message>,
+    facility_requests: HashSet<SIMCONNECT_DATA_REQUEST_ID>,
+}
+
+impl<R: Read + Seek> Playback<R> {
+    fn position(&self, now: Instant) -> Duration {
+        if self.paused {
+            return self.anchor.1;
+        }
+        if self.speed.is_infinite() {
+            return Duration::MAX;
+        }
+        let elapsed = now.saturating_duration_since(self.anchor.0);
+        self.anchor.1 + elapsed.mul_f64(self.speed)
+    }
+
+    // Playing as fast as messages are read stands at the last one delivered.
+    fn settled_position(&self, now: Instant) -> Duration {
+        match self.position(now) {
+            Duration::MAX => self.delivered,
+            position => position,
+        }
+    }
+
+    fn fill(&mut self) -> SimConnectResult<bool> {
+        while self.pending.is_empty() && self.next_block < self.reader.blocks().len() {
+            self.pending
+                .extend(self.reader.read_block(self.next_block)?);
+            self.next_block += 1;
+        }
+        Ok(!self.pending.is_empty())
+    }
+
+    // Next message due at `position`, `None` before it is due.
+    fn next(&mut self, position: Option<Duration>) -> SimConnectResult<Option<Message>> {
+        if let Some(message) = self.outbox.pop_front() {
+            return Ok(Some(message));
+        }
+
+        loop {
+            if !self.fill()? {
+                if self.finished {
+                    return Ok(None);
+                }
+                self.finished = true;
+                return Ok(Some(Message::Quit));
+            }
+            if position.is_some_and(|p| self.pending[0].time > p) {
+                return Ok(None);
+            }
+
+            let record = self.pending.pop_front().unwrap();
+            if let Some(message) = self.accept(&record)? {
+                self.delivered = record.time;
+                if position.is_none() {
+                    self.anchor = (Instant::now(), record.time);
+                }
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    fn accept(&mut self, record: &Record) -> SimConnectResult<Option<Message>> {
+        let message = match record.message() {
+            Some(message) => message?,
+            None => return Ok(None),
+        };
+
+        let deliver = match &message {
+            Message::Quit => {
+                self.finished = true;
+                true
+            }
+            Message::SimObjectData(data) => match self.requests.get(&data.request_id) {
+                Some(Period::Once) => {
+                    self.requests.remove(&data.request_id);
+                    true
+                }
+                Some(_) => true,
+                None => false,
+            },
+            Message::SimObjectDataByType(data) => match self.sweeps.get_mut(&data.request_id) {
+                // Joining a recorded sweep halfway would deliver half of it.
+                Some(started) if *started || data.entry_number <= 1 => {
+                    *started = true;
+                    if data.entry_number >= data.out_of {
+                        self.sweeps.remove(&data.request_id);
+                    }
+                    true
+                }
+                _ => false,
+            },
+            Message::SystemState(state) => {
+                self.states.insert(state.request_id, message.clone());
+                self.state_requests.remove(&state.request_id)
+            }
+            Message::AirportList(FacilityList {
+                request_id,
+                entry_number,
+                out_of,
+                ..
+            })
+            | Message::WaypointList(FacilityList {
+                request_id,
+                entry_number,
+                out_of,
+                ..
+            })
+            | Message::NdbList(FacilityList {
+                request_id,
+                entry_number,
+                out_of,
+                ..
+            })
+            | Message::VorList(FacilityList {
+                request_id,
+                entry_number,
+                out_of,
+                ..
+            }) => match entry_number + 1 >= *out_of {
+                true => self.facility_requests.remove(request_id),
+                false => self.facility_requests.contains(request_id),
+            },
+            Message::Event(event)
+            | Message::EventObjectAddRemove { event, .. }
+            | Message::EventFilename { event, .. }
+            | Message::EventFrame { event, .. } => self.events.contains(&event.event_id),
+            _ => false,
+        };
+
+        Ok(deliver.then_some(message))
+    }
+
+    fn seek(&mut self, time: Duration, now: Instant) -> SimConnectResult<()> {
+        let from = time.as_micros() as u64;
+        self.next_block = self.reader.blocks().partition_point(|b| b.last_time < from);
+        self.pending.clear();
+        self.outbox.retain(|m| matches!(m, Message::Open(_)));
+        self.states.clear();
+        for started in self.sweeps.values_mut() {
+            *started = false;
+        }
+        self.finished = false;
+
+        self.fill()?;
+        while self.pending.front().is_some_and(|r| r.time < time) {
+            self.pending.pop_front();
+        }
+        self.anchor = (now, time);
+        self.delivered = time;
+        Ok(())
+    }
+}
+
+pub struct Replay<R: Read + Seek> {
+    playback: RefCell<Playback<R>>,
+}
+
+impl<R: Read + Seek> Replay<R> {
+    // Starts paused at the beginning of the session, call `resume` or `step`
+    // to play it.
+    pub fn open(input: R) -> SimConnectResult<Self> {
+        let mut reader = SessionReader::open(input)?;
+
+        let mut open = None;
+        for index in 0..reader.blocks().len() {
+            open = reader
+                .read_block(index)?
+                .iter()
+                .filter_map(|r| r.message())
+                .find_map(|m| match m {
+                    Ok(Message::Open(open)) => Some(open),
+                    _ => None,
+                });
+            if open.is_some() {
+                break;
+            }
+        }
+
+        let mut playback = Playback {
+            reader,
+            server_info: open.as_ref().map(|o| o.server_info()),
+            next_block: 0,
+            pending: VecDeque::new(),
+            outbox: VecDeque::new(),
+            anchor: (Instant::now(), Duration::ZERO),
+            delivered: Duration::ZERO,
+            speed: 1.0,
+            paused: true,
+            finished: false,
+            requests: HashMap::new(),
+            sweeps: HashMap::new(),
+            events: HashSet::new(),
+            state_requests: HashSet::new(),
+            states: HashMap::new(),
+            facility_requests: HashSet::new(),
+        };
+        playback.outbox.extend(open.map(Message::Open));
+
+        Ok(Self {
+            playback: RefCell::new(playback),
+        })
+    }
+
+    pub fn metadata(&self) -> SessionMetadata {
+        self.playback.borrow().reader.metadata().clone()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.playback.borrow().reader.duration()
+    }
+
+    pub fn position(&self) -> Duration {
+        let playback = self.playback.borrow();
+        playback
+            .settled_position(Instant::now())
+            .min(playback.reader.duration())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.playback.borrow().paused
+    }
+
+    // True once `Quit` has been delivered.
+    pub fn is_finished(&self) -> bool {
+        let playback = self.playback.borrow();
+        playback.finished && playback.outbox.is_empty()
+    }
+
+    // 1.0 plays in real time, 10.0 ten times as fast and `f64::INFINITY` as
+    // fast as messages are read.
+    pub fn set_speed(&self, speed: f64) -> SimConnectResult<()> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(SimConnectError::new(
+                &format!("Invalid replay speed {}", speed),
+                None,
+            ));
+        }
+        let mut playback = self.playback.borrow_mut();
+        let now = Instant::now();
+        playback.anchor = (now, playback.settled_position(now));
+        playback.speed = speed;
+        Ok(())
+    }
+
+    pub fn pause(&self) {
+        let mut playback = self.playback.borrow_mut();
+        if !playback.paused {
+            let now = Instant::now();
+            let position = playback
+                .settled_position(now)
+                .min(playback.reader.duration());
+            playback.anchor = (now, position);
+            playback.paused = true;
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut playback = self.playback.borrow_mut();
+        if playback.paused {
+            playback.anchor.0 = Instant::now();
+            playback.paused = false;
+        }
+    }
+
+    // Pauses and moves on to the next message that would be delivered,
+    // returning it. The clock then stands at the time it was recorded.
+    pub fn step(&self) -> SimConnectResult<Option<Message>> {
+        self.pause();
+        self.playback.borrow_mut().next(None)
+    }
+
+    // Requests and subscriptions stay in place, cached system states are
+    // dropped as they may not hold at `time`.
+    pub fn seek(&self, time: Duration) -> SimConnectResult<()> {
+        self.playback.borrow_mut().seek(time, Instant::now())
+    }
+}
+
+impl<R: Read + Seek> SimConnectApi for Replay<R> {
+    fn server_info(&self) -> Option<ServerInfo> {
+        self.playback.borrow().server_info.clone()
+    }
+
+    fn get_next_message(&self) -> SimConnectResult<Option<Message>> {
+        let mut playback = self.playback.borrow_mut();
+        let position = playback.position(Instant::now());
+        playback.next(Some(position))
+    }
+
+    // Nothing is sent, so no recorded exception can refer to a replayed
+    // call.
+    fn get_last_sent_packet_id(&self) -> SimConnectResult<DWORD> {
+        Ok(0)
+    }
+
+    // Recorded objects keep the ids the sim gave them, new ones are never
+    // created.
+    fn ai_create_non_atc_aircraft(
+        &self,
+        _container_title: &str,
+        _tail_number: &str,
+        _init_pos: InitPosition,
+        _request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn ai_release_control(
+        &self,
+        _object_id: SIMCONNECT_OBJECT_ID,
+        _request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn ai_remove_object(
+        &self,
+        _object_id: SIMCONNECT_OBJECT_ID,
+        _request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn add_to_data_definition(
+        &self,
+        _define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        _datum_name: &str,
+        _units_name: &str,
+        _datum_type: DataType,
+        _epsilon: f32,
+        _datum_id: DWORD,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn clear_data_definition(
+        &self,
+        _define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn map_client_event_to_sim_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        _event_name: &str,
+    ) -> SimConnectResult<()> {
+        self.playback.borrow_mut().events.insert(event_id);
+        Ok(())
+    }
+
+    fn request_data_on_sim_object(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        _define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        _object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        _flags: DataRequestFlag,
+        _origin: DWORD,
+        _interval: DWORD,
+        _limit: DWORD,
+    ) -> SimConnectResult<()> {
+        let mut playback = self.playback.borrow_mut();
+        match period {
+            Period::Never => playback.requests.remove(&request_id),
+            period => playback.requests.insert(request_id, period),
+        };
+        Ok(())
+    }
+
+    fn request_data_on_sim_object_type(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        _define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        _radius_meters: DWORD,
+        _type_: SimObjectType,
+    ) -> SimConnectResult<()> {
+        self.playback.borrow_mut().sweeps.insert(request_id, false);
+        Ok(())
+    }
+
+    // Answered by the next recorded list for `request_id`.
+    fn request_facilities_list(
+        &self,
+        _type_: FacilityListType,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        self.playback
+            .borrow_mut()
+            .facility_requests
+            .insert(request_id);
+        Ok(())
+    }
+
+    // Answered with the latest recorded reply to `request_id`, or the next
+    // one if none has been played yet.
+    fn request_system_state(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        _state: &str,
+    ) -> SimConnectResult<()> {
+        let mut playback = self.playback.borrow_mut();
+        match playback.states.get(&request_id).cloned() {
+            Some(state) => playback.outbox.push_back(state),
+            None => {
+                playback.state_requests.insert(request_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_data_on_sim_object(
+        &self,
+        _define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        _object_id: SIMCONNECT_OBJECT_ID,
+        _flags: DataSetFlag,
+        _array_count: DWORD,
+        _cb_unit_size: DWORD,
+        _data_set: *mut raw::c_void,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn subscribe_to_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        _system_event_name: &str,
+    ) -> SimConnectResult<()> {
+        self.playback.borrow_mut().events.insert(event_id);
+        Ok(())
+    }
+
+    fn transmit_client_event(
+        &self,
+        _object_id: SIMCONNECT_OBJECT_ID,
+        _event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        _data: DWORD,
+        _group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        _flags: EventFlag,
+    ) -> SimConnectResult<()> {
+        Ok(())
+    }
+
+    fn unsubscribe_from_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    ) -> SimConnectResult<()> {
+        self.playback.borrow_mut().events.remove(&event_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::EventData;
+    use crate::session::{SessionMetadata, SessionWriter};
+    use std::io::Cursor;
+
+    fn recv(id: SIMCONNECT_RECV_ID, body: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(12 + body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&(id as u32).to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn open() -> Vec<u8> {
+        let mut body = vec![0; 256];
+        body[..4].copy_from_slice(b"MSFS");
+        for v in [11u32, 0, 282174, 0, 11, 0, 62651, 3] {
+            body.extend_from_slice(&v.to_le_bytes());
+        }
+        recv(SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_OPEN, &body)
+    }
+
+    fn data(request_id: u32, value: f64) -> Vec<u8> {
+        let mut body = Vec::new();
+        for v in [request_id, 0, 1, 0, 0, 0, 1] {
+            body.extend_from_slice(&v.to_le_bytes());
+        }
+        body.extend_from_slice(&value.to_le_bytes());
+        recv(SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SIMOBJECT_DATA, &body)
+    }
+
+    fn event(event_id: u32) -> Vec<u8> {
+        let mut body = Vec::new();
+        for v in [0, event_id, 1] {
+            body.extend_from_slice(&v.to_le_bytes());
+        }
+        recv(SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_EVENT, &body)
+    }
+
+    fn session() -> Replay<Cursor<Vec<u8>>> {
+        let mut writer =
+            SessionWriter::with_block_size(Vec::new(), &SessionMetadata::new("test"), 64).unwrap();
+        for message in [open(), data(1, 1.0), data(2, 5.0), event(7), data(1, 2.0)] {
+            writer.write_message(&message).unwrap();
+        }
+        writer
+            .write_call("SimConnect_RequestDataOnSimObject", &[], 0)
+            .unwrap();
+        Replay::open(Cursor::new(writer.finish().unwrap())).unwrap()
+    }
+
+    fn value(message: Option<Message>) -> Option<f64> {
+        match message {
+            Some(Message::SimObjectData(data)) => {
+                Some(f64::from_le_bytes(data.data[..8].try_into().unwrap()))
+            }
+            _ => None,
+        }
+    }
+
+    fn subscribe(replay: &Replay<Cursor<Vec<u8>>>) {
+        replay
+            .request_data_on_sim_object(
+                1,
+                1,
+                SIMCONNECT_OBJECT_ID_USER,
+                Period::SimFrame,
+                DataRequestFlag::Default,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+        replay.subscribe_to_system_event(7, "Pause").unwrap();
+    }
+
+    #[test]
+    fn steps_through_requested_messages() {
+        let replay = session();
+        assert_eq!(replay.server_info().unwrap().application_name, "MSFS");
+        assert!(matches!(replay.step().unwrap(), Some(Message::Open(_))));
+
+        subscribe(&replay);
+        assert_eq!(value(replay.step().unwrap()), Some(1.0));
+        assert!(matches!(
+            replay.step().unwrap(),
+            Some(Message::Event(EventData { event_id: 7, .. }))
+        ));
+        assert_eq!(value(replay.step().unwrap()), Some(2.0));
+        assert!(matches!(replay.step().unwrap(), Some(Message::Quit)));
+        assert!(replay.step().unwrap().is_none());
+        assert!(replay.is_finished());
+
+        replay.seek(Duration::ZERO).unwrap();
+        assert_eq!(value(replay.step().unwrap()), Some(1.0));
+    }
+
+    #[test]
+    fn plays_as_fast_as_read() {
+        let replay = session();
+        subscribe(&replay);
+        replay.set_speed(f64::INFINITY).unwrap();
+        replay.resume();
+
+        let mut messages = Vec::new();
+        while let Some(message) = replay.get_next_message().unwrap() {
+            messages.push(message);
+        }
+        assert!(matches!(messages[0], Message::Open(_)));
+        assert_eq!(messages.len(), 5);
+        assert!(matches!(messages[4], Message::Quit));
+        assert_eq!(value(messages.get(3).cloned()), Some(2.0));
+    }
+
+    #[test]
+    fn slows_down_from_the_last_message_delivered() {
+        let mut writer = SessionWriter::new(Vec::new(), &SessionMetadata::new("test")).unwrap();
+        writer.write_message(&open()).unwrap();
+        writer.write_message(&data(1, 1.0)).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        writer.write_message(&data(1, 2.0)).unwrap();
+        writer.write_message(&data(1, 3.0)).unwrap();
+        let replay = Replay::open(Cursor::new(writer.finish().unwrap())).unwrap();
+
+        subscribe(&replay);
+        replay.set_speed(f64::INFINITY).unwrap();
+        replay.resume();
+        assert!(matches!(
+            replay.get_next_message().unwrap(),
+            Some(Message::Open(_))
+        ));
+        assert_eq!(value(replay.get_next_message().unwrap()), Some(1.0));
+        assert_eq!(value(replay.get_next_message().unwrap()), Some(2.0));
+
+        replay.set_speed(1.0).unwrap();
+        assert!(replay.position() >= Duration::from_millis(300));
+        // Due right away, well before the 300ms skipped over have passed.
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let mut next = None;
+        while next.is_none() && Instant::now() < deadline {
+            next = replay.get_next_message().unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(value(next), Some(3.0));
+    }
+}
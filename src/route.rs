@@ -0,0 +1,727 @@
+// Compiles route strings such as `EGLL DCT BPK UN859 RESMI LFPG` into flight
+// plans. Idents are resolved against a `Navdata` set filled from facility
+// lists, X-Plane style `apt.dat`, `earth_fix.dat`, `earth_nav.dat` and
+// `earth_awy.dat` files, or by hand. An ident with several matches resolves to the one
+// nearest the previous point of the route.
+
+use super::message::Message;
+use super::pln::{FlightPlan, Position, RouteType, Waypoint, WaypointType};
+use super::types::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// Fixes closer than this with the same ident and kind are the same fix.
+const SAME_FIX_M: f64 = 1_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub ident: String,
+    pub region: Option<String>,
+    pub kind: WaypointType,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+}
+
+impl Fix {
+    pub fn new(ident: &str, kind: WaypointType, latitude: f64, longitude: f64) -> Self {
+        Self {
+            ident: ident.to_string(),
+            region: None,
+            kind,
+            latitude,
+            longitude,
+            altitude_ft: 0.0,
+        }
+    }
+
+    pub fn region(mut self, region: &str) -> Self {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    pub fn altitude(mut self, altitude_ft: f64) -> Self {
+        self.altitude_ft = altitude_ft;
+        self
+    }
+
+    pub fn distance_m(&self, other: &Fix) -> f64 {
+        distance_m(
+            self.latitude,
+            self.longitude,
+            other.latitude,
+            other.longitude,
+        )
+    }
+
+    fn to_waypoint(&self) -> Waypoint {
+        let position = Position::new(self.latitude, self.longitude, self.altitude_ft);
+        let waypoint = Waypoint::new(&self.ident, self.kind, position);
+        match &self.region {
+            Some(region) => waypoint.region(region),
+            None => waypoint,
+        }
+    }
+}
+
+fn distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+}
+
+fn invalid(what: &str) -> SimConnectError {
+    SimConnectError::new(&format!("Invalid route: {}", what), None)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Navdata {
+    fixes: Vec<Fix>,
+    by_ident: HashMap<String, Vec<usize>>,
+    // Directed segments between fixes, by airway name.
+    airways: HashMap<String, Vec<(usize, usize)>>,
+}
+
+impl Navdata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reads `apt.dat`, `earth_fix.dat`, `earth_nav.dat` and `earth_awy.dat`
+    // (version 1100 or later) from `dir`, skipping any that are missing.
+    // X-Plane 12 keeps `apt.dat` apart, under `Global Scenery/Global
+    // Airports/Earth nav data`, which `read_airports` takes.
+    pub fn load_xplane(dir: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let dir = dir.as_ref();
+        let mut navdata = Self::new();
+        navdata.read_file(&dir.join("apt.dat"), Self::read_airports)?;
+        navdata.read_file(&dir.join("earth_fix.dat"), Self::read_fixes)?;
+        navdata.read_file(&dir.join("earth_nav.dat"), Self::read_navaids)?;
+        navdata.read_file(&dir.join("earth_awy.dat"), Self::read_airways)?;
+        Ok(navdata)
+    }
+
+    fn read_file(
+        &mut self,
+        path: &Path,
+        read: fn(&mut Self, &mut dyn BufRead) -> SimConnectResult<()>,
+    ) -> SimConnectResult<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let file = File::open(path).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+        })?;
+        read(self, &mut BufReader::new(file))
+            .map_err(|e| SimConnectError::new(&format!("{}: {}", path.display(), e), None))
+    }
+
+    // `lat lon ident airport region ...`
+    pub fn read_fixes(&mut self, reader: &mut dyn BufRead) -> SimConnectResult<()> {
+        read_dat(reader, |fields| {
+            if fields.len() < 5 {
+                return Err("expected lat lon ident airport region".to_string());
+            }
+            let fix = Fix::new(
+                fields[2],
+                WaypointType::Intersection,
+                number(fields[0])?,
+                number(fields[1])?,
+            )
+            .region(fields[4]);
+            self.add_fix(fix);
+            Ok(())
+        })
+    }
+
+    // Airport headers `code elevation - - ident name` (codes 1, 16 and 17)
+    // placed at their `1302 datum_lat` and `datum_lon`, or else between the
+    // ends of their runways and helipads. A `1302 icao_code` replaces the
+    // ident. Airports without either are skipped.
+    pub fn read_airports(&mut self, reader: &mut dyn BufRead) -> SimConnectResult<()> {
+        let mut airport: Option<AptAirport> = None;
+        read_dat(reader, |fields| {
+            let ends: &[(usize, usize)] = match fields[0] {
+                "1" | "16" | "17" => {
+                    if fields.len() < 5 {
+                        return Err("expected code elevation - - ident".to_string());
+                    }
+                    if let Some(fix) = airport.take().and_then(AptAirport::into_fix) {
+                        self.add_fix(fix);
+                    }
+                    airport = Some(AptAirport {
+                        ident: fields[4].to_string(),
+                        region: None,
+                        elevation_ft: number(fields[1])?,
+                        datum: (None, None),
+                        ends: Vec::new(),
+                    });
+                    return Ok(());
+                }
+                "100" if fields.len() >= 20 => &[(9, 10), (18, 19)],
+                "101" if fields.len() >= 9 => &[(4, 5), (7, 8)],
+                "102" if fields.len() >= 4 => &[(2, 3)],
+                "1302" if fields.len() >= 3 => {
+                    let Some(airport) = airport.as_mut() else {
+                        return Ok(());
+                    };
+                    match fields[1] {
+                        "datum_lat" => airport.datum.0 = Some(number(fields[2])?),
+                        "datum_lon" => airport.datum.1 = Some(number(fields[2])?),
+                        "icao_code" => airport.ident = fields[2].to_string(),
+                        "region_code" => airport.region = Some(fields[2].to_string()),
+                        _ => {}
+                    }
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            };
+            if let Some(airport) = airport.as_mut() {
+                for &(lat, lon) in ends {
+                    airport
+                        .ends
+                        .push((number(fields[lat])?, number(fields[lon])?));
+                }
+            }
+            Ok(())
+        })?;
+        if let Some(fix) = airport.and_then(AptAirport::into_fix) {
+            self.add_fix(fix);
+        }
+        Ok(())
+    }
+
+    // `code lat lon elevation frequency range variation ident airport region
+    // name`, keeping NDBs (code 2) and VORs (code 3).
+    pub fn read_navaids(&mut self, reader: &mut dyn BufRead) -> SimConnectResult<()> {
+        read_dat(reader, |fields| {
+            let kind = match fields[0] {
+                "2" => WaypointType::Ndb,
+                "3" => WaypointType::Vor,
+                _ => return Ok(()),
+            };
+            if fields.len() < 10 {
+                return Err("expected code lat lon elevation ... ident airport region".to_string());
+            }
+            let fix = Fix::new(fields[7], kind, number(fields[1])?, number(fields[2])?)
+                .region(fields[9])
+                .altitude(number(fields[3])?);
+            self.add_fix(fix);
+            Ok(())
+        })
+    }
+
+    // `ident region type ident region type direction level base top names`,
+    // with names joined by `-` when airways share a segment. Segments whose
+    // ends are not known yet are skipped, so read fixes and navaids first.
+    pub fn read_airways(&mut self, reader: &mut dyn BufRead) -> SimConnectResult<()> {
+        read_dat(reader, |fields| {
+            if fields.len() < 11 {
+                return Err("expected 11 fields".to_string());
+            }
+            let kind = |code: &str| match code {
+                "2" => Ok(WaypointType::Ndb),
+                "3" => Ok(WaypointType::Vor),
+                "11" => Ok(WaypointType::Intersection),
+                code => Err(format!("unknown fix type {}", code)),
+            };
+            let from = self.find(fields[0], fields[1], kind(fields[2])?);
+            let to = self.find(fields[3], fields[4], kind(fields[5])?);
+            let (Some(from), Some(to)) = (from, to) else {
+                return Ok(());
+            };
+            let (forward, backward) = match fields[6] {
+                "N" => (true, true),
+                "F" => (true, false),
+                "B" => (false, true),
+                direction => return Err(format!("unknown direction {}", direction)),
+            };
+            for name in fields[10].split('-') {
+                if forward {
+                    self.add_airway(name, from, to);
+                }
+                if backward {
+                    self.add_airway(name, to, from);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Adds the fixes of an `AirportList`, `WaypointList`, `NdbList` or
+    // `VorList` reply. Other messages are ignored.
+    pub fn add_facilities(&mut self, message: &Message) {
+        let fixes: Vec<Fix> = match message {
+            Message::AirportList(list) => list
+                .items
+                .iter()
+                .map(|a| {
+                    Fix::new(&a.icao, WaypointType::Airport, a.latitude, a.longitude)
+                        .altitude(a.altitude / 0.3048)
+                })
+                .collect(),
+            Message::WaypointList(list) => list
+                .items
+                .iter()
+                .map(|w| Fix::new(&w.icao, WaypointType::Intersection, w.latitude, w.longitude))
+                .collect(),
+            Message::NdbList(list) => list
+                .items
+                .iter()
+                .map(|n| Fix::new(&n.icao, WaypointType::Ndb, n.latitude, n.longitude))
+                .collect(),
+            Message::VorList(list) => list
+                .items
+                .iter()
+                .map(|v| Fix::new(&v.icao, WaypointType::Vor, v.latitude, v.longitude))
+                .collect(),
+            _ => return,
+        };
+        for fix in fixes {
+            self.add_fix(fix);
+        }
+    }
+
+    // Returns the index of the fix, merging it with a known fix of the same
+    // ident and kind at the same place.
+    pub fn add_fix(&mut self, fix: Fix) -> usize {
+        let indices = self.by_ident.entry(fix.ident.clone()).or_default();
+        for &index in indices.iter() {
+            let known = &mut self.fixes[index];
+            let same_region = match (&known.region, &fix.region) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            };
+            if known.kind == fix.kind && same_region && known.distance_m(&fix) < SAME_FIX_M {
+                if known.region.is_none() {
+                    known.region = fix.region;
+                }
+                return index;
+            }
+        }
+        indices.push(self.fixes.len());
+        self.fixes.push(fix);
+        self.fixes.len() - 1
+    }
+
+    pub fn add_airway(&mut self, name: &str, from: usize, to: usize) {
+        self.airways
+            .entry(name.to_string())
+            .or_default()
+            .push((from, to));
+    }
+
+    pub fn fix(&self, index: usize) -> &Fix {
+        &self.fixes[index]
+    }
+
+    pub fn lookup(&self, ident: &str) -> impl Iterator<Item = (usize, &Fix)> {
+        self.by_ident
+            .get(ident)
+            .into_iter()
+            .flatten()
+            .map(|&i| (i, &self.fixes[i]))
+    }
+
+    pub fn is_airway(&self, name: &str) -> bool {
+        self.airways.contains_key(name)
+    }
+
+    fn find(&self, ident: &str, region: &str, kind: WaypointType) -> Option<usize> {
+        self.lookup(ident)
+            .find(|(_, f)| f.kind == kind && f.region.as_deref().is_none_or(|r| r == region))
+            .map(|(i, _)| i)
+    }
+
+    fn nearest(&self, ident: &str, near: Option<&Fix>) -> Option<usize> {
+        let candidates = self.lookup(ident);
+        match near {
+            Some(near) => candidates
+                .min_by(|a, b| a.1.distance_m(near).total_cmp(&b.1.distance_m(near)))
+                .map(|(i, _)| i),
+            None => candidates.map(|(i, _)| i).next(),
+        }
+    }
+
+    // The fixes after `entry` along `airway` up to and including `exit`,
+    // following the fewest segments.
+    fn expand(&self, airway: &str, entry: &Fix, exit: &str) -> Option<Vec<usize>> {
+        let segments = self.airways.get(airway)?;
+        let starts: Vec<usize> = segments
+            .iter()
+            .map(|(from, _)| *from)
+            .filter(|&i| self.fixes[i].ident == entry.ident)
+            .filter(|&i| self.fixes[i].distance_m(entry) < SAME_FIX_M)
+            .collect();
+
+        let mut previous: HashMap<usize, usize> = HashMap::new();
+        let mut seen: HashSet<usize> = starts.iter().copied().collect();
+        let mut queue: VecDeque<usize> = starts.into_iter().collect();
+        while let Some(current) = queue.pop_front() {
+            if self.fixes[current].ident == exit && previous.contains_key(&current) {
+                let mut path = vec![current];
+                while let Some(&before) = previous.get(path.last().unwrap()) {
+                    path.push(before);
+                }
+                path.pop();
+                path.reverse();
+                return Some(path);
+            }
+            for &(_, to) in segments.iter().filter(|(from, _)| *from == current) {
+                if seen.insert(to) {
+                    previous.insert(to, current);
+                    queue.push_back(to);
+                }
+            }
+        }
+        None
+    }
+}
+
+struct AptAirport {
+    ident: String,
+    region: Option<String>,
+    elevation_ft: f64,
+    datum: (Option<f64>, Option<f64>),
+    ends: Vec<(f64, f64)>,
+}
+
+impl AptAirport {
+    fn into_fix(self) -> Option<Fix> {
+        let (latitude, longitude) = match self.datum {
+            (Some(latitude), Some(longitude)) => (latitude, longitude),
+            _ if !self.ends.is_empty() => {
+                let n = self.ends.len() as f64;
+                let (lat, lon) = self
+                    .ends
+                    .iter()
+                    .fold((0.0, 0.0), |(a, b), (lat, lon)| (a + lat, b + lon));
+                (lat / n, lon / n)
+            }
+            _ => return None,
+        };
+        let fix = Fix::new(&self.ident, WaypointType::Airport, latitude, longitude)
+            .altitude(self.elevation_ft);
+        Some(match &self.region {
+            Some(region) => fix.region(region),
+            None => fix,
+        })
+    }
+}
+
+fn read_dat(
+    reader: &mut dyn BufRead,
+    mut read_line: impl FnMut(&[&str]) -> Result<(), String>,
+) -> SimConnectResult<()> {
+    // The first two lines are the origin and the version.
+    for (number, line) in reader.lines().enumerate().skip(2) {
+        let line = line.map_err(|e| SimConnectError::new(&e.to_string(), None))?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.first() {
+            None => continue,
+            Some(&"99") => break,
+            Some(_) => read_line(&fields)
+                .map_err(|e| SimConnectError::new(&format!("line {}: {}", number + 1, e), None))?,
+        }
+    }
+    Ok(())
+}
+
+fn number(text: &str) -> Result<f64, String> {
+    text.parse().map_err(|_| format!("bad number {:?}", text))
+}
+
+// ICAO speed and level groups such as `N0450F350`, returning the level in
+// feet.
+fn parse_speed_level(text: &str) -> Option<f64> {
+    let speed_digits = match text.chars().next()? {
+        'N' | 'K' => 4,
+        'M' => 3,
+        _ => return None,
+    };
+    let level = text.get(1 + speed_digits..)?;
+    if !text[1..1 + speed_digits]
+        .bytes()
+        .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let (unit, digits) = level.split_at(1.min(level.len()));
+    let value: f64 = match (unit, digits.len()) {
+        ("F" | "A", 3) | ("S" | "M", 4) => digits.parse().ok()?,
+        _ => return None,
+    };
+    match unit {
+        "F" | "A" => Some(value * 100.0),
+        _ => Some(value * 10.0 / 0.3048),
+    }
+}
+
+// Coordinates such as `50N030W` or `5020N00130W`, in whole degrees or
+// degrees and minutes.
+fn parse_coordinates(text: &str) -> Option<(f64, f64)> {
+    let split = text.find(['N', 'S'])?;
+    let (lat, rest) = text.split_at(split);
+    let (hemisphere, lon) = rest.split_at(1);
+    let (lon, east_west) = lon.split_at(lon.len().checked_sub(1)?);
+
+    let angle = |digits: &str, degree_digits: usize| -> Option<f64> {
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        match digits.len() - degree_digits {
+            0 => digits.parse().ok(),
+            2 => {
+                let minutes: f64 = digits[degree_digits..].parse().ok()?;
+                Some(digits[..degree_digits].parse::<f64>().ok()? + minutes / 60.0)
+            }
+            _ => None,
+        }
+    };
+    if !matches!(lat.len(), 2 | 4) || !matches!(lon.len(), 3 | 5) {
+        return None;
+    }
+    let latitude = angle(lat, 2)?;
+    let longitude = angle(lon, 3)?;
+    let latitude = if hemisphere == "S" {
+        -latitude
+    } else {
+        latitude
+    };
+    let longitude = match east_west {
+        "E" => longitude,
+        "W" => -longitude,
+        _ => return None,
+    };
+    Some((latitude, longitude))
+}
+
+// Compiles a route from departure to destination airport. Tokens are fix
+// idents, airways between two fixes, `DCT`, coordinates and ICAO speed and
+// level groups, either alone or after a fix as in `BPK/N0450F350`. The
+// first level becomes the cruising altitude.
+pub fn compile(route: &str, navdata: &Navdata) -> SimConnectResult<FlightPlan> {
+    let mut tokens = Vec::new();
+    let mut cruising_altitude_ft = None;
+    for token in route.split_whitespace() {
+        // Idents, coordinates and levels are all ASCII, and parsing them
+        // slices by byte.
+        if !token.is_ascii() {
+            return Err(invalid(&format!("unexpected characters in {:?}", token)));
+        }
+        let token = token.to_ascii_uppercase();
+        let (name, suffix) = match token.split_once('/') {
+            Some((name, suffix)) => (name.to_string(), Some(suffix.to_string())),
+            None => (token, None),
+        };
+        if let Some(level) = parse_speed_level(&name) {
+            cruising_altitude_ft.get_or_insert(level);
+            continue;
+        }
+        if let Some(suffix) = suffix {
+            let level = parse_speed_level(&suffix)
+                .ok_or_else(|| invalid(&format!("bad speed and level {:?}", suffix)))?;
+            cruising_altitude_ft.get_or_insert(level);
+        }
+        if name != "DCT" {
+            tokens.push(name);
+        }
+    }
+
+    let mut fixes: Vec<(Fix, Option<String>)> = Vec::new();
+    let mut airways = false;
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        let previous = fixes.last().map(|(fix, _)| fix);
+
+        if let (Some(entry), Some(exit)) = (previous, tokens.get(index + 1)) {
+            if navdata.is_airway(token) {
+                let path = navdata.expand(token, entry, exit).ok_or_else(|| {
+                    invalid(&format!(
+                        "no way from {} to {} on {}",
+                        entry.ident, exit, token
+                    ))
+                })?;
+                for fix in path {
+                    fixes.push((navdata.fix(fix).clone(), Some(token.clone())));
+                }
+                airways = true;
+                index += 2;
+                continue;
+            }
+        }
+
+        let fix = match parse_coordinates(token) {
+            Some((latitude, longitude)) => Fix::new(token, WaypointType::User, latitude, longitude),
+            None => {
+                // Without a previous point, the first unambiguous ident
+                // further along the route decides.
+                let anchor = match previous {
+                    Some(previous) => Some(previous.clone()),
+                    None => tokens[index + 1..]
+                        .iter()
+                        .filter_map(|t| {
+                            let mut found = navdata.lookup(t);
+                            match (found.next(), found.next()) {
+                                (Some((_, fix)), None) => Some(fix.clone()),
+                                _ => None,
+                            }
+                        })
+                        .next(),
+                };
+                let found = navdata
+                    .nearest(token, anchor.as_ref())
+                    .ok_or_else(|| invalid(&format!("unknown fix or airway {}", token)))?;
+                navdata.fix(found).clone()
+            }
+        };
+        fixes.push((fix, None));
+        index += 1;
+    }
+
+    if fixes.len() < 2 {
+        return Err(invalid("need at least a departure and a destination"));
+    }
+    let mut waypoints: Vec<Waypoint> = fixes
+        .iter()
+        .map(|(fix, airway)| match airway {
+            Some(airway) => fix.to_waypoint().airway(airway),
+            None => fix.to_waypoint(),
+        })
+        .collect();
+    let destination = waypoints.pop().unwrap();
+    let mut plan = FlightPlan::new(waypoints.remove(0), destination).route_type(match airways {
+        true => RouteType::HighAlt,
+        false => RouteType::Direct,
+    });
+    for waypoint in waypoints {
+        plan = plan.via(waypoint);
+    }
+    if let Some(altitude_ft) = cruising_altitude_ft {
+        plan = plan.cruising_altitude(altitude_ft);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn navdata() -> Navdata {
+        let mut navdata = Navdata::new();
+        navdata.add_fix(Fix::new("EGLL", WaypointType::Airport, 51.47, -0.45));
+        navdata.add_fix(Fix::new("LFPG", WaypointType::Airport, 49.01, 2.55));
+        let airway: Vec<usize> = [
+            ("BPK", WaypointType::Vor, 51.75, -0.11),
+            ("DET", WaypointType::Vor, 51.30, 0.60),
+            ("SITET", WaypointType::Intersection, 50.90, 1.10),
+            ("RESMI", WaypointType::Intersection, 50.50, 1.70),
+        ]
+        .into_iter()
+        .map(|(ident, kind, latitude, longitude)| {
+            navdata.add_fix(Fix::new(ident, kind, latitude, longitude))
+        })
+        .collect();
+        for pair in airway.windows(2) {
+            navdata.add_airway("UN859", pair[0], pair[1]);
+            navdata.add_airway("UN859", pair[1], pair[0]);
+        }
+        navdata
+    }
+
+    fn idents(plan: &FlightPlan) -> Vec<(&str, Option<&str>)> {
+        plan.waypoints
+            .iter()
+            .map(|w| (w.id.as_str(), w.airway.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn expands_airways_between_fixes() {
+        let plan = compile("egll dct BPK/N0450F350 UN859 RESMI LFPG", &navdata()).unwrap();
+        assert_eq!(
+            idents(&plan),
+            vec![
+                ("EGLL", None),
+                ("BPK", None),
+                ("DET", Some("UN859")),
+                ("SITET", Some("UN859")),
+                ("RESMI", Some("UN859")),
+                ("LFPG", None),
+            ]
+        );
+        assert_eq!(plan.route_type, RouteType::HighAlt);
+        assert_eq!(plan.cruising_altitude_ft, 35_000.0);
+
+        let error = compile("EGLL BPK UN859 LFPG", &navdata()).unwrap_err();
+        assert!(error.to_string().contains("no way from BPK to LFPG"));
+    }
+
+    #[test]
+    fn picks_the_nearest_of_duplicate_idents() {
+        let mut navdata = navdata();
+        navdata.add_fix(Fix::new("ABC", WaypointType::Ndb, -33.9, 151.2));
+        navdata.add_fix(Fix::new("ABC", WaypointType::Ndb, 50.1, 1.9));
+        navdata.add_fix(Fix::new("ABC", WaypointType::Ndb, 40.6, -73.8));
+
+        let plan = compile("EGLL ABC LFPG", &navdata).unwrap();
+        assert_eq!(plan.waypoints[1].position.latitude, 50.1);
+
+        // Leading with a duplicate, the next unambiguous ident decides.
+        let plan = compile("ABC LFPG", &navdata).unwrap();
+        assert_eq!(plan.waypoints[0].position.latitude, 50.1);
+    }
+
+    #[test]
+    fn rejects_non_ascii_tokens() {
+        assert!(compile("EGLL 50NÖ LFPG", &navdata()).is_err());
+        assert!(compile("EGLL BPK/NÖ450 LFPG", &navdata()).is_err());
+    }
+
+    #[test]
+    fn loads_airports_with_the_local_navdata() {
+        let dir =
+            std::env::temp_dir().join(format!("simply-simconnect-navdata-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("apt.dat"),
+            "I\n1100 Version\n\n\
+             1 83 0 0 EGLL London Heathrow\n\
+             1302 region_code EG\n\
+             100 50.00 1 0 0.25 1 3 0 09L 51.47750 -0.48500 0 0 3 0 0 0 27R 51.47767 -0.43330 0 0 3 0 0 0\n\
+             1 392 0 0 XLFPG Paris Charles de Gaulle\n\
+             1302 icao_code LFPG\n\
+             1302 datum_lat 49.009722\n\
+             1302 datum_lon 2.547778\n\
+             17 0 0 0 XHEL Without a pad\n\
+             99\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("earth_fix.dat"),
+            "I\n1100 Version\n50.5 1.7 RESMI ENRT LF 2115146\n99\n",
+        )
+        .unwrap();
+        let navdata = Navdata::load_xplane(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let navdata = navdata.unwrap();
+
+        let plan = compile("EGLL DCT RESMI DCT LFPG", &navdata).unwrap();
+        assert_eq!(
+            idents(&plan),
+            vec![("EGLL", None), ("RESMI", None), ("LFPG", None)]
+        );
+        let (_, egll) = navdata.lookup("EGLL").next().unwrap();
+        assert_eq!(egll.region.as_deref(), Some("EG"));
+        assert!((egll.latitude - 51.477585).abs() < 1e-9);
+        assert_eq!(egll.altitude_ft, 83.0);
+        assert!(navdata.lookup("XHEL").next().is_none());
+    }
+}
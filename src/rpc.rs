@@ -0,0 +1,820 @@
+//! Request/response framing over a pair of client data areas.
+//!
+//! The client writes frames into a *command* area and the peer (usually a
+//! WASM module inside the simulator) answers in a *response* area. Both areas
+//! have the same fixed size and each `SimConnect_SetClientData` call carries
+//! exactly one frame, padded with zeros to the area size. Readers subscribe
+//! with `ClientDataPeriod::OnSet` so every write arrives as its own message.
+//!
+//! Every frame starts with a 28 byte little-endian header:
+//!
+//! | offset | size | field         | notes                                       |
+//! |--------|------|---------------|---------------------------------------------|
+//! | 0      | 4    | magic         | `0x43505253` ("SRPC")                       |
+//! | 4      | 2    | version       | protocol version the frame is encoded with  |
+//! | 6      | 1    | kind          | see below                                   |
+//! | 7      | 1    | flags         | reserved, written as 0                      |
+//! | 8      | 4    | request_id    | 0 for handshake frames                      |
+//! | 12     | 4    | sequence      | per writer, starts at 1, wraps              |
+//! | 16     | 2    | chunk_index   | 0 based                                     |
+//! | 18     | 2    | chunk_count   | at least 1                                  |
+//! | 20     | 4    | chunk_len     | payload bytes following the header          |
+//! | 24     | 4    | total_len     | payload bytes of the whole message          |
+//!
+//! Frame kinds:
+//!
+//! * `0` hello: client to peer, payload is `min_version: u16, max_version: u16`.
+//! * `1` hello ack: peer to client, payload is the chosen `version: u16`.
+//! * `2` request: client to peer, opaque payload.
+//! * `3` response: peer to client, opaque payload, same `request_id`.
+//! * `4` error: peer to client, UTF-8 message, same `request_id`. A rejected
+//!   hello is answered with an error for `request_id` 0.
+//!
+//! A message larger than `area_size - 28` bytes is split into chunks that are
+//! written in order. Readers drop frames whose magic does not match (such as
+//! an area that is still zeroed) and frames whose sequence is not newer than
+//! the last one seen, which filters out the initial snapshot SimConnect sends
+//! when a client data request is made. A hello, a hello ack or a frame with
+//! sequence 1 resets the reader's sequence tracking, so either end may restart
+//! at any time.
+
+use super::bindings::*;
+use super::message::{Message, Reader};
+#[cfg(feature = "sdk")]
+use super::simconnect::SimConnect;
+use super::types::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+pub const FRAME_MAGIC: u32 = 0x4350_5253;
+pub const HEADER_LEN: usize = 28;
+pub const DEFAULT_AREA_SIZE: usize = SIMCONNECT_CLIENTDATA_MAX_SIZE as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Hello = 0,
+    HelloAck = 1,
+    Request = 2,
+    Response = 3,
+    Error = 4,
+}
+
+impl FrameKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FrameKind::Hello),
+            1 => Some(FrameKind::HelloAck),
+            2 => Some(FrameKind::Request),
+            3 => Some(FrameKind::Response),
+            4 => Some(FrameKind::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub version: u16,
+    pub kind: FrameKind,
+    pub request_id: u32,
+    pub sequence: u32,
+    pub chunk_index: u16,
+    pub chunk_count: u16,
+    pub total_len: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn encode(&self, area_size: usize) -> SimConnectResult<Vec<u8>> {
+        if HEADER_LEN + self.payload.len() > area_size {
+            return Err(SimConnectError::new("Frame does not fit in area", None));
+        }
+
+        let mut out = Vec::with_capacity(area_size);
+        out.extend_from_slice(&FRAME_MAGIC.to_le_bytes());
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.push(self.kind as u8);
+        out.push(0);
+        out.extend_from_slice(&self.request_id.to_le_bytes());
+        out.extend_from_slice(&self.sequence.to_le_bytes());
+        out.extend_from_slice(&self.chunk_index.to_le_bytes());
+        out.extend_from_slice(&self.chunk_count.to_le_bytes());
+        out.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.total_len.to_le_bytes());
+        out.extend_from_slice(&self.payload);
+        out.resize(area_size, 0);
+        Ok(out)
+    }
+
+    // Returns `Ok(None)` for areas that do not hold a frame at all.
+    pub fn decode(area: &[u8]) -> SimConnectResult<Option<Self>> {
+        let r = Reader::new(area);
+        if area.len() < HEADER_LEN || r.u32(0)? != FRAME_MAGIC {
+            return Ok(None);
+        }
+
+        let header = r.slice(4, HEADER_LEN - 4)?;
+        let kind = FrameKind::from_u8(header[2])
+            .ok_or_else(|| SimConnectError::new("Unknown rpc frame kind", None))?;
+        let chunk_len = r.u32(20)? as usize;
+        let chunk_count = u16::from_le_bytes([header[14], header[15]]);
+        if chunk_count == 0 {
+            return Err(SimConnectError::new("Rpc frame has no chunks", None));
+        }
+
+        Ok(Some(Self {
+            version: u16::from_le_bytes([header[0], header[1]]),
+            kind,
+            request_id: r.u32(8)?,
+            sequence: r.u32(12)?,
+            chunk_index: u16::from_le_bytes([header[12], header[13]]),
+            chunk_count,
+            total_len: r.u32(24)?,
+            payload: r.slice(HEADER_LEN, chunk_len)?.to_vec(),
+        }))
+    }
+}
+
+pub trait Transport {
+    fn area_size(&self) -> usize;
+    fn send(&mut self, area: &[u8]) -> SimConnectResult<()>;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AreaIds {
+    pub client_data_id: SIMCONNECT_CLIENT_DATA_ID,
+    pub define_id: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+}
+
+// Client data plumbing for one side of the channel: the area it writes and the
+// area it reads. The peer side uses the same type with the areas swapped.
+pub struct SimConnectAreas {
+    pub write: AreaIds,
+    pub read: AreaIds,
+    pub read_request_id: SIMCONNECT_DATA_REQUEST_ID,
+    pub area_size: usize,
+}
+
+impl SimConnectAreas {
+    #[cfg(feature = "sdk")]
+    pub fn setup(
+        &self,
+        simconnect: &SimConnect,
+        write_name: &str,
+        read_name: &str,
+        create: bool,
+    ) -> SimConnectResult<()> {
+        let size = self.area_size as DWORD;
+        for (area, name) in [(self.write, write_name), (self.read, read_name)] {
+            simconnect.map_client_data_name_to_id(name, area.client_data_id)?;
+            if create {
+                simconnect.create_client_data(
+                    area.client_data_id,
+                    size,
+                    CreateClientDataFlag::Default,
+                )?;
+            }
+            simconnect.add_to_client_data_definition(area.define_id, 0, size, 0.0, 0)?;
+        }
+
+        simconnect.request_client_data(
+            self.read.client_data_id,
+            self.read_request_id,
+            self.read.define_id,
+            ClientDataPeriod::OnSet,
+            ClientDataRequestFlag::Default,
+            0,
+            0,
+            0,
+        )
+    }
+
+    pub fn incoming<'a>(&self, message: &'a Message) -> Option<&'a [u8]> {
+        match message {
+            Message::ClientData(data) if data.request_id == self.read_request_id => {
+                Some(&data.data)
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "sdk")]
+    pub fn transport<'a>(&'a self, simconnect: &'a SimConnect) -> SimConnectTransport<'a> {
+        SimConnectTransport {
+            simconnect,
+            areas: self,
+        }
+    }
+}
+
+#[cfg(feature = "sdk")]
+pub struct SimConnectTransport<'a> {
+    simconnect: &'a SimConnect,
+    areas: &'a SimConnectAreas,
+}
+
+#[cfg(feature = "sdk")]
+impl Transport for SimConnectTransport<'_> {
+    fn area_size(&self) -> usize {
+        self.areas.area_size
+    }
+
+    fn send(&mut self, area: &[u8]) -> SimConnectResult<()> {
+        let mut buffer = area.to_vec();
+        self.simconnect.set_client_data(
+            self.areas.write.client_data_id,
+            self.areas.write.define_id,
+            ClientDataSetFlag::Default,
+            0,
+            buffer.len() as DWORD,
+            buffer.as_mut_ptr() as *mut _,
+        )
+    }
+}
+
+// In-process transport pair for running a client against a peer without a
+// simulator. Whatever one end sends can be received on the other.
+#[derive(Clone)]
+pub struct LoopbackTransport {
+    area_size: usize,
+    outbox: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    inbox: Rc<RefCell<VecDeque<Vec<u8>>>>,
+}
+
+impl LoopbackTransport {
+    pub fn pair(area_size: usize) -> (Self, Self) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (
+            Self {
+                area_size,
+                outbox: a.clone(),
+                inbox: b.clone(),
+            },
+            Self {
+                area_size,
+                outbox: b,
+                inbox: a,
+            },
+        )
+    }
+
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        self.inbox.borrow_mut().pop_front()
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn area_size(&self) -> usize {
+        self.area_size
+    }
+
+    fn send(&mut self, area: &[u8]) -> SimConnectResult<()> {
+        if area.len() != self.area_size {
+            return Err(SimConnectError::new("Frame does not match area size", None));
+        }
+        self.outbox.borrow_mut().push_back(area.to_vec());
+        Ok(())
+    }
+}
+
+struct FrameWriter<T: Transport> {
+    transport: T,
+    sequence: u32,
+}
+
+impl<T: Transport> FrameWriter<T> {
+    fn new(transport: T) -> Self {
+        Self {
+            transport,
+            sequence: 0,
+        }
+    }
+
+    fn max_chunk(&self) -> usize {
+        self.transport.area_size().saturating_sub(HEADER_LEN)
+    }
+
+    fn write(
+        &mut self,
+        version: u16,
+        kind: FrameKind,
+        request_id: u32,
+        payload: &[u8],
+    ) -> SimConnectResult<()> {
+        let max_chunk = self.max_chunk();
+        if max_chunk == 0 {
+            return Err(SimConnectError::new(
+                "Area is too small for rpc frames",
+                None,
+            ));
+        }
+
+        let chunk_count = payload.len().div_ceil(max_chunk).max(1);
+        if chunk_count > u16::MAX as usize || payload.len() > u32::MAX as usize {
+            return Err(SimConnectError::new("Rpc message is too large", None));
+        }
+
+        for chunk_index in 0..chunk_count {
+            let start = chunk_index * max_chunk;
+            let end = (start + max_chunk).min(payload.len());
+            self.sequence = self.sequence.wrapping_add(1).max(1);
+            let frame = Frame {
+                version,
+                kind,
+                request_id,
+                sequence: self.sequence,
+                chunk_index: chunk_index as u16,
+                chunk_count: chunk_count as u16,
+                total_len: payload.len() as u32,
+                payload: payload[start..end].to_vec(),
+            };
+            self.transport
+                .send(&frame.encode(self.transport.area_size())?)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct FrameReader {
+    last_sequence: Option<u32>,
+    partial: HashMap<u32, Partial>,
+}
+
+struct Partial {
+    kind: FrameKind,
+    next_index: u16,
+    chunk_count: u16,
+    total_len: usize,
+    payload: Vec<u8>,
+}
+
+struct Assembled {
+    kind: FrameKind,
+    request_id: u32,
+    payload: Vec<u8>,
+}
+
+impl FrameReader {
+    fn reset(&mut self) {
+        self.last_sequence = None;
+        self.partial.clear();
+    }
+
+    fn is_new(&self, sequence: u32) -> bool {
+        match self.last_sequence {
+            None => true,
+            Some(last) => (sequence.wrapping_sub(last) as i32) > 0,
+        }
+    }
+
+    fn read(&mut self, area: &[u8]) -> SimConnectResult<Option<Assembled>> {
+        let frame = match Frame::decode(area)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        // A restarted writer starts again at sequence 1.
+        let restarted =
+            frame.sequence == 1 || matches!(frame.kind, FrameKind::Hello | FrameKind::HelloAck);
+        if restarted {
+            self.reset();
+        } else if !self.is_new(frame.sequence) {
+            return Ok(None);
+        }
+        self.last_sequence = Some(frame.sequence);
+
+        if frame.chunk_count == 1 {
+            return Ok(Some(Assembled {
+                kind: frame.kind,
+                request_id: frame.request_id,
+                payload: frame.payload,
+            }));
+        }
+
+        if frame.chunk_index == 0 {
+            let max_chunk = area.len() - HEADER_LEN;
+            if frame.total_len as usize > frame.chunk_count as usize * max_chunk {
+                return Err(SimConnectError::new(
+                    "Rpc message is larger than its chunks",
+                    None,
+                ));
+            }
+            self.partial.insert(
+                frame.request_id,
+                Partial {
+                    kind: frame.kind,
+                    next_index: 0,
+                    chunk_count: frame.chunk_count,
+                    total_len: frame.total_len as usize,
+                    payload: Vec::with_capacity(frame.total_len as usize),
+                },
+            );
+        }
+
+        let partial = match self.partial.get_mut(&frame.request_id) {
+            Some(partial)
+                if partial.kind == frame.kind
+                    && partial.next_index == frame.chunk_index
+                    && partial.chunk_count == frame.chunk_count =>
+            {
+                partial
+            }
+            _ => {
+                self.partial.remove(&frame.request_id);
+                return Err(SimConnectError::new("Rpc chunk out of order", None));
+            }
+        };
+
+        partial.payload.extend_from_slice(&frame.payload);
+        partial.next_index += 1;
+        if partial.next_index < partial.chunk_count {
+            return Ok(None);
+        }
+
+        let partial = self.partial.remove(&frame.request_id).unwrap();
+        if partial.payload.len() != partial.total_len {
+            return Err(SimConnectError::new("Rpc message length mismatch", None));
+        }
+
+        Ok(Some(Assembled {
+            kind: partial.kind,
+            request_id: frame.request_id,
+            payload: partial.payload,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcEvent {
+    Connected { version: u16 },
+    Rejected { message: String },
+    Response { request_id: u32, payload: Vec<u8> },
+    Failed { request_id: u32, message: String },
+    TimedOut { request_id: u32 },
+}
+
+enum Handshake {
+    Idle,
+    Pending(Instant),
+    Done(u16),
+}
+
+pub struct RpcClient<T: Transport> {
+    writer: FrameWriter<T>,
+    reader: FrameReader,
+    handshake: Handshake,
+    timeout: Duration,
+    next_request_id: u32,
+    pending: HashMap<u32, Instant>,
+}
+
+impl<T: Transport> RpcClient<T> {
+    pub fn new(transport: T, timeout: Duration) -> Self {
+        Self {
+            writer: FrameWriter::new(transport),
+            reader: FrameReader::default(),
+            handshake: Handshake::Idle,
+            timeout,
+            next_request_id: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn connect(&mut self, now: Instant) -> SimConnectResult<()> {
+        self.reader.reset();
+        self.pending.clear();
+        let mut payload = MIN_PROTOCOL_VERSION.to_le_bytes().to_vec();
+        payload.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        self.writer
+            .write(PROTOCOL_VERSION, FrameKind::Hello, 0, &payload)?;
+        self.handshake = Handshake::Pending(now + self.timeout);
+        Ok(())
+    }
+
+    pub fn version(&self) -> Option<u16> {
+        match self.handshake {
+            Handshake::Done(version) => Some(version),
+            _ => None,
+        }
+    }
+
+    pub fn call(&mut self, payload: &[u8], now: Instant) -> SimConnectResult<u32> {
+        let version = self
+            .version()
+            .ok_or_else(|| SimConnectError::new("Rpc handshake not completed", None))?;
+
+        self.next_request_id = self.next_request_id.wrapping_add(1).max(1);
+        let request_id = self.next_request_id;
+        self.writer
+            .write(version, FrameKind::Request, request_id, payload)?;
+        self.pending.insert(request_id, now + self.timeout);
+        Ok(request_id)
+    }
+
+    pub fn receive(&mut self, area: &[u8]) -> SimConnectResult<Option<RpcEvent>> {
+        let message = match self.reader.read(area)? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let event = match message.kind {
+            FrameKind::HelloAck => {
+                let version = Reader::new(&message.payload)
+                    .slice(0, 2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]))?;
+                if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+                    return Err(SimConnectError::new(
+                        "Peer chose an unsupported rpc version",
+                        None,
+                    ));
+                }
+                self.handshake = Handshake::Done(version);
+                Some(RpcEvent::Connected { version })
+            }
+            FrameKind::Error if message.request_id == 0 => {
+                self.handshake = Handshake::Idle;
+                Some(RpcEvent::Rejected {
+                    message: String::from_utf8_lossy(&message.payload).into_owned(),
+                })
+            }
+            FrameKind::Response => {
+                self.pending
+                    .remove(&message.request_id)
+                    .map(|_| RpcEvent::Response {
+                        request_id: message.request_id,
+                        payload: message.payload,
+                    })
+            }
+            FrameKind::Error => {
+                self.pending
+                    .remove(&message.request_id)
+                    .map(|_| RpcEvent::Failed {
+                        request_id: message.request_id,
+                        message: String::from_utf8_lossy(&message.payload).into_owned(),
+                    })
+            }
+            FrameKind::Hello | FrameKind::Request => None,
+        };
+
+        Ok(event)
+    }
+
+    pub fn expire(&mut self, now: Instant) -> Vec<RpcEvent> {
+        let mut expired: Vec<RpcEvent> = Vec::new();
+
+        if let Handshake::Pending(deadline) = self.handshake {
+            if now >= deadline {
+                self.handshake = Handshake::Idle;
+                expired.push(RpcEvent::TimedOut { request_id: 0 });
+            }
+        }
+
+        self.pending.retain(|request_id, deadline| {
+            let alive = now < *deadline;
+            if !alive {
+                expired.push(RpcEvent::TimedOut {
+                    request_id: *request_id,
+                });
+            }
+            alive
+        });
+
+        expired
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcRequest {
+    pub request_id: u32,
+    pub payload: Vec<u8>,
+}
+
+pub struct RpcPeer<T: Transport> {
+    writer: FrameWriter<T>,
+    reader: FrameReader,
+    version: Option<u16>,
+}
+
+impl<T: Transport> RpcPeer<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            writer: FrameWriter::new(transport),
+            reader: FrameReader::default(),
+            version: None,
+        }
+    }
+
+    pub fn version(&self) -> Option<u16> {
+        self.version
+    }
+
+    pub fn receive(&mut self, area: &[u8]) -> SimConnectResult<Option<RpcRequest>> {
+        let message = match self.reader.read(area)? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        match message.kind {
+            FrameKind::Hello => {
+                self.hello(&message)?;
+                Ok(None)
+            }
+            FrameKind::Request if self.version.is_some() => Ok(Some(RpcRequest {
+                request_id: message.request_id,
+                payload: message.payload,
+            })),
+            FrameKind::Request => {
+                self.fail(message.request_id, "Rpc handshake not completed")?;
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn respond(&mut self, request_id: u32, payload: &[u8]) -> SimConnectResult<()> {
+        self.writer.write(
+            self.frame_version(),
+            FrameKind::Response,
+            request_id,
+            payload,
+        )
+    }
+
+    pub fn fail(&mut self, request_id: u32, message: &str) -> SimConnectResult<()> {
+        self.writer.write(
+            self.frame_version(),
+            FrameKind::Error,
+            request_id,
+            message.as_bytes(),
+        )
+    }
+
+    fn frame_version(&self) -> u16 {
+        self.version.unwrap_or(PROTOCOL_VERSION)
+    }
+
+    fn hello(&mut self, message: &Assembled) -> SimConnectResult<()> {
+        let r = Reader::new(&message.payload);
+        let range = r.slice(0, 4).map(|b| {
+            (
+                u16::from_le_bytes([b[0], b[1]]),
+                u16::from_le_bytes([b[2], b[3]]),
+            )
+        });
+
+        let chosen = match range {
+            Ok((min, max)) if min <= PROTOCOL_VERSION && max >= MIN_PROTOCOL_VERSION => {
+                max.min(PROTOCOL_VERSION)
+            }
+            _ => {
+                self.version = None;
+                return self.fail(0, "No common rpc protocol version");
+            }
+        };
+
+        self.version = Some(chosen);
+        self.writer
+            .write(chosen, FrameKind::HelloAck, 0, &chosen.to_le_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA_SIZE: usize = 64;
+
+    fn deliver_to_peer(
+        transport: &LoopbackTransport,
+        peer: &mut RpcPeer<LoopbackTransport>,
+    ) -> Vec<RpcRequest> {
+        let mut requests = Vec::new();
+        while let Some(area) = transport.recv() {
+            requests.extend(peer.receive(&area).unwrap());
+        }
+        requests
+    }
+
+    fn deliver_to_client(
+        transport: &LoopbackTransport,
+        client: &mut RpcClient<LoopbackTransport>,
+    ) -> Vec<RpcEvent> {
+        let mut events = Vec::new();
+        while let Some(area) = transport.recv() {
+            events.extend(client.receive(&area).unwrap());
+        }
+        events
+    }
+
+    fn connected() -> (
+        RpcClient<LoopbackTransport>,
+        LoopbackTransport,
+        RpcPeer<LoopbackTransport>,
+        LoopbackTransport,
+        Instant,
+    ) {
+        let (client_end, peer_end) = LoopbackTransport::pair(AREA_SIZE);
+        let mut client = RpcClient::new(client_end.clone(), Duration::from_secs(1));
+        let mut peer = RpcPeer::new(peer_end.clone());
+        let now = Instant::now();
+
+        client.connect(now).unwrap();
+        assert!(deliver_to_peer(&peer_end, &mut peer).is_empty());
+        assert_eq!(
+            deliver_to_client(&client_end, &mut client),
+            vec![RpcEvent::Connected {
+                version: PROTOCOL_VERSION
+            }]
+        );
+        assert_eq!(peer.version(), Some(PROTOCOL_VERSION));
+        (client, client_end, peer, peer_end, now)
+    }
+
+    #[test]
+    fn answers_a_chunked_request() {
+        let (mut client, client_end, mut peer, peer_end, now) = connected();
+        let request: Vec<u8> = (0..100).collect();
+        let response: Vec<u8> = (0..150).map(|i| (i * 3) as u8).collect();
+
+        let request_id = client.call(&request, now).unwrap();
+        let requests = deliver_to_peer(&peer_end, &mut peer);
+        assert_eq!(
+            requests,
+            vec![RpcRequest {
+                request_id,
+                payload: request
+            }]
+        );
+
+        peer.respond(request_id, &response).unwrap();
+        assert_eq!(
+            deliver_to_client(&client_end, &mut client),
+            vec![RpcEvent::Response {
+                request_id,
+                payload: response
+            }]
+        );
+        assert_eq!(client.pending(), 0);
+    }
+
+    #[test]
+    fn times_out_unanswered_requests() {
+        let (mut client, client_end, mut peer, peer_end, now) = connected();
+        let request_id = client.call(b"ping", now).unwrap();
+        deliver_to_peer(&peer_end, &mut peer);
+
+        assert!(client.expire(now + Duration::from_millis(999)).is_empty());
+        assert_eq!(
+            client.expire(now + Duration::from_secs(1)),
+            vec![RpcEvent::TimedOut { request_id }]
+        );
+
+        // A late answer is dropped.
+        peer.respond(request_id, b"pong").unwrap();
+        assert!(deliver_to_client(&client_end, &mut client).is_empty());
+    }
+
+    #[test]
+    fn hears_a_restarted_peer() {
+        let (mut client, client_end, _, peer_end, now) = connected();
+        let mut peer = RpcPeer::new(peer_end.clone());
+
+        let request_id = client.call(b"ping", now).unwrap();
+        assert!(deliver_to_peer(&peer_end, &mut peer).is_empty());
+        assert_eq!(
+            deliver_to_client(&client_end, &mut client),
+            vec![RpcEvent::Failed {
+                request_id,
+                message: "Rpc handshake not completed".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_a_length_its_chunks_cannot_hold() {
+        let frame = Frame {
+            version: PROTOCOL_VERSION,
+            kind: FrameKind::Response,
+            request_id: 1,
+            sequence: 1,
+            chunk_index: 0,
+            chunk_count: 2,
+            total_len: u32::MAX,
+            payload: vec![0; 8],
+        };
+
+        let mut reader = FrameReader::default();
+        assert!(reader.read(&frame.encode(AREA_SIZE).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,1012 @@
+use super::bindings::*;
+use super::message::*;
+use super::multiplexer::{Multiplexer, SimVarKey, SubscriberId, Update};
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::os::raw;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const STRING_UNITS: &str = "string";
+const STRING_SIZE: usize = 256;
+const SENT_PACKET_HISTORY: usize = 256;
+const IDLE_SLEEP: Duration = Duration::from_millis(5);
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+const MULTIPLEXER_FIRST_ID: u32 = 0x1000_0000;
+// Client events cannot be unmapped, so the names clients may map are capped.
+const MAX_KEY_EVENTS: usize = 1024;
+
+pub type ClientId = u64;
+
+pub enum Command {
+    Connected {
+        client: ClientId,
+        sender: Sender<String>,
+    },
+    Disconnected {
+        client: ClientId,
+    },
+    Text {
+        client: ClientId,
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodName {
+    Once,
+    VisualFrame,
+    #[default]
+    SimFrame,
+    Second,
+}
+
+impl PeriodName {
+    fn as_period(self) -> Period {
+        match self {
+            PeriodName::Once => Period::Once,
+            PeriodName::VisualFrame => Period::VisualFrame,
+            PeriodName::SimFrame => Period::SimFrame,
+            PeriodName::Second => Period::Second,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FacilityKind {
+    Airport,
+    Waypoint,
+    Ndb,
+    Vor,
+}
+
+impl FacilityKind {
+    fn as_list_type(self) -> FacilityListType {
+        match self {
+            FacilityKind::Airport => FacilityListType::Airport,
+            FacilityKind::Waypoint => FacilityListType::Waypoint,
+            FacilityKind::Ndb => FacilityListType::Ndb,
+            FacilityKind::Vor => FacilityListType::Vor,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe {
+        id: String,
+        name: String,
+        units: String,
+        #[serde(default)]
+        period: PeriodName,
+        epsilon: Option<f32>,
+        #[serde(default)]
+        object: u32,
+    },
+    Unsubscribe {
+        id: String,
+    },
+    Set {
+        id: Option<String>,
+        name: String,
+        units: String,
+        value: Value,
+        #[serde(default)]
+        object: u32,
+    },
+    TransmitEvent {
+        id: Option<String>,
+        name: String,
+        #[serde(default)]
+        data: u32,
+        #[serde(default)]
+        object: u32,
+    },
+    SubscribeEvent {
+        id: String,
+        name: String,
+    },
+    UnsubscribeEvent {
+        id: String,
+    },
+    Facilities {
+        id: String,
+        kind: FacilityKind,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Ok {
+        id: String,
+    },
+    Data {
+        id: String,
+        object: u32,
+        value: Value,
+    },
+    Event {
+        id: String,
+        data: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        frame_rate: Option<f32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sim_speed: Option<f32>,
+    },
+    Facilities {
+        id: String,
+        items: Vec<Value>,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        message: String,
+    },
+    Quit,
+}
+
+struct EventSubscription {
+    client: ClientId,
+    id: String,
+}
+
+struct FacilityQuery {
+    client: ClientId,
+    id: String,
+    items: Vec<Value>,
+}
+
+struct SentPacket {
+    packet_id: DWORD,
+    client: ClientId,
+    id: Option<String>,
+}
+
+// Serves clients from anything implementing `SimConnectApi`, a live
+// connection or a `replay::Replay`.
+pub struct Server<S: SimConnectApi> {
+    simconnect: PacketTracker<S>,
+    next_id: u32,
+    clients: HashMap<ClientId, Sender<String>>,
+    multiplexer: Multiplexer,
+    subscribers: HashMap<SubscriberId, (ClientId, String)>,
+    client_subscriptions: HashMap<(ClientId, String), SubscriberId>,
+    set_definitions: HashMap<(String, String), SIMCONNECT_DATA_DEFINITION_ID>,
+    key_events: HashMap<String, SIMCONNECT_CLIENT_EVENT_ID>,
+    // Packet ids of recent mappings, so a name the sim refuses is forgotten.
+    key_event_packets: VecDeque<(DWORD, String)>,
+    system_events: HashMap<SIMCONNECT_CLIENT_EVENT_ID, EventSubscription>,
+    client_system_events: HashMap<(ClientId, String), SIMCONNECT_CLIENT_EVENT_ID>,
+    facility_queries: HashMap<SIMCONNECT_DATA_REQUEST_ID, FacilityQuery>,
+    sent_packets: VecDeque<SentPacket>,
+}
+
+impl<S: SimConnectApi> Server<S> {
+    pub fn new(simconnect: S) -> Self {
+        Self {
+            simconnect: PacketTracker {
+                inner: simconnect,
+                sent: RefCell::new(Vec::new()),
+            },
+            next_id: 0,
+            clients: HashMap::new(),
+            multiplexer: Multiplexer::new(MULTIPLEXER_FIRST_ID),
+            subscribers: HashMap::new(),
+            client_subscriptions: HashMap::new(),
+            set_definitions: HashMap::new(),
+            key_events: HashMap::new(),
+            key_event_packets: VecDeque::new(),
+            system_events: HashMap::new(),
+            client_system_events: HashMap::new(),
+            facility_queries: HashMap::new(),
+            sent_packets: VecDeque::new(),
+        }
+    }
+
+    pub fn run(&mut self, commands: Receiver<Command>) -> SimConnectResult<()> {
+        loop {
+            let mut busy = false;
+
+            match commands.try_recv() {
+                Ok(command) => {
+                    busy = true;
+                    self.handle_command(command);
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+
+            while let Some(message) = self.simconnect.get_next_message()? {
+                busy = true;
+                if let Message::Quit = message {
+                    self.broadcast(&ServerMessage::Quit);
+                    self.close_clients(&commands);
+                    return Ok(());
+                }
+                self.handle_message(message);
+            }
+
+            if !busy {
+                thread::sleep(IDLE_SLEEP);
+            }
+        }
+    }
+
+    // Drops every client's sender and waits for the client threads to flush
+    // what was queued and close their sockets.
+    fn close_clients(&mut self, commands: &Receiver<Command>) {
+        let mut open: HashSet<ClientId> = self.clients.drain().map(|(client, _)| client).collect();
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+
+        while !open.is_empty() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match commands.recv_timeout(timeout) {
+                Ok(Command::Disconnected { client }) => {
+                    open.remove(&client);
+                }
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Connected { client, sender } => {
+                self.clients.insert(client, sender);
+            }
+            Command::Disconnected { client } => self.disconnect(client),
+            Command::Text { client, text } => match serde_json::from_str(&text) {
+                Ok(message) => self.handle_client_message(client, message),
+                Err(e) => self.send(
+                    client,
+                    &ServerMessage::Error {
+                        id: None,
+                        message: format!("Invalid message: {}", e),
+                    },
+                ),
+            },
+        }
+    }
+
+    fn handle_client_message(&mut self, client: ClientId, message: ClientMessage) {
+        self.simconnect.sent.borrow_mut().clear();
+        let (id, result) = match message {
+            ClientMessage::Subscribe {
+                id,
+                name,
+                units,
+                period,
+                epsilon,
+                object,
+            } => {
+                let result = self.subscribe(client, &id, &name, &units, period, epsilon, object);
+                (Some(id), result)
+            }
+            ClientMessage::Unsubscribe { id } => {
+                let result = self.unsubscribe(client, &id);
+                (Some(id), result)
+            }
+            ClientMessage::Set {
+                id,
+                name,
+                units,
+                value,
+                object,
+            } => (id, self.set(&name, &units, &value, object)),
+            ClientMessage::TransmitEvent {
+                id,
+                name,
+                data,
+                object,
+            } => (id, self.transmit_event(&name, data, object)),
+            ClientMessage::SubscribeEvent { id, name } => {
+                let result = self.subscribe_event(client, &id, &name);
+                (Some(id), result)
+            }
+            ClientMessage::UnsubscribeEvent { id } => {
+                let result = self.unsubscribe_event(client, &id);
+                (Some(id), result)
+            }
+            ClientMessage::Facilities { id, kind } => {
+                let result = self.request_facilities(client, &id, kind);
+                (Some(id), result)
+            }
+        };
+
+        // Calls that went out before a failure can still raise exceptions.
+        let sent = self.simconnect.sent.take();
+        for packet_id in sent {
+            self.remember_packet(packet_id, client, id.clone());
+        }
+
+        match result {
+            Ok(()) => {
+                if let Some(id) = id {
+                    self.send(client, &ServerMessage::Ok { id });
+                }
+            }
+            Err(e) => self.send(
+                client,
+                &ServerMessage::Error {
+                    id,
+                    message: e.to_string(),
+                },
+            ),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn subscribe(
+        &mut self,
+        client: ClientId,
+        id: &str,
+        name: &str,
+        units: &str,
+        period: PeriodName,
+        epsilon: Option<f32>,
+        object: u32,
+    ) -> SimConnectResult<()> {
+        let key = (client, id.to_string());
+        if self.client_subscriptions.contains_key(&key) {
+            return Err(SimConnectError::new("Subscription id already in use", None));
+        }
+
+        let simvar = SimVarKey {
+            name: name.to_string(),
+            units: units.to_string(),
+            object_id: object,
+            is_string: units == STRING_UNITS,
+        };
+        let subscriber =
+            self.multiplexer
+                .subscribe(&self.simconnect, simvar, period.as_period(), epsilon)?;
+
+        self.subscribers.insert(subscriber, key.clone());
+        self.client_subscriptions.insert(key, subscriber);
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, client: ClientId, id: &str) -> SimConnectResult<()> {
+        let subscriber = self
+            .client_subscriptions
+            .remove(&(client, id.to_string()))
+            .ok_or_else(|| SimConnectError::new("Unknown subscription id", None))?;
+        self.subscribers.remove(&subscriber);
+        self.multiplexer.unsubscribe(&self.simconnect, subscriber)
+    }
+
+    fn set(&mut self, name: &str, units: &str, value: &Value, object: u32) -> SimConnectResult<()> {
+        let is_string = units == STRING_UNITS;
+        let key = (name.to_string(), units.to_string());
+        let define_id = match self.set_definitions.get(&key) {
+            Some(define_id) => *define_id,
+            None => {
+                let define_id = self.allocate_id();
+                self.add_definition(define_id, name, units, is_string, 0.0)?;
+                self.set_definitions.insert(key, define_id);
+                define_id
+            }
+        };
+
+        let mut buffer = match (is_string, value) {
+            (true, Value::String(s)) if s.len() < STRING_SIZE => {
+                let mut buffer = s.as_bytes().to_vec();
+                buffer.resize(STRING_SIZE, 0);
+                buffer
+            }
+            (false, Value::Number(n)) => n.as_f64().unwrap_or_default().to_le_bytes().to_vec(),
+            (false, Value::Bool(b)) => (*b as u8 as f64).to_le_bytes().to_vec(),
+            _ => return Err(SimConnectError::new("Value does not match units", None)),
+        };
+
+        self.simconnect.set_data_on_sim_object(
+            define_id,
+            object,
+            DataSetFlag::Default,
+            0,
+            buffer.len() as DWORD,
+            buffer.as_mut_ptr() as *mut _,
+        )
+    }
+
+    fn transmit_event(&mut self, name: &str, data: u32, object: u32) -> SimConnectResult<()> {
+        let event_id = match self.key_events.get(name) {
+            Some(event_id) => *event_id,
+            None => {
+                if name.is_empty()
+                    || name.len() >= STRING_SIZE
+                    || !name.bytes().all(|b| b.is_ascii_graphic())
+                {
+                    return Err(SimConnectError::new("Invalid event name", None));
+                }
+                if self.key_events.len() >= MAX_KEY_EVENTS {
+                    return Err(SimConnectError::new("Too many events mapped", None));
+                }
+                let event_id = self.allocate_id();
+                self.simconnect
+                    .map_client_event_to_sim_event(event_id, name)?;
+                self.key_events.insert(name.to_string(), event_id);
+                if let Some(packet_id) = self.simconnect.sent.borrow().last() {
+                    if self.key_event_packets.len() >= SENT_PACKET_HISTORY {
+                        self.key_event_packets.pop_front();
+                    }
+                    self.key_event_packets
+                        .push_back((*packet_id, name.to_string()));
+                }
+                event_id
+            }
+        };
+
+        self.simconnect.transmit_client_event(
+            object,
+            event_id,
+            data,
+            SIMCONNECT_GROUP_PRIORITY_HIGHEST,
+            EventFlag::GroupIdIsPriority,
+        )
+    }
+
+    fn subscribe_event(&mut self, client: ClientId, id: &str, name: &str) -> SimConnectResult<()> {
+        let key = (client, id.to_string());
+        if self.client_system_events.contains_key(&key) {
+            return Err(SimConnectError::new(
+                "Event subscription id already in use",
+                None,
+            ));
+        }
+
+        let event_id = self.allocate_id();
+        self.simconnect.subscribe_to_system_event(event_id, name)?;
+        self.system_events.insert(
+            event_id,
+            EventSubscription {
+                client,
+                id: id.to_string(),
+            },
+        );
+        self.client_system_events.insert(key, event_id);
+        Ok(())
+    }
+
+    fn unsubscribe_event(&mut self, client: ClientId, id: &str) -> SimConnectResult<()> {
+        let event_id = self
+            .client_system_events
+            .remove(&(client, id.to_string()))
+            .ok_or_else(|| SimConnectError::new("Unknown event subscription id", None))?;
+        self.system_events.remove(&event_id);
+        self.simconnect.unsubscribe_from_system_event(event_id)
+    }
+
+    fn request_facilities(
+        &mut self,
+        client: ClientId,
+        id: &str,
+        kind: FacilityKind,
+    ) -> SimConnectResult<()> {
+        let request_id = self.allocate_id();
+        self.simconnect
+            .request_facilities_list(kind.as_list_type(), request_id)?;
+        self.facility_queries.insert(
+            request_id,
+            FacilityQuery {
+                client,
+                id: id.to_string(),
+                items: Vec::new(),
+            },
+        );
+        Ok(())
+    }
+
+    fn disconnect(&mut self, client: ClientId) {
+        self.clients.remove(&client);
+
+        let ids: Vec<String> = self
+            .client_subscriptions
+            .keys()
+            .filter(|(c, _)| *c == client)
+            .map(|(_, id)| id.clone())
+            .collect();
+        for id in ids {
+            let _ = self.unsubscribe(client, &id);
+        }
+
+        let ids: Vec<String> = self
+            .client_system_events
+            .keys()
+            .filter(|(c, _)| *c == client)
+            .map(|(_, id)| id.clone())
+            .collect();
+        for id in ids {
+            let _ = self.unsubscribe_event(client, &id);
+        }
+
+        self.facility_queries.retain(|_, q| q.client != client);
+        self.sent_packets.retain(|p| p.client != client);
+    }
+
+    fn handle_message(&mut self, message: Message) {
+        match message {
+            Message::SimObjectData(_) => self.handle_data(&message),
+            Message::Event(event) => self.handle_event(event, None, None, None),
+            Message::EventFilename {
+                event, file_name, ..
+            } => self.handle_event(event, Some(file_name), None, None),
+            Message::EventFrame {
+                event,
+                frame_rate,
+                sim_speed,
+            } => self.handle_event(event, None, Some(frame_rate), Some(sim_speed)),
+            Message::EventObjectAddRemove { event, .. } => {
+                self.handle_event(event, None, None, None)
+            }
+            Message::AirportList(list) => self.handle_facilities(
+                list.request_id,
+                list.entry_number,
+                list.out_of,
+                list.items
+                    .into_iter()
+                    .map(|f| json!({"icao": f.icao, "latitude": f.latitude, "longitude": f.longitude, "altitude": f.altitude}))
+                    .collect(),
+            ),
+            Message::WaypointList(list) => self.handle_facilities(
+                list.request_id,
+                list.entry_number,
+                list.out_of,
+                list.items
+                    .into_iter()
+                    .map(|f| json!({"icao": f.icao, "latitude": f.latitude, "longitude": f.longitude, "altitude": f.altitude, "mag_var": f.mag_var}))
+                    .collect(),
+            ),
+            Message::NdbList(list) => self.handle_facilities(
+                list.request_id,
+                list.entry_number,
+                list.out_of,
+                list.items
+                    .into_iter()
+                    .map(|f| json!({"icao": f.icao, "latitude": f.latitude, "longitude": f.longitude, "altitude": f.altitude, "mag_var": f.mag_var, "frequency": f.frequency}))
+                    .collect(),
+            ),
+            Message::VorList(list) => self.handle_facilities(
+                list.request_id,
+                list.entry_number,
+                list.out_of,
+                list.items
+                    .into_iter()
+                    .map(|f| json!({"icao": f.icao, "latitude": f.latitude, "longitude": f.longitude, "altitude": f.altitude, "mag_var": f.mag_var, "frequency": f.frequency, "localizer": f.localizer, "glide_slope_angle": f.glide_slope_angle}))
+                    .collect(),
+            ),
+            Message::Exception(exception) => self.handle_exception(exception),
+            _ => {}
+        }
+    }
+
+    fn handle_data(&mut self, message: &Message) {
+        let updates =
+            match self
+                .multiplexer
+                .handle_message(&self.simconnect, message, Instant::now())
+            {
+                Ok(updates) => updates,
+                Err(_) => return,
+            };
+
+        for update in updates {
+            self.send_update(update);
+        }
+    }
+
+    fn send_update(&mut self, update: Update) {
+        let (client, id) = match self.subscribers.get(&update.subscriber) {
+            Some(subscriber) => subscriber.clone(),
+            None => return,
+        };
+
+        // Once subscriptions are dropped by the multiplexer after delivery.
+        if self.multiplexer.key(update.subscriber).is_none() {
+            self.subscribers.remove(&update.subscriber);
+            self.client_subscriptions.remove(&(client, id.clone()));
+        }
+
+        let value = match update.value {
+            SimVarValue::Number(n) => json!(n),
+            SimVarValue::String(s) => Value::String(s),
+        };
+        self.send(
+            client,
+            &ServerMessage::Data {
+                id,
+                object: update.object_id,
+                value,
+            },
+        );
+    }
+
+    fn handle_event(
+        &mut self,
+        event: EventData,
+        file_name: Option<String>,
+        frame_rate: Option<f32>,
+        sim_speed: Option<f32>,
+    ) {
+        if let Some(subscription) = self.system_events.get(&event.event_id) {
+            let (client, message) = (
+                subscription.client,
+                ServerMessage::Event {
+                    id: subscription.id.clone(),
+                    data: event.data,
+                    file_name,
+                    frame_rate,
+                    sim_speed,
+                },
+            );
+            self.send(client, &message);
+        }
+    }
+
+    fn handle_facilities(
+        &mut self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        entry_number: DWORD,
+        out_of: DWORD,
+        items: Vec<Value>,
+    ) {
+        let done = match self.facility_queries.get_mut(&request_id) {
+            Some(query) => {
+                query.items.extend(items);
+                entry_number + 1 >= out_of
+            }
+            None => return,
+        };
+
+        if done {
+            if let Some(query) = self.facility_queries.remove(&request_id) {
+                self.send(
+                    query.client,
+                    &ServerMessage::Facilities {
+                        id: query.id,
+                        items: query.items,
+                    },
+                );
+            }
+        }
+    }
+
+    fn handle_exception(&mut self, exception: ExceptionData) {
+        if let Some(index) = self
+            .key_event_packets
+            .iter()
+            .position(|(packet_id, _)| *packet_id == exception.send_id)
+        {
+            if let Some((_, name)) = self.key_event_packets.remove(index) {
+                self.key_events.remove(&name);
+            }
+        }
+
+        let packet = self
+            .sent_packets
+            .iter()
+            .find(|p| p.packet_id == exception.send_id);
+
+        if let Some(packet) = packet {
+            let (client, message) = (
+                packet.client,
+                ServerMessage::Error {
+                    id: packet.id.clone(),
+                    message: format!("SimConnect exception {}", exception.exception),
+                },
+            );
+            self.send(client, &message);
+        }
+    }
+
+    fn add_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        name: &str,
+        units: &str,
+        is_string: bool,
+        epsilon: f32,
+    ) -> SimConnectResult<()> {
+        let (units, datum_type) = match is_string {
+            true => ("", DataType::String256),
+            false => (units, DataType::Float64),
+        };
+        self.simconnect.add_to_data_definition(
+            define_id,
+            name,
+            units,
+            datum_type,
+            epsilon,
+            SIMCONNECT_UNUSED,
+        )
+    }
+
+    fn allocate_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn remember_packet(&mut self, packet_id: DWORD, client: ClientId, id: Option<String>) {
+        if self.sent_packets.len() >= SENT_PACKET_HISTORY {
+            self.sent_packets.pop_front();
+        }
+        self.sent_packets.push_back(SentPacket {
+            packet_id,
+            client,
+            id,
+        });
+    }
+
+    fn send(&mut self, client: ClientId, message: &ServerMessage) {
+        let text = match serde_json::to_string(message) {
+            Ok(text) => text,
+            Err(_) => return,
+        };
+        if let Some(sender) = self.clients.get(&client) {
+            if sender.send(text).is_err() {
+                self.disconnect(client);
+            }
+        }
+    }
+
+    fn broadcast(&mut self, message: &ServerMessage) {
+        let clients: Vec<ClientId> = self.clients.keys().copied().collect();
+        for client in clients {
+            self.send(client, message);
+        }
+    }
+}
+
+// Remembers the packet id of every call that reached SimConnect, including
+// the ones the multiplexer makes, so exceptions can be traced back to the
+// client request that caused them.
+struct PacketTracker<S: SimConnectApi> {
+    inner: S,
+    sent: RefCell<Vec<DWORD>>,
+}
+
+impl<S: SimConnectApi> PacketTracker<S> {
+    fn track(&self, result: SimConnectResult<()>) -> SimConnectResult<()> {
+        if result.is_ok() {
+            if let Ok(packet_id) = self.inner.get_last_sent_packet_id() {
+                self.sent.borrow_mut().push(packet_id);
+            }
+        }
+        result
+    }
+}
+
+impl<S: SimConnectApi> SimConnectApi for PacketTracker<S> {
+    fn server_info(&self) -> Option<ServerInfo> {
+        self.inner.server_info()
+    }
+
+    fn get_next_message(&self) -> SimConnectResult<Option<Message>> {
+        self.inner.get_next_message()
+    }
+
+    fn get_last_sent_packet_id(&self) -> SimConnectResult<DWORD> {
+        self.inner.get_last_sent_packet_id()
+    }
+
+    fn ai_create_non_atc_aircraft(
+        &self,
+        container_title: &str,
+        tail_number: &str,
+        init_pos: InitPosition,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.ai_create_non_atc_aircraft(
+            container_title,
+            tail_number,
+            init_pos,
+            request_id,
+        ))
+    }
+
+    fn ai_release_control(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.ai_release_control(object_id, request_id))
+    }
+
+    fn ai_remove_object(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.ai_remove_object(object_id, request_id))
+    }
+
+    fn add_to_data_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        datum_name: &str,
+        units_name: &str,
+        datum_type: DataType,
+        epsilon: f32,
+        datum_id: DWORD,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.add_to_data_definition(
+            define_id, datum_name, units_name, datum_type, epsilon, datum_id,
+        ))
+    }
+
+    fn clear_data_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.clear_data_definition(define_id))
+    }
+
+    fn map_client_event_to_sim_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        event_name: &str,
+    ) -> SimConnectResult<()> {
+        self.track(
+            self.inner
+                .map_client_event_to_sim_event(event_id, event_name),
+        )
+    }
+
+    fn request_data_on_sim_object(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        flags: DataRequestFlag,
+        origin: DWORD,
+        interval: DWORD,
+        limit: DWORD,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.request_data_on_sim_object(
+            request_id, define_id, object_id, period, flags, origin, interval, limit,
+        ))
+    }
+
+    fn request_data_on_sim_object_type(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        radius_meters: DWORD,
+        type_: SimObjectType,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.request_data_on_sim_object_type(
+            request_id,
+            define_id,
+            radius_meters,
+            type_,
+        ))
+    }
+
+    fn request_facilities_list(
+        &self,
+        type_: FacilityListType,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.request_facilities_list(type_, request_id))
+    }
+
+    fn request_system_state(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        state: &str,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.request_system_state(request_id, state))
+    }
+
+    fn set_data_on_sim_object(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        flags: DataSetFlag,
+        array_count: DWORD,
+        cb_unit_size: DWORD,
+        data_set: *mut raw::c_void,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.set_data_on_sim_object(
+            define_id,
+            object_id,
+            flags,
+            array_count,
+            cb_unit_size,
+            data_set,
+        ))
+    }
+
+    fn subscribe_to_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        system_event_name: &str,
+    ) -> SimConnectResult<()> {
+        self.track(
+            self.inner
+                .subscribe_to_system_event(event_id, system_event_name),
+        )
+    }
+
+    fn transmit_client_event(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        data: DWORD,
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        flags: EventFlag,
+    ) -> SimConnectResult<()> {
+        self.track(
+            self.inner
+                .transmit_client_event(object_id, event_id, data, group_id, flags),
+        )
+    }
+
+    fn unsubscribe_from_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    ) -> SimConnectResult<()> {
+        self.track(self.inner.unsubscribe_from_system_event(event_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::Replay;
+    use crate::session::{SessionMetadata, SessionWriter};
+    use std::io::Cursor;
+
+    fn server() -> Server<Replay<Cursor<Vec<u8>>>> {
+        let writer = SessionWriter::new(Vec::new(), &SessionMetadata::new("test")).unwrap();
+        Server::new(Replay::open(Cursor::new(writer.finish().unwrap())).unwrap())
+    }
+
+    #[test]
+    fn forgets_event_names_the_sim_refuses() {
+        let mut server = server();
+        server.transmit_event("NOT_AN_EVENT", 0, 0).unwrap();
+        assert!(server.key_events.contains_key("NOT_AN_EVENT"));
+
+        // Replays number every packet 0, the mapping included.
+        server.handle_exception(ExceptionData {
+            exception: 1,
+            send_id: 0,
+            index: 0,
+        });
+        assert!(server.key_events.is_empty());
+        assert!(server.key_event_packets.is_empty());
+    }
+
+    #[test]
+    fn caps_the_event_names_mapped() {
+        let mut server = server();
+        assert!(server.transmit_event("", 0, 0).is_err());
+        assert!(server.transmit_event("AP MASTER", 0, 0).is_err());
+        assert!(server
+            .transmit_event(&"A".repeat(STRING_SIZE), 0, 0)
+            .is_err());
+
+        for i in 0..MAX_KEY_EVENTS {
+            server
+                .transmit_event(&format!("EVENT_{}", i), 0, 0)
+                .unwrap();
+        }
+        assert!(server.transmit_event("ONE_TOO_MANY", 0, 0).is_err());
+        assert!(server.transmit_event("EVENT_0", 0, 0).is_ok());
+        assert_eq!(server.key_event_packets.len(), SENT_PACKET_HISTORY);
+    }
+}
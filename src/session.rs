@@ -0,0 +1,1094 @@
+//! Recorded SimConnect sessions.
+//!
+//! A session file holds every message received on a connection, every call
+//! made on it and user markers, each stamped with the time since recording
+//! started. All integers are little-endian.
+//!
+//! The file starts with a 16 byte header followed by `metadata_len` bytes of
+//! JSON metadata (program name, wall clock start, user properties and the
+//! list of record kinds with their layout):
+//!
+//! | offset | size | field        | notes                      |
+//! |--------|------|--------------|----------------------------|
+//! | 0      | 4    | magic        | `0x4E534353` ("SCSN")      |
+//! | 4      | 2    | version      | format version, 2          |
+//! | 6      | 2    | flags        | reserved, written as 0     |
+//! | 8      | 4    | metadata_len |                            |
+//! | 12     | 4    | reserved     | written as 0               |
+//!
+//! Records are grouped into blocks, each a 48 byte header followed by
+//! `compressed_len` bytes of zlib data:
+//!
+//! | offset | size | field          | notes                       |
+//! |--------|------|----------------|-----------------------------|
+//! | 0      | 4    | magic          | `0x4B424353` ("SCBK")       |
+//! | 4      | 4    | record_count   |                             |
+//! | 8      | 4    | raw_len        | bytes after decompression   |
+//! | 12     | 4    | compressed_len |                             |
+//! | 16     | 8    | first_time     | microseconds, first record  |
+//! | 24     | 8    | last_time      | microseconds, last record   |
+//! | 32     | 8    | first_sim_time | `f64` seconds, NaN if none  |
+//! | 40     | 8    | last_sim_time  | `f64` seconds, NaN if none  |
+//!
+//! Decompressed, a block is a sequence of records, each a 13 byte header
+//! (`kind: u8`, `time: u64` in microseconds, `len: u32`) and `len` bytes of
+//! payload. Record kinds:
+//!
+//! * `0` message: the raw `SIMCONNECT_RECV` bytes as received.
+//! * `1` call: `result: i32` (the HRESULT), `function_len: u16`, the UTF-8
+//!   function name, `arg_count: u16` and the arguments, each a `tag: u8`
+//!   followed by its value: `0` integer (`i64`), `1` float (`f64`), `2` text
+//!   (`len: u32` and UTF-8), `3` struct bytes (`len: u32` and the packed C
+//!   layout), `4` pointer (no value). Version 1 files hold only the result
+//!   and the function name.
+//! * `2` marker: UTF-8 text added by the user.
+//! * `3` sim time: `seconds: f64`, the `ABSOLUTE TIME` simvar, following the
+//!   message it came in. The first and last of a block go in its header.
+//!
+//! Readers skip kinds they do not know. A finished file ends with an index,
+//! `magic: u32` `0x58494353` ("SCIX") and `count: u32` followed by one 44 byte
+//! entry per block (`offset: u64`, `first_time: u64`, `last_time: u64`,
+//! `record_count: u32`, `first_sim_time: f64`, `last_sim_time: f64`), and a
+//! 16 byte trailer (`index_offset: u64`, `reserved: u32`, `magic: u32`
+//! `0x52544353` ("SCTR")). Without a trailer, as left by a crash, readers
+//! rebuild the index by walking the blocks.
+//!
+//! Version 1 files have 32 byte block headers and 28 byte index entries,
+//! without the sim times.
+
+use super::bindings::*;
+#[cfg(feature = "sdk")]
+use super::message::OBJECT_DATA_OFFSET;
+use super::message::{Message, Reader};
+#[cfg(feature = "sdk")]
+use super::simconnect::{SimConnect, Tap};
+use super::types::*;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "sdk")]
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "sdk")]
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const FORMAT_VERSION: u16 = 2;
+pub const FILE_MAGIC: u32 = 0x4E53_4353;
+pub const BLOCK_MAGIC: u32 = 0x4B42_4353;
+pub const INDEX_MAGIC: u32 = 0x5849_4353;
+pub const TRAILER_MAGIC: u32 = 0x5254_4353;
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+const FILE_HEADER_LEN: u64 = 16;
+const BLOCK_HEADER_LEN: usize = 48;
+const BLOCK_HEADER_LEN_V1: usize = 32;
+const RECORD_HEADER_LEN: usize = 13;
+const INDEX_ENTRY_LEN: usize = 44;
+const INDEX_ENTRY_LEN_V1: usize = 28;
+const TRAILER_LEN: u64 = 16;
+
+const KIND_MESSAGE: u8 = 0;
+const KIND_CALL: u8 = 1;
+const KIND_MARKER: u8 = 2;
+const KIND_SIM_TIME: u8 = 3;
+
+const ARG_INT: u8 = 0;
+const ARG_FLOAT: u8 = 1;
+const ARG_TEXT: u8 = 2;
+const ARG_BYTES: u8 = 3;
+const ARG_POINTER: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordSchema {
+    pub kind: u8,
+    pub name: String,
+    pub layout: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub program_name: String,
+    // Wall clock time recording started, in milliseconds since the epoch.
+    pub started_at_ms: u64,
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+    pub records: Vec<RecordSchema>,
+}
+
+impl SessionMetadata {
+    pub fn new(program_name: &str) -> Self {
+        let schema = |kind, name: &str, layout: &str| RecordSchema {
+            kind,
+            name: name.to_string(),
+            layout: layout.to_string(),
+        };
+
+        Self {
+            program_name: program_name.to_string(),
+            started_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            properties: BTreeMap::new(),
+            records: vec![
+                schema(KIND_MESSAGE, "message", "SIMCONNECT_RECV"),
+                schema(
+                    KIND_CALL,
+                    "call",
+                    "result: i32, function_len: u16, function: utf8, arg_count: u16, \
+                     args: [tag: u8 (0 i64, 1 f64, 2 len: u32 utf8, 3 len: u32 bytes, 4 pointer)]",
+                ),
+                schema(KIND_MARKER, "marker", "text: utf8"),
+                schema(KIND_SIM_TIME, "sim_time", "seconds: f64"),
+            ],
+        }
+    }
+
+    pub fn property(mut self, key: &str, value: &str) -> Self {
+        self.properties.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordData {
+    Message(Vec<u8>),
+    Call {
+        function: String,
+        args: Vec<CallArg>,
+        result: HRESULT,
+    },
+    Marker(String),
+    SimTime(f64),
+    Unknown {
+        kind: u8,
+        payload: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub time: Duration,
+    pub data: RecordData,
+}
+
+impl Record {
+    pub fn message(&self) -> Option<SimConnectResult<Message>> {
+        match &self.data {
+            RecordData::Message(bytes) => Some(Message::from_bytes(bytes)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockInfo {
+    pub offset: u64,
+    pub first_time: u64,
+    pub last_time: u64,
+    pub record_count: u32,
+    pub first_sim_time: Option<f64>,
+    pub last_sim_time: Option<f64>,
+}
+
+fn io_error(what: &str, e: io::Error) -> SimConnectError {
+    SimConnectError::new(&format!("{}: {}", what, e), None)
+}
+
+fn format_error(what: &str) -> SimConnectError {
+    SimConnectError::new(&format!("Invalid session file: {}", what), None)
+}
+
+pub struct SessionWriter<W: Write> {
+    out: W,
+    offset: u64,
+    start: Instant,
+    block_size: usize,
+    block: Vec<u8>,
+    block_records: u32,
+    block_first: u64,
+    block_last: u64,
+    block_first_sim: Option<f64>,
+    block_last_sim: Option<f64>,
+    index: Vec<BlockInfo>,
+}
+
+impl<W: Write> SessionWriter<W> {
+    pub fn new(out: W, metadata: &SessionMetadata) -> SimConnectResult<Self> {
+        Self::with_block_size(out, metadata, DEFAULT_BLOCK_SIZE)
+    }
+
+    // Smaller blocks lose less on a crash and seek more precisely, larger
+    // ones compress better.
+    pub fn with_block_size(
+        mut out: W,
+        metadata: &SessionMetadata,
+        block_size: usize,
+    ) -> SimConnectResult<Self> {
+        let json = serde_json::to_vec(metadata)
+            .map_err(|e| SimConnectError::new(&format!("Invalid metadata: {}", e), None))?;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_LEN as usize + json.len());
+        header.extend_from_slice(&FILE_MAGIC.to_le_bytes());
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&json);
+        out.write_all(&header)
+            .map_err(|e| io_error("Failed to write session header", e))?;
+
+        Ok(Self {
+            out,
+            offset: header.len() as u64,
+            start: Instant::now(),
+            block_size: block_size.max(1),
+            block: Vec::new(),
+            block_records: 0,
+            block_first: 0,
+            block_last: 0,
+            block_first_sim: None,
+            block_last_sim: None,
+            index: Vec::new(),
+        })
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn write_message(&mut self, bytes: &[u8]) -> SimConnectResult<()> {
+        self.write_record(KIND_MESSAGE, &[bytes])
+    }
+
+    pub fn write_call(
+        &mut self,
+        function: &str,
+        args: &[CallArg],
+        result: HRESULT,
+    ) -> SimConnectResult<()> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&result.to_le_bytes());
+        payload.extend_from_slice(&(function.len() as u16).to_le_bytes());
+        payload.extend_from_slice(function.as_bytes());
+        payload.extend_from_slice(&(args.len() as u16).to_le_bytes());
+        for arg in args {
+            match arg {
+                CallArg::Int(value) => {
+                    payload.push(ARG_INT);
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+                CallArg::Float(value) => {
+                    payload.push(ARG_FLOAT);
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+                CallArg::Text(text) => {
+                    payload.push(ARG_TEXT);
+                    payload.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                    payload.extend_from_slice(text.as_bytes());
+                }
+                CallArg::Bytes(bytes) => {
+                    payload.push(ARG_BYTES);
+                    payload.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                    payload.extend_from_slice(bytes);
+                }
+                CallArg::Pointer => payload.push(ARG_POINTER),
+            }
+        }
+        self.write_record(KIND_CALL, &[&payload])
+    }
+
+    pub fn write_marker(&mut self, text: &str) -> SimConnectResult<()> {
+        self.write_record(KIND_MARKER, &[text.as_bytes()])
+    }
+
+    pub fn write_sim_time(&mut self, seconds: f64) -> SimConnectResult<()> {
+        self.block_first_sim.get_or_insert(seconds);
+        self.block_last_sim = Some(seconds);
+        self.write_record(KIND_SIM_TIME, &[&seconds.to_le_bytes()])
+    }
+
+    fn write_record(&mut self, kind: u8, parts: &[&[u8]]) -> SimConnectResult<()> {
+        let time = self.start.elapsed().as_micros() as u64;
+        let len: usize = parts.iter().map(|p| p.len()).sum();
+
+        if self.block_records == 0 {
+            self.block_first = time;
+        }
+        self.block_last = time;
+        self.block_records += 1;
+
+        self.block.push(kind);
+        self.block.extend_from_slice(&time.to_le_bytes());
+        self.block.extend_from_slice(&(len as u32).to_le_bytes());
+        for part in parts {
+            self.block.extend_from_slice(part);
+        }
+
+        if self.block.len() >= self.block_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // Writes out the current block, records written so far then survive a
+    // crash.
+    pub fn flush(&mut self) -> SimConnectResult<()> {
+        if self.block_records == 0 {
+            return Ok(());
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&self.block)
+            .map_err(|e| io_error("Failed to compress block", e))?;
+        let compressed = encoder
+            .finish()
+            .map_err(|e| io_error("Failed to compress block", e))?;
+
+        let mut header = Vec::with_capacity(BLOCK_HEADER_LEN);
+        header.extend_from_slice(&BLOCK_MAGIC.to_le_bytes());
+        header.extend_from_slice(&self.block_records.to_le_bytes());
+        header.extend_from_slice(&(self.block.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        header.extend_from_slice(&self.block_first.to_le_bytes());
+        header.extend_from_slice(&self.block_last.to_le_bytes());
+        header.extend_from_slice(&sim_time_bytes(self.block_first_sim));
+        header.extend_from_slice(&sim_time_bytes(self.block_last_sim));
+
+        self.out
+            .write_all(&header)
+            .and_then(|_| self.out.write_all(&compressed))
+            .and_then(|_| self.out.flush())
+            .map_err(|e| io_error("Failed to write block", e))?;
+
+        self.index.push(BlockInfo {
+            offset: self.offset,
+            first_time: self.block_first,
+            last_time: self.block_last,
+            record_count: self.block_records,
+            first_sim_time: self.block_first_sim.take(),
+            last_sim_time: self.block_last_sim.take(),
+        });
+        self.offset += (header.len() + compressed.len()) as u64;
+        self.block.clear();
+        self.block_records = 0;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> SimConnectResult<W> {
+        self.flush()?;
+
+        let mut index = Vec::with_capacity(8 + self.index.len() * INDEX_ENTRY_LEN + 16);
+        index.extend_from_slice(&INDEX_MAGIC.to_le_bytes());
+        index.extend_from_slice(&(self.index.len() as u32).to_le_bytes());
+        for block in &self.index {
+            index.extend_from_slice(&block.offset.to_le_bytes());
+            index.extend_from_slice(&block.first_time.to_le_bytes());
+            index.extend_from_slice(&block.last_time.to_le_bytes());
+            index.extend_from_slice(&block.record_count.to_le_bytes());
+            index.extend_from_slice(&sim_time_bytes(block.first_sim_time));
+            index.extend_from_slice(&sim_time_bytes(block.last_sim_time));
+        }
+        index.extend_from_slice(&self.offset.to_le_bytes());
+        index.extend_from_slice(&0u32.to_le_bytes());
+        index.extend_from_slice(&TRAILER_MAGIC.to_le_bytes());
+
+        self.out
+            .write_all(&index)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| io_error("Failed to write index", e))?;
+        Ok(self.out)
+    }
+}
+
+// Ids the recorder defines and requests `ABSOLUTE TIME` with, at the top of
+// the range to stay clear of the program's own.
+#[cfg(feature = "sdk")]
+const SIM_TIME_ID: DWORD = 0xFFFF_FF00;
+
+#[cfg(feature = "sdk")]
+#[derive(Debug, Clone)]
+pub struct RecorderOptions {
+    sim_time: Option<(SIMCONNECT_DATA_DEFINITION_ID, SIMCONNECT_DATA_REQUEST_ID)>,
+}
+
+#[cfg(feature = "sdk")]
+impl RecorderOptions {
+    pub fn new() -> Self {
+        Self {
+            sim_time: Some((SIM_TIME_ID, SIM_TIME_ID)),
+        }
+    }
+
+    // For programs already using the default ids.
+    pub fn sim_time_ids(
+        mut self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> Self {
+        self.sim_time = Some((define_id, request_id));
+        self
+    }
+
+    // Records without sim time, so the session can only be seeked by
+    // recording time.
+    pub fn without_sim_time(mut self) -> Self {
+        self.sim_time = None;
+        self
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        RecorderOptions::new()
+    }
+}
+
+#[cfg(feature = "sdk")]
+struct Recording<W: Write> {
+    writer: Option<SessionWriter<W>>,
+    // Taps cannot report errors, the first one is kept for `stop`.
+    error: Option<SimConnectError>,
+    sim_time_request: Option<SIMCONNECT_DATA_REQUEST_ID>,
+}
+
+#[cfg(feature = "sdk")]
+impl<W: Write> Recording<W> {
+    fn write(&mut self, f: impl FnOnce(&mut SessionWriter<W>) -> SimConnectResult<()>) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = f(writer) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    // Reads the `SIMCONNECT_RECV_SIMOBJECT_DATA` header in place, every
+    // received message passes through here.
+    fn sim_time(&self, bytes: &[u8]) -> Option<f64> {
+        let request_id = self.sim_time_request?;
+        let r = Reader::new(bytes);
+        let is_data = r.u32(8).ok()? == SIMCONNECT_RECV_ID_SIMCONNECT_RECV_ID_SIMOBJECT_DATA as u32;
+        match is_data && r.u32(12).ok()? == request_id {
+            true => r.f64(OBJECT_DATA_OFFSET).ok(),
+            false => None,
+        }
+    }
+}
+
+#[cfg(feature = "sdk")]
+struct RecorderTap<W: Write>(Rc<RefCell<Recording<W>>>);
+
+#[cfg(feature = "sdk")]
+impl<W: Write> Tap for RecorderTap<W> {
+    fn received(&mut self, bytes: &[u8]) {
+        let mut recording = self.0.borrow_mut();
+        recording.write(|w| w.write_message(bytes));
+        if let Some(seconds) = recording.sim_time(bytes) {
+            recording.write(|w| w.write_sim_time(seconds));
+        }
+    }
+
+    fn called(&mut self, function: &str, args: &[CallArg], result: HRESULT) {
+        self.0
+            .borrow_mut()
+            .write(|w| w.write_call(function, args, result));
+    }
+}
+
+// Records everything passing through a `SimConnect` until stopped. Messages
+// are captured as `get_next_message` returns them. Unless disabled in the
+// options, the recorder requests `ABSOLUTE TIME` every sim frame to stamp
+// the session with sim time. The program sees those replies too and should
+// ignore the request id. Dropping the recorder without `stop` detaches it
+// and flushes, leaving a file without an index that readers recover by
+// walking the blocks.
+#[cfg(feature = "sdk")]
+pub struct Recorder<'a, W: Write> {
+    simconnect: &'a SimConnect,
+    recording: Rc<RefCell<Recording<W>>>,
+    sim_time: Option<(SIMCONNECT_DATA_DEFINITION_ID, SIMCONNECT_DATA_REQUEST_ID)>,
+}
+
+#[cfg(feature = "sdk")]
+impl<'a, W: Write + 'static> Recorder<'a, W> {
+    pub fn start(
+        simconnect: &'a SimConnect,
+        out: W,
+        metadata: &SessionMetadata,
+    ) -> SimConnectResult<Self> {
+        Self::start_with(simconnect, out, metadata, &RecorderOptions::new())
+    }
+
+    pub fn start_with(
+        simconnect: &'a SimConnect,
+        out: W,
+        metadata: &SessionMetadata,
+        options: &RecorderOptions,
+    ) -> SimConnectResult<Self> {
+        let recording = Rc::new(RefCell::new(Recording {
+            writer: Some(SessionWriter::new(out, metadata)?),
+            error: None,
+            sim_time_request: options.sim_time.map(|(_, request_id)| request_id),
+        }));
+        simconnect.set_tap(Some(Box::new(RecorderTap(recording.clone()))));
+        let recorder = Self {
+            simconnect,
+            recording,
+            sim_time: options.sim_time,
+        };
+
+        if let Some((define_id, request_id)) = recorder.sim_time {
+            simconnect.add_to_data_definition(
+                define_id,
+                "ABSOLUTE TIME",
+                "seconds",
+                DataType::Float64,
+                0.0,
+                SIMCONNECT_UNUSED,
+            )?;
+            simconnect.request_data_on_sim_object(
+                request_id,
+                define_id,
+                SIMCONNECT_OBJECT_ID_USER,
+                Period::SimFrame,
+                DataRequestFlag::Changed,
+                0,
+                0,
+                0,
+            )?;
+        }
+        Ok(recorder)
+    }
+
+    pub fn marker(&self, text: &str) {
+        self.recording.borrow_mut().write(|w| w.write_marker(text));
+    }
+
+    pub fn flush(&self) -> SimConnectResult<()> {
+        let mut recording = self.recording.borrow_mut();
+        recording.write(|w| w.flush());
+        match &recording.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    // Detaches from the connection and writes the index.
+    pub fn stop(self) -> SimConnectResult<W> {
+        self.detach();
+        let mut recording = self.recording.borrow_mut();
+        let writer = recording
+            .writer
+            .take()
+            .ok_or_else(|| SimConnectError::new("Recording already stopped", None))?;
+        match recording.error.take() {
+            Some(e) => Err(e),
+            None => writer.finish(),
+        }
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl<W: Write> Recorder<'_, W> {
+    // The tap records these calls, so the recording must not be borrowed.
+    fn detach(&self) {
+        if let Some((define_id, request_id)) = self.sim_time {
+            let _ = self.simconnect.request_data_on_sim_object(
+                request_id,
+                define_id,
+                SIMCONNECT_OBJECT_ID_USER,
+                Period::Never,
+                DataRequestFlag::Default,
+                0,
+                0,
+                0,
+            );
+            let _ = self.simconnect.clear_data_definition(define_id);
+        }
+        self.simconnect.set_tap(None);
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl<W: Write> Drop for Recorder<'_, W> {
+    fn drop(&mut self) {
+        if self.recording.borrow().writer.is_none() {
+            return;
+        }
+        self.detach();
+        self.recording.borrow_mut().write(|w| w.flush());
+    }
+}
+
+pub struct SessionReader<R: Read + Seek> {
+    input: R,
+    version: u16,
+    metadata: SessionMetadata,
+    blocks: Vec<BlockInfo>,
+}
+
+impl<R: Read + Seek> SessionReader<R> {
+    pub fn open(mut input: R) -> SimConnectResult<Self> {
+        let mut header = [0; FILE_HEADER_LEN as usize];
+        read_exact_at(&mut input, 0, &mut header)?;
+        let r = Reader::new(&header);
+        if r.u32(0)? != FILE_MAGIC {
+            return Err(format_error("bad magic"));
+        }
+        let version = r.u16(4)?;
+        if version > FORMAT_VERSION {
+            return Err(format_error(&format!("unsupported version {}", version)));
+        }
+
+        let metadata_len = r.u32(8)? as usize;
+        let mut json = vec![0; metadata_len];
+        input
+            .read_exact(&mut json)
+            .map_err(|e| io_error("Failed to read metadata", e))?;
+        let metadata: SessionMetadata =
+            serde_json::from_slice(&json).map_err(|_| format_error("bad metadata"))?;
+
+        let data_start = FILE_HEADER_LEN + metadata_len as u64;
+        let blocks = match read_index(&mut input, version)? {
+            Some(blocks) => blocks,
+            None => scan_blocks(&mut input, data_start, version)?,
+        };
+
+        Ok(Self {
+            input,
+            version,
+            metadata,
+            blocks,
+        })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn metadata(&self) -> &SessionMetadata {
+        &self.metadata
+    }
+
+    pub fn blocks(&self) -> &[BlockInfo] {
+        &self.blocks
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.blocks.last().map_or(0, |b| b.last_time))
+    }
+
+    pub fn read_block(&mut self, index: usize) -> SimConnectResult<Vec<Record>> {
+        let block = *self
+            .blocks
+            .get(index)
+            .ok_or_else(|| SimConnectError::new("Block index out of range", None))?;
+
+        let mut header = vec![0; block_header_len(self.version)];
+        read_exact_at(&mut self.input, block.offset, &mut header)?;
+        let r = Reader::new(&header);
+        if r.u32(0)? != BLOCK_MAGIC {
+            return Err(format_error("bad block magic"));
+        }
+        let raw_len = r.u32(8)? as usize;
+        let compressed_len = r.u32(12)? as usize;
+
+        let mut compressed = vec![0; compressed_len];
+        self.input
+            .read_exact(&mut compressed)
+            .map_err(|e| io_error("Failed to read block", e))?;
+        let mut raw = Vec::with_capacity(raw_len);
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut raw)
+            .map_err(|_| format_error("corrupt block"))?;
+
+        parse_records(&raw, self.version)
+    }
+
+    pub fn records(&mut self) -> Records<'_, R> {
+        self.seek(Duration::ZERO)
+    }
+
+    // Iterates from the first record at or after `time`, counted from the
+    // start of the recording.
+    pub fn seek(&mut self, time: Duration) -> Records<'_, R> {
+        // Record times are whole microseconds.
+        let time = Duration::from_micros(time.as_micros() as u64);
+        let block = self
+            .blocks
+            .partition_point(|b| Duration::from_micros(b.last_time) < time);
+        self.records_from(block, Start::Time(time))
+    }
+
+    // Iterates from the first sim time record at or after `seconds` of
+    // `ABSOLUTE TIME`. Sim time stands still while paused and jumps when the
+    // clock is changed, so the first block reaching it is used.
+    pub fn seek_sim_time(&mut self, seconds: f64) -> Records<'_, R> {
+        let block = self
+            .blocks
+            .iter()
+            .position(|b| b.last_sim_time.is_some_and(|t| t >= seconds))
+            .unwrap_or(self.blocks.len());
+        self.records_from(block, Start::SimTime(seconds))
+    }
+
+    fn records_from(&mut self, block: usize, start: Start) -> Records<'_, R> {
+        Records {
+            reader: self,
+            block,
+            start,
+            started: false,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+enum Start {
+    Time(Duration),
+    SimTime(f64),
+}
+
+pub struct Records<'a, R: Read + Seek> {
+    reader: &'a mut SessionReader<R>,
+    block: usize,
+    start: Start,
+    started: bool,
+    pending: VecDeque<Record>,
+}
+
+impl<R: Read + Seek> Iterator for Records<'_, R> {
+    type Item = SimConnectResult<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+            if self.block >= self.reader.blocks.len() {
+                return None;
+            }
+
+            let records = match self.reader.read_block(self.block) {
+                Ok(records) => records,
+                Err(e) => {
+                    self.block = self.reader.blocks.len();
+                    return Some(Err(e));
+                }
+            };
+            self.block += 1;
+            for record in records {
+                self.started = self.started
+                    || match (&self.start, &record.data) {
+                        (Start::Time(time), _) => record.time >= *time,
+                        (Start::SimTime(seconds), RecordData::SimTime(t)) => t >= seconds,
+                        (Start::SimTime(_), _) => false,
+                    };
+                if self.started {
+                    self.pending.push_back(record);
+                }
+            }
+        }
+    }
+}
+
+fn read_exact_at<R: Read + Seek>(
+    input: &mut R,
+    offset: u64,
+    buf: &mut [u8],
+) -> SimConnectResult<()> {
+    input
+        .seek(SeekFrom::Start(offset))
+        .and_then(|_| input.read_exact(buf))
+        .map_err(|e| io_error("Failed to read session", e))
+}
+
+fn block_header_len(version: u16) -> usize {
+    match version {
+        1 => BLOCK_HEADER_LEN_V1,
+        _ => BLOCK_HEADER_LEN,
+    }
+}
+
+fn sim_time_bytes(seconds: Option<f64>) -> [u8; 8] {
+    seconds.unwrap_or(f64::NAN).to_le_bytes()
+}
+
+// Sim times of version 1 files and of blocks without any are `None`.
+fn read_sim_time(r: &Reader, offset: usize, version: u16) -> SimConnectResult<Option<f64>> {
+    if version < 2 {
+        return Ok(None);
+    }
+    let seconds = r.f64(offset)?;
+    Ok((!seconds.is_nan()).then_some(seconds))
+}
+
+fn read_index<R: Read + Seek>(
+    input: &mut R,
+    version: u16,
+) -> SimConnectResult<Option<Vec<BlockInfo>>> {
+    let end = input
+        .seek(SeekFrom::End(0))
+        .map_err(|e| io_error("Failed to read session", e))?;
+    if end < FILE_HEADER_LEN + TRAILER_LEN {
+        return Ok(None);
+    }
+
+    let mut trailer = [0; TRAILER_LEN as usize];
+    read_exact_at(input, end - TRAILER_LEN, &mut trailer)?;
+    let r = Reader::new(&trailer);
+    if r.u32(12)? != TRAILER_MAGIC {
+        return Ok(None);
+    }
+    let index_offset = r.u64(0)?;
+
+    let mut head = [0; 8];
+    read_exact_at(input, index_offset, &mut head)?;
+    let r = Reader::new(&head);
+    if r.u32(0)? != INDEX_MAGIC {
+        return Ok(None);
+    }
+    let count = r.u32(4)? as usize;
+
+    let entry_len = match version {
+        1 => INDEX_ENTRY_LEN_V1,
+        _ => INDEX_ENTRY_LEN,
+    };
+    let mut entries = vec![0; count * entry_len];
+    input
+        .read_exact(&mut entries)
+        .map_err(|e| io_error("Failed to read index", e))?;
+    let r = Reader::new(&entries);
+    (0..count)
+        .map(|i| {
+            let base = i * entry_len;
+            Ok(BlockInfo {
+                offset: r.u64(base)?,
+                first_time: r.u64(base + 8)?,
+                last_time: r.u64(base + 16)?,
+                record_count: r.u32(base + 24)?,
+                first_sim_time: read_sim_time(&r, base + 28, version)?,
+                last_sim_time: read_sim_time(&r, base + 36, version)?,
+            })
+        })
+        .collect::<SimConnectResult<Vec<_>>>()
+        .map(Some)
+}
+
+// Walks the blocks one by one, stopping at the first incomplete one.
+fn scan_blocks<R: Read + Seek>(
+    input: &mut R,
+    data_start: u64,
+    version: u16,
+) -> SimConnectResult<Vec<BlockInfo>> {
+    let end = input
+        .seek(SeekFrom::End(0))
+        .map_err(|e| io_error("Failed to read session", e))?;
+    let mut blocks = Vec::new();
+    let mut offset = data_start;
+    let header_len = block_header_len(version);
+
+    while offset + header_len as u64 <= end {
+        let mut header = vec![0; header_len];
+        read_exact_at(input, offset, &mut header)?;
+        let r = Reader::new(&header);
+        if r.u32(0)? != BLOCK_MAGIC {
+            break;
+        }
+        let compressed_len = r.u32(12)? as u64;
+        let next = offset + header_len as u64 + compressed_len;
+        if next > end {
+            break;
+        }
+
+        blocks.push(BlockInfo {
+            offset,
+            first_time: r.u64(16)?,
+            last_time: r.u64(24)?,
+            record_count: r.u32(4)?,
+            first_sim_time: read_sim_time(&r, 32, version)?,
+            last_sim_time: read_sim_time(&r, 40, version)?,
+        });
+        offset = next;
+    }
+
+    Ok(blocks)
+}
+
+fn parse_records(raw: &[u8], version: u16) -> SimConnectResult<Vec<Record>> {
+    let r = Reader::new(raw);
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while offset < raw.len() {
+        let header = r.slice(offset, RECORD_HEADER_LEN)?;
+        let kind = header[0];
+        let time = r.u64(offset + 1)?;
+        let len = r.u32(offset + 9)? as usize;
+        let payload = r.slice(offset + RECORD_HEADER_LEN, len)?;
+        offset += RECORD_HEADER_LEN + len;
+
+        let data = match kind {
+            KIND_MESSAGE => RecordData::Message(payload.to_vec()),
+            KIND_CALL => parse_call(payload, version)?,
+            KIND_MARKER => RecordData::Marker(String::from_utf8_lossy(payload).into_owned()),
+            KIND_SIM_TIME => RecordData::SimTime(Reader::new(payload).f64(0)?),
+            kind => RecordData::Unknown {
+                kind,
+                payload: payload.to_vec(),
+            },
+        };
+        records.push(Record {
+            time: Duration::from_micros(time),
+            data,
+        });
+    }
+
+    Ok(records)
+}
+
+fn parse_call(payload: &[u8], version: u16) -> SimConnectResult<RecordData> {
+    let r = Reader::new(payload);
+    let result = r.u32(0)? as HRESULT;
+    if version < 2 {
+        return Ok(RecordData::Call {
+            function: String::from_utf8_lossy(r.rest(4)).into_owned(),
+            args: Vec::new(),
+            result,
+        });
+    }
+
+    let function_len = r.u16(4)? as usize;
+    let function = String::from_utf8_lossy(r.slice(6, function_len)?).into_owned();
+    let mut offset = 6 + function_len;
+    let count = r.u16(offset)?;
+    offset += 2;
+
+    let mut args = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let tag = r.slice(offset, 1)?[0];
+        offset += 1;
+        let arg = match tag {
+            ARG_INT => {
+                offset += 8;
+                CallArg::Int(r.u64(offset - 8)? as i64)
+            }
+            ARG_FLOAT => {
+                offset += 8;
+                CallArg::Float(r.f64(offset - 8)?)
+            }
+            ARG_TEXT | ARG_BYTES => {
+                let len = r.u32(offset)? as usize;
+                let bytes = r.slice(offset + 4, len)?;
+                offset += 4 + len;
+                match tag {
+                    ARG_TEXT => CallArg::Text(String::from_utf8_lossy(bytes).into_owned()),
+                    _ => CallArg::Bytes(bytes.to_vec()),
+                }
+            }
+            ARG_POINTER => CallArg::Pointer,
+            _ => return Err(format_error("unknown call argument")),
+        };
+        args.push(arg);
+    }
+
+    Ok(RecordData::Call {
+        function,
+        args,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::thread;
+
+    fn marker(text: &str) -> RecordData {
+        RecordData::Marker(text.to_string())
+    }
+
+    fn read_all(bytes: Vec<u8>) -> (SessionReader<Cursor<Vec<u8>>>, Vec<RecordData>) {
+        let mut reader = SessionReader::open(Cursor::new(bytes)).unwrap();
+        let records = reader.records().map(|r| r.unwrap().data).collect();
+        (reader, records)
+    }
+
+    #[test]
+    fn reads_back_what_was_written_and_seeks() {
+        let metadata = SessionMetadata::new("test").property("aircraft", "C172");
+        let mut writer = SessionWriter::with_block_size(Vec::new(), &metadata, 1).unwrap();
+        let args = [
+            CallArg::Int(7),
+            CallArg::Text("PLANE ALTITUDE".to_string()),
+            CallArg::Float(0.5),
+            CallArg::Bytes(vec![1, 2, 3]),
+            CallArg::Pointer,
+        ];
+        writer.write_message(&[1, 2, 3, 4]).unwrap();
+        writer
+            .write_call("SimConnect_AddToDataDefinition", &args, 0)
+            .unwrap();
+        writer.write_marker("a").unwrap();
+        thread::sleep(Duration::from_millis(2));
+        let time = writer.elapsed();
+        writer.write_marker("b").unwrap();
+        writer.write_sim_time(100.0).unwrap();
+        writer.write_marker("c").unwrap();
+        writer.write_sim_time(160.0).unwrap();
+        writer.write_marker("d").unwrap();
+
+        let (mut reader, records) = read_all(writer.finish().unwrap());
+        assert_eq!(reader.version(), FORMAT_VERSION);
+        assert_eq!(reader.metadata(), &metadata);
+        assert_eq!(reader.blocks().len(), 8);
+        assert_eq!(reader.blocks()[4].first_sim_time, Some(100.0));
+        assert_eq!(reader.blocks()[5].last_sim_time, None);
+        assert_eq!(reader.blocks()[6].last_sim_time, Some(160.0));
+        assert_eq!(records.len(), 8);
+        assert_eq!(records[0], RecordData::Message(vec![1, 2, 3, 4]));
+        assert_eq!(
+            records[1],
+            RecordData::Call {
+                function: "SimConnect_AddToDataDefinition".to_string(),
+                args: args.to_vec(),
+                result: 0,
+            }
+        );
+
+        let first = reader.seek(time).next().unwrap().unwrap();
+        assert_eq!(first.data, marker("b"));
+
+        let from_sim_time: Vec<RecordData> = reader
+            .seek_sim_time(150.0)
+            .map(|r| r.unwrap().data)
+            .collect();
+        assert_eq!(from_sim_time, [RecordData::SimTime(160.0), marker("d")]);
+        assert_eq!(reader.seek_sim_time(200.0).count(), 0);
+    }
+
+    #[test]
+    fn recovers_the_blocks_of_a_truncated_file() {
+        let metadata = SessionMetadata::new("test");
+        let mut writer = SessionWriter::with_block_size(Vec::new(), &metadata, 1).unwrap();
+        for text in ["a", "b", "c"] {
+            writer.write_marker(text).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap();
+
+        // Cut into the last block, as a crash while writing it would.
+        let index_offset = Reader::new(&bytes).u64(bytes.len() - 16).unwrap();
+        bytes.truncate(index_offset as usize - 4);
+
+        let (reader, records) = read_all(bytes);
+        assert_eq!(reader.blocks().len(), 2);
+        assert_eq!(records, [marker("a"), marker("b")]);
+    }
+}
@@ -0,0 +1,416 @@
+// Imports SimBrief OFP XML files: the route as a flight plan, and the planned
+// fuel and payload as simvar values. Weights are converted to pounds. How
+// fuel and payload spread over tanks and stations depends on the aircraft,
+// so the caller describes them with `LoadLayout`.
+
+use super::bindings::*;
+use super::pln::{FlightPlan, Position, RouteType, Waypoint, WaypointType};
+use super::simconnect::SimConnectApi;
+use super::state::StateVar;
+use super::tagged::{DatumValue, TaggedDefinition};
+use super::types::*;
+use std::fs;
+use std::path::Path;
+
+const KG_TO_LB: f64 = 2.204_622_621_8;
+
+fn invalid(what: &str) -> SimConnectError {
+    SimConnectError::new(&format!("Invalid OFP: {}", what), None)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfpAirport {
+    pub icao: String,
+    pub name: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation_ft: f64,
+    pub runway: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfpFix {
+    pub ident: String,
+    pub name: Option<String>,
+    pub kind: WaypointType,
+    pub region: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+    // The airway or procedure flown to reach the fix, `None` for direct.
+    pub via: Option<String>,
+    pub sid_star: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OfpFuel {
+    pub ramp_lb: f64,
+    pub takeoff_lb: f64,
+    pub landing_lb: f64,
+    pub taxi_lb: f64,
+    pub enroute_burn_lb: f64,
+    pub contingency_lb: f64,
+    pub alternate_burn_lb: f64,
+    pub reserve_lb: f64,
+    pub extra_lb: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OfpPayload {
+    pub passengers: u32,
+    // Per passenger, including carry-on.
+    pub passenger_weight_lb: f64,
+    pub bags: u32,
+    pub bag_weight_lb: f64,
+    // Bags and freight together.
+    pub cargo_lb: f64,
+    pub payload_lb: f64,
+    pub zero_fuel_weight_lb: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ofp {
+    pub callsign: Option<String>,
+    pub aircraft_icao: Option<String>,
+    pub origin: OfpAirport,
+    pub destination: OfpAirport,
+    pub route: String,
+    pub cruise_altitude_ft: f64,
+    // En route fixes in flying order, without the airports and the top of
+    // climb and descent markers.
+    pub fixes: Vec<OfpFix>,
+    pub fuel: OfpFuel,
+    pub payload: OfpPayload,
+}
+
+impl Ofp {
+    pub fn load(path: impl AsRef<Path>) -> SimConnectResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            SimConnectError::new(&format!("Failed to read {}: {}", path.display(), e), None)
+        })?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> SimConnectResult<Self> {
+        let document = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))
+            .map_err(|e| invalid(&e.to_string()))?;
+        let root = document.root_element();
+        if !root.has_tag_name("OFP") {
+            return Err(invalid("no OFP element"));
+        }
+
+        let weight_scale = match text_at(root, "params/units") {
+            Some(units) if units.eq_ignore_ascii_case("kgs") => KG_TO_LB,
+            Some(units) if units.eq_ignore_ascii_case("lbs") => 1.0,
+            Some(units) => return Err(invalid(&format!("unknown units {:?}", units))),
+            None => return Err(invalid("missing params/units")),
+        };
+        let weight = |path: &str| -> SimConnectResult<f64> {
+            Ok(number_at(root, path)?.unwrap_or(0.0) * weight_scale)
+        };
+
+        let origin = airport(root, "origin")?;
+        let destination = airport(root, "destination")?;
+        let mut fixes = Vec::new();
+        for node in child(root, "navlog")
+            .into_iter()
+            .flat_map(|n| n.children())
+            .filter(|n| n.has_tag_name("fix"))
+        {
+            let ident = required(node, "ident")?;
+            let kind = match required(node, "type")? {
+                "apt" => WaypointType::Airport,
+                "vor" => WaypointType::Vor,
+                "ndb" => WaypointType::Ndb,
+                "wpt" => WaypointType::Intersection,
+                "ltlg" => WaypointType::User,
+                kind => return Err(invalid(&format!("unknown fix type {:?}", kind))),
+            };
+            let is_marker = kind == WaypointType::User && matches!(ident, "TOC" | "TOD");
+            let is_airport = kind == WaypointType::Airport
+                && (ident == origin.icao || ident == destination.icao);
+            if is_marker || is_airport {
+                continue;
+            }
+            fixes.push(OfpFix {
+                ident: ident.to_string(),
+                name: text_at(node, "name").map(str::to_string),
+                kind,
+                region: text_at(node, "icao_region").map(str::to_string),
+                latitude: required_number(node, "pos_lat")?,
+                longitude: required_number(node, "pos_long")?,
+                altitude_ft: number_at(node, "altitude_feet")?.unwrap_or(0.0),
+                via: text_at(node, "via_airway")
+                    .filter(|via| *via != "DCT")
+                    .map(str::to_string),
+                sid_star: text_at(node, "is_sid_star") == Some("1"),
+            });
+        }
+
+        Ok(Self {
+            callsign: text_at(root, "atc/callsign").map(str::to_string),
+            aircraft_icao: text_at(root, "aircraft/icaocode").map(str::to_string),
+            origin,
+            destination,
+            route: text_at(root, "general/route")
+                .unwrap_or_default()
+                .to_string(),
+            cruise_altitude_ft: number_at(root, "general/initial_altitude")?
+                .ok_or_else(|| invalid("missing general/initial_altitude"))?,
+            fixes,
+            fuel: OfpFuel {
+                ramp_lb: weight("fuel/plan_ramp")?,
+                takeoff_lb: weight("fuel/plan_takeoff")?,
+                landing_lb: weight("fuel/plan_landing")?,
+                taxi_lb: weight("fuel/taxi")?,
+                enroute_burn_lb: weight("fuel/enroute_burn")?,
+                contingency_lb: weight("fuel/contingency")?,
+                alternate_burn_lb: weight("fuel/alternate_burn")?,
+                reserve_lb: weight("fuel/reserve")?,
+                extra_lb: weight("fuel/extra")?,
+            },
+            payload: OfpPayload {
+                passengers: number_at(root, "weights/pax_count")?.unwrap_or(0.0) as u32,
+                passenger_weight_lb: weight("weights/pax_weight")?,
+                bags: number_at(root, "weights/bag_count")?.unwrap_or(0.0) as u32,
+                bag_weight_lb: weight("weights/bag_weight")?,
+                cargo_lb: weight("weights/cargo")?,
+                payload_lb: weight("weights/payload")?,
+                zero_fuel_weight_lb: weight("weights/est_zfw")?,
+            },
+        })
+    }
+
+    pub fn flight_plan(&self) -> FlightPlan {
+        let airport = |a: &OfpAirport| {
+            let position = Position::new(a.latitude, a.longitude, a.elevation_ft);
+            Waypoint::airport(&a.icao, position)
+        };
+
+        let mut plan = FlightPlan::new(airport(&self.origin), airport(&self.destination))
+            .cruising_altitude(self.cruise_altitude_ft)
+            .route_type(
+                match self.fixes.iter().any(|f| f.via.is_some() && !f.sid_star) {
+                    true => RouteType::HighAlt,
+                    false => RouteType::Direct,
+                },
+            );
+        plan.description = self.route.clone();
+        plan.departure_name = self.origin.name.clone();
+        plan.departure_spot = self.origin.runway.clone();
+        plan.destination_name = self.destination.name.clone();
+
+        for fix in &self.fixes {
+            let position = Position::new(fix.latitude, fix.longitude, fix.altitude_ft);
+            let mut waypoint = Waypoint::new(&fix.ident, fix.kind, position);
+            if let Some(region) = &fix.region {
+                waypoint = waypoint.region(region);
+            }
+            // Procedures are not airways, the sim works those out itself.
+            if let (Some(via), false) = (&fix.via, fix.sid_star) {
+                waypoint = waypoint.airway(via);
+            }
+            plan = plan.via(waypoint);
+        }
+        plan
+    }
+
+    // Simvar values for the planned ramp fuel and payload.
+    pub fn loading(&self, layout: &LoadLayout) -> Vec<(StateVar, f64)> {
+        let mut values = Vec::new();
+
+        let mut fuel_gal = self.fuel.ramp_lb / layout.fuel_weight_lb_per_gal;
+        for group in &layout.tank_groups {
+            let capacity: f64 = group.iter().map(|t| t.capacity_gal).sum();
+            let share = match capacity > 0.0 {
+                true => (fuel_gal / capacity).min(1.0),
+                false => 0.0,
+            };
+            for tank in group {
+                let var = StateVar::new(&tank.simvar, "gallons");
+                values.push((var, tank.capacity_gal * share));
+            }
+            fuel_gal -= capacity * share;
+        }
+
+        let passengers_lb = self.payload.passengers as f64 * self.payload.passenger_weight_lb;
+        for (kind, total_lb) in [
+            (StationKind::Passengers, passengers_lb),
+            (StationKind::Cargo, self.payload.cargo_lb),
+        ] {
+            let stations: Vec<&PayloadStation> =
+                layout.stations.iter().filter(|s| s.kind == kind).collect();
+            let capacity: f64 = stations.iter().map(|s| s.max_lb).sum();
+            let share = match capacity > 0.0 {
+                true => (total_lb / capacity).min(1.0),
+                false => 0.0,
+            };
+            for station in stations {
+                let name = format!("PAYLOAD STATION WEIGHT:{}", station.index);
+                values.push((StateVar::new(&name, "pounds"), station.max_lb * share));
+            }
+        }
+        values
+    }
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn text_at<'a>(node: roxmltree::Node<'a, '_>, path: &str) -> Option<&'a str> {
+    let mut node = node;
+    for name in path.split('/') {
+        node = child(node, name)?;
+    }
+    node.text().map(str::trim).filter(|t| !t.is_empty())
+}
+
+fn number_at(node: roxmltree::Node, path: &str) -> SimConnectResult<Option<f64>> {
+    text_at(node, path)
+        .map(|text| {
+            text.parse()
+                .map_err(|_| invalid(&format!("bad number {:?} in {}", text, path)))
+        })
+        .transpose()
+}
+
+fn required<'a>(node: roxmltree::Node<'a, '_>, path: &str) -> SimConnectResult<&'a str> {
+    text_at(node, path).ok_or_else(|| invalid(&format!("missing {}", path)))
+}
+
+fn required_number(node: roxmltree::Node, path: &str) -> SimConnectResult<f64> {
+    number_at(node, path)?.ok_or_else(|| invalid(&format!("missing {}", path)))
+}
+
+fn airport(root: roxmltree::Node, name: &str) -> SimConnectResult<OfpAirport> {
+    let node = child(root, name).ok_or_else(|| invalid(&format!("missing {}", name)))?;
+    Ok(OfpAirport {
+        icao: required(node, "icao_code")?.to_string(),
+        name: text_at(node, "name").map(str::to_string),
+        latitude: required_number(node, "pos_lat")?,
+        longitude: required_number(node, "pos_long")?,
+        elevation_ft: number_at(node, "elevation")?.unwrap_or(0.0),
+        runway: text_at(node, "plan_rwy").map(str::to_string),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuelTank {
+    // Such as `FUEL TANK LEFT MAIN QUANTITY`.
+    pub simvar: String,
+    pub capacity_gal: f64,
+}
+
+impl FuelTank {
+    pub fn new(simvar: &str, capacity_gal: f64) -> Self {
+        Self {
+            simvar: simvar.to_string(),
+            capacity_gal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StationKind {
+    Passengers,
+    Cargo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PayloadStation {
+    // The index of `PAYLOAD STATION WEIGHT`, starting at 1.
+    pub index: u32,
+    pub kind: StationKind,
+    pub max_lb: f64,
+}
+
+impl PayloadStation {
+    pub fn new(index: u32, kind: StationKind, max_lb: f64) -> Self {
+        Self {
+            index,
+            kind,
+            max_lb,
+        }
+    }
+}
+
+// Tank groups fill in order, the tanks of a group evenly in proportion to
+// their capacity. Passengers and cargo spread over the stations of their
+// kind in proportion to what each can carry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadLayout {
+    pub tank_groups: Vec<Vec<FuelTank>>,
+    pub stations: Vec<PayloadStation>,
+    pub fuel_weight_lb_per_gal: f64,
+}
+
+impl LoadLayout {
+    pub fn new() -> Self {
+        Self {
+            tank_groups: Vec::new(),
+            stations: Vec::new(),
+            // Jet A, use `FUEL WEIGHT PER GALLON` for the loaded aircraft.
+            fuel_weight_lb_per_gal: 6.7,
+        }
+    }
+
+    pub fn tanks(mut self, tanks: Vec<FuelTank>) -> Self {
+        self.tank_groups.push(tanks);
+        self
+    }
+
+    pub fn station(mut self, station: PayloadStation) -> Self {
+        self.stations.push(station);
+        self
+    }
+
+    pub fn fuel_weight(mut self, lb_per_gal: f64) -> Self {
+        self.fuel_weight_lb_per_gal = lb_per_gal;
+        self
+    }
+}
+
+impl Default for LoadLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Sets the values on the object through a temporary tagged definition,
+// cleared again once the values are sent.
+pub fn apply_loading(
+    simconnect: &impl SimConnectApi,
+    define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    object_id: SIMCONNECT_OBJECT_ID,
+    values: &[(StateVar, f64)],
+) -> SimConnectResult<()> {
+    let mut definition = TaggedDefinition::new(define_id);
+    let result = values
+        .iter()
+        .enumerate()
+        .try_for_each(|(index, (var, _))| {
+            definition.add(
+                simconnect,
+                index as DWORD,
+                &var.name,
+                &var.units,
+                DataType::Float64,
+                0.0,
+            )
+        })
+        .and_then(|_| {
+            let tagged: Vec<(DWORD, DatumValue)> = values
+                .iter()
+                .enumerate()
+                .map(|(index, (_, value))| (index as DWORD, DatumValue::Float64(*value)))
+                .collect();
+            definition.set(simconnect, object_id, &tagged)
+        });
+    simconnect.clear_data_definition(define_id)?;
+    result
+}
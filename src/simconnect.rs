@@ -1,49 +1,213 @@
 // Partially based on https://github.com/Sequal32/simconnect-rust/blob/master/src/lib.rs
 
 use super::bindings::*;
+use super::message::Message;
 use super::types::*;
+#[cfg(feature = "sdk")]
+use std::cell::RefCell;
+#[cfg(feature = "sdk")]
+use std::ffi::CStr;
+#[cfg(feature = "sdk")]
+use std::mem;
 use std::os::raw;
+#[cfg(feature = "sdk")]
 use std::ptr;
+#[cfg(feature = "sdk")]
+use std::thread;
+#[cfg(feature = "sdk")]
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "sdk")]
+const OPEN_POLL: Duration = Duration::from_millis(10);
+
+#[cfg(feature = "sdk")]
 macro_rules! simconnect_call {
-    ($call: expr, $msg: expr) => {
-        unsafe {
-            match $call {
-                0 => Ok(()),
-                r => Err(SimConnectError::new($msg, Some(r))),
-            }
+    ($simconnect: expr, $function: ident($handle: expr $(, $arg: expr)* $(,)?), $msg: expr) => {{
+        let result = unsafe { $function($handle $(, $arg)*) };
+        if $simconnect.tapped() {
+            // The arguments are plain values and `as_c_string!` pointers, so
+            // evaluating them again for the tap is harmless.
+            let args = unsafe { [$(ToCallArg::to_call_arg(&$arg)),*] };
+            $simconnect.called(stringify!($function), &args, result);
+        }
+        match result {
+            0 => Ok(()),
+            r => Err(SimConnectError::new($msg, Some(r))),
+        }
+    }};
+}
+
+// Observes the raw traffic of a connection, used for recording sessions.
+pub trait Tap {
+    fn received(&mut self, bytes: &[u8]);
+    fn called(&mut self, function: &str, args: &[CallArg], result: HRESULT);
+}
+
+#[cfg(feature = "sdk")]
+trait ToCallArg {
+    // C strings must still be alive.
+    unsafe fn to_call_arg(&self) -> CallArg;
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for u32 {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        CallArg::Int(i64::from(*self))
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for i32 {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        CallArg::Int(i64::from(*self))
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for f32 {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        CallArg::Float(f64::from(*self))
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for f64 {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        CallArg::Float(*self)
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for *const raw::c_char {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        match self.is_null() {
+            true => CallArg::Pointer,
+            false => CallArg::Text(CStr::from_ptr(*self).to_string_lossy().into_owned()),
         }
-    };
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl<T> ToCallArg for *mut T {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        CallArg::Pointer
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for DispatchProc {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        CallArg::Pointer
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for GUID {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        struct_bytes(self)
+    }
+}
+
+#[cfg(feature = "sdk")]
+impl ToCallArg for SIMCONNECT_DATA_INITPOSITION {
+    unsafe fn to_call_arg(&self) -> CallArg {
+        struct_bytes(self)
+    }
 }
 
+// Only for the packed SimConnect structs, which have no padding.
+#[cfg(feature = "sdk")]
+unsafe fn struct_bytes<T: Copy>(value: &T) -> CallArg {
+    let bytes = std::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>());
+    CallArg::Bytes(bytes.to_vec())
+}
+
+#[cfg(feature = "sdk")]
 pub struct SimConnect {
     handle: HANDLE,
+    server_info: RefCell<Option<ServerInfo>>,
+    // The open reply read by `open`, handed out by the next `get_next_message`.
+    open_reply: RefCell<Option<Vec<u8>>>,
+    tap: RefCell<Option<Box<dyn Tap>>>,
 }
 
+#[cfg(feature = "sdk")]
 impl SimConnect {
     pub fn new() -> Self {
         Self {
             handle: ptr::null_mut(),
+            server_info: RefCell::new(None),
+            open_reply: RefCell::new(None),
+            tap: RefCell::new(None),
         }
     }
 
     pub fn open(&mut self, program_name: &str) -> SimConnectResult<()> {
-        if !self.opened() {
-            unsafe {
-                SimConnect_Open(
-                    &mut self.handle,
-                    as_c_string!(program_name),
-                    ptr::null_mut(),
-                    0,
-                    ptr::null_mut(),
-                    0,
-                );
-            }
+        self.open_with(program_name, &OpenOptions::new())
+    }
+
+    // Waits for the open reply, so the server is known once this returns.
+    pub fn open_with(&mut self, program_name: &str, options: &OpenOptions) -> SimConnectResult<()> {
+        if self.opened() {
+            return Ok(());
         }
 
-        match self.handle.is_null() {
-            true => Err(SimConnectError::new("Failed to open connection", None)),
-            false => Ok(()),
+        unsafe {
+            SimConnect_Open(
+                &mut self.handle,
+                as_c_string!(program_name),
+                options.window,
+                options.user_message_id,
+                options.event_handle,
+                options.config_index,
+            );
+        }
+        if self.handle.is_null() {
+            return Err(SimConnectError::new("Failed to open connection", None));
+        }
+
+        let result = self.wait_for_open_reply(options.open_timeout);
+        if result.is_err() {
+            let _ = self.close();
+        }
+        result
+    }
+
+    fn wait_for_open_reply(&self, timeout: Duration) -> SimConnectResult<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut data: *mut SIMCONNECT_RECV = ptr::null_mut();
+            let mut cb_data: DWORD = 0;
+            let result =
+                unsafe { SimConnect_GetNextDispatch(self.handle, &mut data, &mut cb_data) };
+
+            if result == 0 && !data.is_null() {
+                let bytes =
+                    unsafe { std::slice::from_raw_parts(data as *const u8, cb_data as usize) };
+                match Message::from_bytes(bytes)? {
+                    Message::Open(open) => {
+                        self.server_info.replace(Some(open.server_info()));
+                        self.open_reply.replace(Some(bytes.to_vec()));
+                        return Ok(());
+                    }
+                    Message::Exception(exception) => {
+                        return Err(SimConnectError::new(
+                            &format!("SimConnect exception {} while opening", exception.exception),
+                            None,
+                        ))
+                    }
+                    // Nothing was requested yet, so nothing else is expected.
+                    _ => continue,
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(SimConnectError::new(
+                    "Timed out waiting for the open reply",
+                    None,
+                ));
+            }
+            thread::sleep(OPEN_POLL);
         }
     }
 
@@ -53,6 +217,8 @@ impl SimConnect {
                 SimConnect_Close(self.handle);
             }
             self.handle = ptr::null_mut();
+            self.server_info.replace(None);
+            self.open_reply.replace(None);
         } else {
             return Err(SimConnectError::new("Connection already closed", None));
         }
@@ -67,6 +233,40 @@ impl SimConnect {
         !self.handle.is_null()
     }
 
+    // Returns the tap it replaces.
+    pub fn set_tap(&self, tap: Option<Box<dyn Tap>>) -> Option<Box<dyn Tap>> {
+        self.tap.replace(tap)
+    }
+
+    fn tapped(&self) -> bool {
+        self.tap.borrow().is_some()
+    }
+
+    fn called(&self, function: &str, args: &[CallArg], result: HRESULT) {
+        if let Some(tap) = self.tap.borrow_mut().as_mut() {
+            tap.called(function, args, result);
+        }
+    }
+
+    // Known from a successful `open` until `close`.
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.server_info.borrow().clone()
+    }
+
+    pub fn require(&self, feature: Feature) -> SimConnectResult<()> {
+        match &*self.server_info.borrow() {
+            Some(info) => match info.support(feature) {
+                Support::Supported => Ok(()),
+                Support::Unsupported => Err(SimConnectError::unsupported(feature, info)),
+                Support::Removed => Err(SimConnectError::removed(feature, info)),
+            },
+            None => Err(SimConnectError::new(
+                &format!("{:?} needs an open connection", feature),
+                None,
+            )),
+        }
+    }
+
     pub fn ai_create_enroute_atc_aircraft(
         &self,
         container_title: &str,
@@ -78,6 +278,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AICreateEnrouteATCAircraft(
                 self.handle,
                 as_c_string!(container_title),
@@ -100,6 +301,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AICreateNonATCAircraft(
                 self.handle,
                 as_c_string!(container_title),
@@ -119,6 +321,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AICreateParkedATCAircraft(
                 self.handle,
                 as_c_string!(container_title),
@@ -137,6 +340,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AICreateSimulatedObject(
                 self.handle,
                 as_c_string!(container_title),
@@ -153,6 +357,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AIReleaseControl(self.handle, object_id, request_id),
             "Failed to release control"
         )
@@ -164,6 +369,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AIRemoveObject(self.handle, object_id, request_id),
             "Failed to remove object"
         )
@@ -176,6 +382,7 @@ impl SimConnect {
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AISetAircraftFlightPlan(
                 self.handle,
                 object_id,
@@ -193,6 +400,7 @@ impl SimConnect {
         maskable: bool,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AddClientEventToNotificationGroup(
                 self.handle,
                 group_id,
@@ -212,6 +420,7 @@ impl SimConnect {
         datum_id: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AddToClientDataDefinition(
                 self.handle,
                 define_id,
@@ -234,6 +443,7 @@ impl SimConnect {
         datum_id: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_AddToDataDefinition(
                 self.handle,
                 define_id,
@@ -253,6 +463,7 @@ impl SimConnect {
         context: *mut raw::c_void,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_CallDispatch(self.handle, dispatch, context),
             "Failed to set call dispatch"
         )
@@ -267,7 +478,9 @@ impl SimConnect {
         bank_deg: f32,
         heading_deg: f32,
     ) -> SimConnectResult<()> {
+        self.require(Feature::Camera6Dof)?;
         simconnect_call!(
+            self,
             SimConnect_CameraSetRelative6DOF(
                 self.handle,
                 delta_x,
@@ -286,6 +499,7 @@ impl SimConnect {
         define_id: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_ClearClientDataDefinition(self.handle, define_id),
             "Failed to clear client data definition"
         )
@@ -296,6 +510,7 @@ impl SimConnect {
         define_id: SIMCONNECT_DATA_DEFINITION_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_ClearDataDefinition(self.handle, define_id),
             "Failed to clear data definition"
         )
@@ -303,6 +518,7 @@ impl SimConnect {
 
     pub fn clear_input_group(&self, group_id: SIMCONNECT_INPUT_GROUP_ID) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_ClearInputGroup(self.handle, group_id),
             "Failed to clear input group"
         )
@@ -313,13 +529,16 @@ impl SimConnect {
         group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_ClearNotificationGroup(self.handle, group_id),
             "Failed to clear notification group"
         )
     }
 
     pub fn complete_custom_missing_action(&self, instance_id: GUID) -> SimConnectResult<()> {
+        self.require(Feature::Missions)?;
         simconnect_call!(
+            self,
             SimConnect_CompleteCustomMissionAction(self.handle, instance_id),
             "Failed to complete custom missing action"
         )
@@ -332,6 +551,7 @@ impl SimConnect {
         flags: CreateClientDataFlag,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_CreateClientData(
                 self.handle,
                 client_data_id,
@@ -343,7 +563,9 @@ impl SimConnect {
     }
 
     pub fn execute_missing_action(&self, instance_id: GUID) -> SimConnectResult<()> {
+        self.require(Feature::Missions)?;
         simconnect_call!(
+            self,
             SimConnect_ExecuteMissionAction(self.handle, instance_id),
             "Failed to execute missing action"
         )
@@ -351,6 +573,7 @@ impl SimConnect {
 
     pub fn flight_load(&self, filename: &str) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_FlightLoad(self.handle, as_c_string!(filename)),
             "Failed to load flight"
         )
@@ -358,6 +581,7 @@ impl SimConnect {
 
     pub fn flight_plan_load(&self, filename: &str) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_FlightPlanLoad(self.handle, as_c_string!(filename)),
             "Failed to load flight plan"
         )
@@ -371,6 +595,7 @@ impl SimConnect {
         flags: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_FlightSave(
                 self.handle,
                 as_c_string!(filename),
@@ -401,17 +626,45 @@ impl SimConnect {
         cb_data: *mut DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_GetNextDispatch(self.handle, data, cb_data),
             "Failed to get next dispatch"
         )
     }
 
+    pub fn get_next_message(&self) -> SimConnectResult<Option<Message>> {
+        if let Some(bytes) = self.open_reply.take() {
+            if let Some(tap) = self.tap.borrow_mut().as_mut() {
+                tap.received(&bytes);
+            }
+            return Message::from_bytes(&bytes).map(Some);
+        }
+
+        let mut data: *mut SIMCONNECT_RECV = ptr::null_mut();
+        let mut cb_data: DWORD = 0;
+        unsafe {
+            // SimConnect_GetNextDispatch returns E_FAIL when the queue is empty.
+            match SimConnect_GetNextDispatch(self.handle, &mut data, &mut cb_data) {
+                0 if !data.is_null() => {
+                    let bytes = std::slice::from_raw_parts(data as *const u8, cb_data as usize);
+                    if let Some(tap) = self.tap.borrow_mut().as_mut() {
+                        tap.received(bytes);
+                    }
+                    let message = Message::from_bytes(bytes)?;
+                    Ok(Some(message))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
     pub fn map_client_data_name_to_id(
         &self,
         client_data_name: &str,
         client_data_id: SIMCONNECT_CLIENT_DATA_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MapClientDataNameToID(
                 self.handle,
                 as_c_string!(client_data_name),
@@ -427,6 +680,7 @@ impl SimConnect {
         event_name: &str,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MapClientEventToSimEvent(self.handle, event_id, as_c_string!(event_name)),
             "Failed to map client event to sim event"
         )
@@ -443,6 +697,7 @@ impl SimConnect {
         maskable: bool,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MapInputEventToClientEvent(
                 self.handle,
                 group_id,
@@ -464,6 +719,7 @@ impl SimConnect {
         data: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MenuAddItem(self.handle, as_c_string!(menu_item), menu_event_id, data),
             "Failed to add menu item"
         )
@@ -477,6 +733,7 @@ impl SimConnect {
         data: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MenuAddSubItem(
                 self.handle,
                 menu_event_id,
@@ -493,6 +750,7 @@ impl SimConnect {
         menu_event_id: SIMCONNECT_CLIENT_EVENT_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MenuDeleteItem(self.handle, menu_event_id),
             "Failed to delete menu item"
         )
@@ -504,6 +762,7 @@ impl SimConnect {
         sub_menu_event_id: SIMCONNECT_CLIENT_EVENT_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_MenuDeleteSubItem(self.handle, menu_event_id, sub_menu_event_id),
             "Failed to delete sub menu item"
         )
@@ -515,6 +774,7 @@ impl SimConnect {
         event_id: SIMCONNECT_CLIENT_EVENT_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RemoveClientEvent(self.handle, group_id, event_id),
             "Failed to remove client event"
         )
@@ -526,6 +786,7 @@ impl SimConnect {
         input_definition: &str,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RemoveInputEvent(self.handle, group_id, as_c_string!(input_definition)),
             "Failed to remove input event"
         )
@@ -543,6 +804,7 @@ impl SimConnect {
         limit: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RequestClientData(
                 self.handle,
                 client_data_id,
@@ -570,6 +832,7 @@ impl SimConnect {
         limit: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RequestDataOnSimObject(
                 self.handle,
                 request_id,
@@ -593,6 +856,7 @@ impl SimConnect {
         type_: SimObjectType,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RequestDataOnSimObjectType(
                 self.handle,
                 request_id,
@@ -609,7 +873,9 @@ impl SimConnect {
         type_: FacilityListType,
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
+        self.require(Feature::FacilitiesList)?;
         simconnect_call!(
+            self,
             SimConnect_RequestFacilitiesList(
                 self.handle,
                 type_ as SIMCONNECT_FACILITY_LIST_TYPE,
@@ -626,6 +892,7 @@ impl SimConnect {
         flags: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RequestNotificationGroup(self.handle, group_id, reserved, flags),
             "Failed to request notification group"
         )
@@ -639,6 +906,7 @@ impl SimConnect {
         key_choice_3: &str,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RequestReservedKey(
                 self.handle,
                 event_id,
@@ -669,6 +937,7 @@ impl SimConnect {
         state: &str,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_RequestSystemState(self.handle, request_id, as_c_string!(state)),
             "Failed to request system state"
         )
@@ -684,6 +953,7 @@ impl SimConnect {
         data_set: *mut raw::c_void,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetClientData(
                 self.handle,
                 client_id,
@@ -707,6 +977,7 @@ impl SimConnect {
         data_set: *mut raw::c_void,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetDataOnSimObject(
                 self.handle,
                 define_id,
@@ -726,6 +997,7 @@ impl SimConnect {
         priority: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetInputGroupPriority(self.handle, group_id, priority),
             "Failed to set input group priority"
         )
@@ -737,6 +1009,7 @@ impl SimConnect {
         state: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetInputGroupState(self.handle, group_id, state),
             "Failed to set input group state"
         )
@@ -748,6 +1021,7 @@ impl SimConnect {
         priority: DWORD,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetNotificationGroupPriority(self.handle, group_id, priority),
             "Failed to set notification group priority"
         )
@@ -759,6 +1033,7 @@ impl SimConnect {
         state: State,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetSystemEventState(self.handle, event_id, state as SIMCONNECT_STATE),
             "Failed to set system event state"
         )
@@ -772,6 +1047,7 @@ impl SimConnect {
         string: &str,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SetSystemState(
                 self.handle,
                 as_c_string!(state),
@@ -788,7 +1064,9 @@ impl SimConnect {
         type_: FacilityListType,
         request_id: SIMCONNECT_DATA_REQUEST_ID,
     ) -> SimConnectResult<()> {
+        self.require(Feature::FacilitiesList)?;
         simconnect_call!(
+            self,
             SimConnect_SubscribeToFacilities(
                 self.handle,
                 type_ as SIMCONNECT_FACILITY_LIST_TYPE,
@@ -804,6 +1082,7 @@ impl SimConnect {
         system_event_name: &str,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_SubscribeToSystemEvent(
                 self.handle,
                 event_id,
@@ -822,6 +1101,7 @@ impl SimConnect {
         flags: EventFlag,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_TransmitClientEvent(
                 self.handle,
                 object_id,
@@ -839,13 +1119,16 @@ impl SimConnect {
         event_id: SIMCONNECT_CLIENT_EVENT_ID,
     ) -> SimConnectResult<()> {
         simconnect_call!(
+            self,
             SimConnect_UnsubscribeFromSystemEvent(self.handle, event_id),
             "Failed to unsubscribe from system event"
         )
     }
 
     pub fn unsubcribe_to_facilities(&self, type_: FacilityListType) -> SimConnectResult<()> {
+        self.require(Feature::FacilitiesList)?;
         simconnect_call!(
+            self,
             SimConnect_UnsubscribeToFacilities(self.handle, type_ as SIMCONNECT_FACILITY_LIST_TYPE),
             "Failed to unsubscribe to facilities"
         )
@@ -857,12 +1140,14 @@ impl SimConnect {
     // TODO: SimConnect_Weather* ??
 }
 
+#[cfg(feature = "sdk")]
 impl Default for SimConnect {
     fn default() -> Self {
         SimConnect::new()
     }
 }
 
+#[cfg(feature = "sdk")]
 impl Drop for SimConnect {
     fn drop(&mut self) {
         if self.opened() {
@@ -870,3 +1155,287 @@ impl Drop for SimConnect {
         }
     }
 }
+
+// The calls shared by a live connection and `replay::Replay`, so code written
+// against it runs on either.
+pub trait SimConnectApi {
+    fn server_info(&self) -> Option<ServerInfo>;
+
+    fn get_next_message(&self) -> SimConnectResult<Option<Message>>;
+
+    fn get_last_sent_packet_id(&self) -> SimConnectResult<DWORD>;
+
+    fn ai_create_non_atc_aircraft(
+        &self,
+        container_title: &str,
+        tail_number: &str,
+        init_pos: InitPosition,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()>;
+
+    fn ai_release_control(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()>;
+
+    fn ai_remove_object(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()>;
+
+    fn add_to_data_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        datum_name: &str,
+        units_name: &str,
+        datum_type: DataType,
+        epsilon: f32,
+        datum_id: DWORD,
+    ) -> SimConnectResult<()>;
+
+    fn clear_data_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    ) -> SimConnectResult<()>;
+
+    fn map_client_event_to_sim_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        event_name: &str,
+    ) -> SimConnectResult<()>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn request_data_on_sim_object(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        flags: DataRequestFlag,
+        origin: DWORD,
+        interval: DWORD,
+        limit: DWORD,
+    ) -> SimConnectResult<()>;
+
+    fn request_data_on_sim_object_type(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        radius_meters: DWORD,
+        type_: SimObjectType,
+    ) -> SimConnectResult<()>;
+
+    fn request_facilities_list(
+        &self,
+        type_: FacilityListType,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()>;
+
+    fn request_system_state(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        state: &str,
+    ) -> SimConnectResult<()>;
+
+    fn set_data_on_sim_object(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        flags: DataSetFlag,
+        array_count: DWORD,
+        cb_unit_size: DWORD,
+        data_set: *mut raw::c_void,
+    ) -> SimConnectResult<()>;
+
+    fn subscribe_to_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        system_event_name: &str,
+    ) -> SimConnectResult<()>;
+
+    fn transmit_client_event(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        data: DWORD,
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        flags: EventFlag,
+    ) -> SimConnectResult<()>;
+
+    fn unsubscribe_from_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    ) -> SimConnectResult<()>;
+}
+
+#[cfg(feature = "sdk")]
+impl SimConnectApi for SimConnect {
+    fn server_info(&self) -> Option<ServerInfo> {
+        SimConnect::server_info(self)
+    }
+
+    fn get_next_message(&self) -> SimConnectResult<Option<Message>> {
+        SimConnect::get_next_message(self)
+    }
+
+    fn get_last_sent_packet_id(&self) -> SimConnectResult<DWORD> {
+        SimConnect::get_last_sent_packet_id(self)
+    }
+
+    fn ai_create_non_atc_aircraft(
+        &self,
+        container_title: &str,
+        tail_number: &str,
+        init_pos: InitPosition,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        SimConnect::ai_create_non_atc_aircraft(
+            self,
+            container_title,
+            tail_number,
+            init_pos,
+            request_id,
+        )
+    }
+
+    fn ai_release_control(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        SimConnect::ai_release_control(self, object_id, request_id)
+    }
+
+    fn ai_remove_object(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        SimConnect::ai_remove_object(self, object_id, request_id)
+    }
+
+    fn add_to_data_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        datum_name: &str,
+        units_name: &str,
+        datum_type: DataType,
+        epsilon: f32,
+        datum_id: DWORD,
+    ) -> SimConnectResult<()> {
+        SimConnect::add_to_data_definition(
+            self, define_id, datum_name, units_name, datum_type, epsilon, datum_id,
+        )
+    }
+
+    fn clear_data_definition(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    ) -> SimConnectResult<()> {
+        SimConnect::clear_data_definition(self, define_id)
+    }
+
+    fn map_client_event_to_sim_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        event_name: &str,
+    ) -> SimConnectResult<()> {
+        SimConnect::map_client_event_to_sim_event(self, event_id, event_name)
+    }
+
+    fn request_data_on_sim_object(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        flags: DataRequestFlag,
+        origin: DWORD,
+        interval: DWORD,
+        limit: DWORD,
+    ) -> SimConnectResult<()> {
+        SimConnect::request_data_on_sim_object(
+            self, request_id, define_id, object_id, period, flags, origin, interval, limit,
+        )
+    }
+
+    fn request_data_on_sim_object_type(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        radius_meters: DWORD,
+        type_: SimObjectType,
+    ) -> SimConnectResult<()> {
+        SimConnect::request_data_on_sim_object_type(
+            self,
+            request_id,
+            define_id,
+            radius_meters,
+            type_,
+        )
+    }
+
+    fn request_facilities_list(
+        &self,
+        type_: FacilityListType,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> SimConnectResult<()> {
+        SimConnect::request_facilities_list(self, type_, request_id)
+    }
+
+    fn request_system_state(
+        &self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        state: &str,
+    ) -> SimConnectResult<()> {
+        SimConnect::request_system_state(self, request_id, state)
+    }
+
+    fn set_data_on_sim_object(
+        &self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        flags: DataSetFlag,
+        array_count: DWORD,
+        cb_unit_size: DWORD,
+        data_set: *mut raw::c_void,
+    ) -> SimConnectResult<()> {
+        SimConnect::set_data_on_sim_object(
+            self,
+            define_id,
+            object_id,
+            flags,
+            array_count,
+            cb_unit_size,
+            data_set,
+        )
+    }
+
+    fn subscribe_to_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        system_event_name: &str,
+    ) -> SimConnectResult<()> {
+        SimConnect::subscribe_to_system_event(self, event_id, system_event_name)
+    }
+
+    fn transmit_client_event(
+        &self,
+        object_id: SIMCONNECT_OBJECT_ID,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        data: DWORD,
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        flags: EventFlag,
+    ) -> SimConnectResult<()> {
+        SimConnect::transmit_client_event(self, object_id, event_id, data, group_id, flags)
+    }
+
+    fn unsubscribe_from_system_event(
+        &self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+    ) -> SimConnectResult<()> {
+        SimConnect::unsubscribe_from_system_event(self, event_id)
+    }
+}
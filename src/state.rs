@@ -0,0 +1,295 @@
+// Keeps the latest value of a configured set of simvars for each tracked
+// object. Every object gets one tagged definition, so all values of an update
+// arrive in a single message and a snapshot never mixes two updates. Pick a
+// period well below `stale_after`.
+
+use super::bindings::*;
+use super::message::Message;
+use super::simconnect::SimConnectApi;
+use super::tagged::{DatumValue, TaggedDefinition};
+use super::types::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateVar {
+    pub name: String,
+    // `"string"` reads the simvar as a string, anything else as a number.
+    pub units: String,
+}
+
+impl StateVar {
+    pub fn new(name: &str, units: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            units: units.to_string(),
+        }
+    }
+
+    pub(crate) fn is_string(&self) -> bool {
+        self.units.eq_ignore_ascii_case("string")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub value: SimVarValue,
+    pub updated: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub object_id: SIMCONNECT_OBJECT_ID,
+    // Bumped by every update, equal revisions mean equal values.
+    pub revision: u64,
+    pub updated: Option<Instant>,
+    pub values: Vec<(String, Option<Sample>)>,
+}
+
+impl Snapshot {
+    pub fn get(&self, name: &str) -> Option<&SimVarValue> {
+        self.values
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .and_then(|(_, s)| s.as_ref())
+            .map(|s| &s.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub object_id: SIMCONNECT_OBJECT_ID,
+    pub name: String,
+    pub old: Option<SimVarValue>,
+    pub new: SimVarValue,
+}
+
+struct Tracked {
+    id: u32,
+    definition: TaggedDefinition,
+    vars: Vec<StateVar>,
+    samples: Vec<Option<Sample>>,
+    revision: u64,
+    updated: Option<Instant>,
+    tracked_since: Instant,
+}
+
+pub struct StateStore {
+    next_id: u32,
+    free_ids: Vec<u32>,
+    stale_after: Duration,
+    objects: HashMap<SIMCONNECT_OBJECT_ID, Tracked>,
+    by_request: HashMap<SIMCONNECT_DATA_REQUEST_ID, SIMCONNECT_OBJECT_ID>,
+}
+
+impl StateStore {
+    // Definition and request ids are both taken from `first_id` upwards, one
+    // per tracked object. Objects without an update for `stale_after` are
+    // reported as stale.
+    pub fn new(first_id: u32, stale_after: Duration) -> Self {
+        Self {
+            next_id: first_id,
+            free_ids: Vec::new(),
+            stale_after,
+            objects: HashMap::new(),
+            by_request: HashMap::new(),
+        }
+    }
+
+    // Tracking an object again replaces its set of simvars.
+    pub fn track(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+        vars: &[StateVar],
+        period: Period,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        if vars.is_empty() {
+            return Err(SimConnectError::new("No simvars to track", None));
+        }
+        if self.objects.contains_key(&object_id) {
+            self.untrack(simconnect, object_id)?;
+        }
+
+        let id = self.free_ids.pop().unwrap_or_else(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+            id
+        });
+
+        let definition = match Self::define(simconnect, id, object_id, vars, period) {
+            Ok(definition) => definition,
+            Err(e) => {
+                let _ = simconnect.clear_data_definition(id);
+                self.free_ids.push(id);
+                return Err(e);
+            }
+        };
+
+        self.objects.insert(
+            object_id,
+            Tracked {
+                id,
+                definition,
+                vars: vars.to_vec(),
+                samples: vec![None; vars.len()],
+                revision: 0,
+                updated: None,
+                tracked_since: now,
+            },
+        );
+        self.by_request.insert(id, object_id);
+        Ok(())
+    }
+
+    fn define(
+        simconnect: &impl SimConnectApi,
+        id: u32,
+        object_id: SIMCONNECT_OBJECT_ID,
+        vars: &[StateVar],
+        period: Period,
+    ) -> SimConnectResult<TaggedDefinition> {
+        let mut definition = TaggedDefinition::new(id);
+        for (datum_id, var) in vars.iter().enumerate() {
+            let (units, datum_type) = match var.is_string() {
+                true => ("", DataType::String256),
+                false => (var.units.as_str(), DataType::Float64),
+            };
+            definition.add(
+                simconnect,
+                datum_id as DWORD,
+                &var.name,
+                units,
+                datum_type,
+                0.0,
+            )?;
+        }
+        // Without the `Changed` flag every period brings an update, which is
+        // what makes staleness detectable for objects sitting still.
+        definition.request(simconnect, id, object_id, period, false)?;
+        Ok(definition)
+    }
+
+    pub fn untrack(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+    ) -> SimConnectResult<()> {
+        let tracked = self
+            .objects
+            .remove(&object_id)
+            .ok_or_else(|| SimConnectError::new("Object is not tracked", None))?;
+        self.by_request.remove(&tracked.id);
+        self.free_ids.push(tracked.id);
+
+        simconnect.request_data_on_sim_object(
+            tracked.id,
+            tracked.id,
+            object_id,
+            Period::Never,
+            DataRequestFlag::Default,
+            0,
+            0,
+            0,
+        )?;
+        simconnect.clear_data_definition(tracked.id)
+    }
+
+    pub fn tracked(&self) -> impl Iterator<Item = SIMCONNECT_OBJECT_ID> + '_ {
+        self.objects.keys().copied()
+    }
+
+    pub fn handle_message(&mut self, message: &Message, now: Instant) -> Vec<Change> {
+        let data = match message {
+            Message::SimObjectData(data) => data,
+            _ => return Vec::new(),
+        };
+        let tracked = match self
+            .by_request
+            .get(&data.request_id)
+            .and_then(|object_id| self.objects.get_mut(object_id))
+        {
+            Some(tracked) => tracked,
+            None => return Vec::new(),
+        };
+        let values = match tracked.definition.decode(data) {
+            Ok(values) => values,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut changes = Vec::new();
+        for (datum_id, value) in values {
+            let index = datum_id as usize;
+            let value = match value {
+                DatumValue::Float64(v) => SimVarValue::Number(v),
+                DatumValue::String(s) => SimVarValue::String(s),
+                _ => continue,
+            };
+            let sample = match tracked.samples.get_mut(index) {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            let old = sample.replace(Sample {
+                value: value.clone(),
+                updated: now,
+            });
+            let old = old.map(|s| s.value);
+            if old.as_ref() != Some(&value) {
+                changes.push(Change {
+                    object_id: data.object_id,
+                    name: tracked.vars[index].name.clone(),
+                    old,
+                    new: value,
+                });
+            }
+        }
+
+        tracked.revision += 1;
+        tracked.updated = Some(now);
+        changes
+    }
+
+    pub fn snapshot(&self, object_id: SIMCONNECT_OBJECT_ID) -> Option<Snapshot> {
+        let tracked = self.objects.get(&object_id)?;
+        Some(Snapshot {
+            object_id,
+            revision: tracked.revision,
+            updated: tracked.updated,
+            values: tracked
+                .vars
+                .iter()
+                .zip(&tracked.samples)
+                .map(|(var, sample)| (var.name.clone(), sample.clone()))
+                .collect(),
+        })
+    }
+
+    pub fn get(&self, object_id: SIMCONNECT_OBJECT_ID, name: &str) -> Option<&Sample> {
+        let tracked = self.objects.get(&object_id)?;
+        let index = tracked
+            .vars
+            .iter()
+            .position(|v| v.name.eq_ignore_ascii_case(name))?;
+        tracked.samples[index].as_ref()
+    }
+
+    pub fn is_stale(&self, object_id: SIMCONNECT_OBJECT_ID, now: Instant) -> bool {
+        match self.objects.get(&object_id) {
+            Some(tracked) => {
+                let since = tracked.updated.unwrap_or(tracked.tracked_since);
+                now.duration_since(since) > self.stale_after
+            }
+            None => true,
+        }
+    }
+
+    pub fn stale(&self, now: Instant) -> Vec<SIMCONNECT_OBJECT_ID> {
+        self.objects
+            .keys()
+            .copied()
+            .filter(|id| self.is_stale(*id, now))
+            .collect()
+    }
+}
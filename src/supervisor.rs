@@ -0,0 +1,676 @@
+// Keeps a connection alive across simulator restarts. Everything registered
+// through the supervisor is recorded and replayed in order after reconnecting.
+// A registration the simulator refuses on replay is skipped and reported, and
+// tried again on the next reconnect unless it is cleared.
+
+use super::bindings::*;
+use super::message::{Message, SystemStateData};
+use super::simconnect::SimConnect;
+use super::types::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const HEARTBEAT: Duration = Duration::from_secs(5);
+const HEARTBEAT_REQUEST_ID: SIMCONNECT_DATA_REQUEST_ID = u32::MAX - 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Registration {
+    DataDefinition {
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        datum_name: String,
+        units_name: String,
+        datum_type: DataType,
+        epsilon: f32,
+        datum_id: DWORD,
+    },
+    DataRequest {
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        flags: DataRequestFlag,
+        origin: DWORD,
+        interval: DWORD,
+        limit: DWORD,
+    },
+    ClientEvent {
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        event_name: String,
+    },
+    NotificationGroupEvent {
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        maskable: bool,
+    },
+    NotificationGroupPriority {
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        priority: DWORD,
+    },
+    InputEvent {
+        group_id: SIMCONNECT_INPUT_GROUP_ID,
+        input_definition: String,
+        down_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        down_value: DWORD,
+        up_event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        up_value: DWORD,
+        maskable: bool,
+    },
+    InputGroupPriority {
+        group_id: SIMCONNECT_INPUT_GROUP_ID,
+        priority: DWORD,
+    },
+    InputGroupState {
+        group_id: SIMCONNECT_INPUT_GROUP_ID,
+        state: DWORD,
+    },
+    SystemEvent {
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        system_event_name: String,
+    },
+    ClientDataName {
+        client_data_name: String,
+        client_data_id: SIMCONNECT_CLIENT_DATA_ID,
+    },
+    ClientDataArea {
+        client_data_id: SIMCONNECT_CLIENT_DATA_ID,
+        size: DWORD,
+        flags: CreateClientDataFlag,
+    },
+    ClientDataDefinition {
+        define_id: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+        offset: DWORD,
+        size_or_type: DWORD,
+        epsilon: f32,
+        datum_id: DWORD,
+    },
+    ClientDataRequest {
+        client_data_id: SIMCONNECT_CLIENT_DATA_ID,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+        period: ClientDataPeriod,
+        flags: ClientDataRequestFlag,
+        origin: DWORD,
+        interval: DWORD,
+        limit: DWORD,
+    },
+}
+
+impl Registration {
+    pub fn apply(&self, simconnect: &SimConnect) -> SimConnectResult<()> {
+        match self {
+            Registration::DataDefinition {
+                define_id,
+                datum_name,
+                units_name,
+                datum_type,
+                epsilon,
+                datum_id,
+            } => simconnect.add_to_data_definition(
+                *define_id,
+                datum_name,
+                units_name,
+                *datum_type,
+                *epsilon,
+                *datum_id,
+            ),
+            Registration::DataRequest {
+                request_id,
+                define_id,
+                object_id,
+                period,
+                flags,
+                origin,
+                interval,
+                limit,
+            } => simconnect.request_data_on_sim_object(
+                *request_id,
+                *define_id,
+                *object_id,
+                *period,
+                *flags,
+                *origin,
+                *interval,
+                *limit,
+            ),
+            Registration::ClientEvent {
+                event_id,
+                event_name,
+            } => simconnect.map_client_event_to_sim_event(*event_id, event_name),
+            Registration::NotificationGroupEvent {
+                group_id,
+                event_id,
+                maskable,
+            } => simconnect.add_client_event_to_notification_group(*group_id, *event_id, *maskable),
+            Registration::NotificationGroupPriority { group_id, priority } => {
+                simconnect.set_notification_group_priority(*group_id, *priority)
+            }
+            Registration::InputEvent {
+                group_id,
+                input_definition,
+                down_event_id,
+                down_value,
+                up_event_id,
+                up_value,
+                maskable,
+            } => simconnect.map_input_event_to_client_event(
+                *group_id,
+                input_definition,
+                *down_event_id,
+                *down_value,
+                *up_event_id,
+                *up_value,
+                *maskable,
+            ),
+            Registration::InputGroupPriority { group_id, priority } => {
+                simconnect.set_input_group_priority(*group_id, *priority)
+            }
+            Registration::InputGroupState { group_id, state } => {
+                simconnect.set_input_group_state(*group_id, *state)
+            }
+            Registration::SystemEvent {
+                event_id,
+                system_event_name,
+            } => simconnect.subscribe_to_system_event(*event_id, system_event_name),
+            Registration::ClientDataName {
+                client_data_name,
+                client_data_id,
+            } => simconnect.map_client_data_name_to_id(client_data_name, *client_data_id),
+            Registration::ClientDataArea {
+                client_data_id,
+                size,
+                flags,
+            } => simconnect.create_client_data(*client_data_id, *size, *flags),
+            Registration::ClientDataDefinition {
+                define_id,
+                offset,
+                size_or_type,
+                epsilon,
+                datum_id,
+            } => simconnect.add_to_client_data_definition(
+                *define_id,
+                *offset,
+                *size_or_type,
+                *epsilon,
+                *datum_id,
+            ),
+            Registration::ClientDataRequest {
+                client_data_id,
+                request_id,
+                define_id,
+                period,
+                flags,
+                origin,
+                interval,
+                limit,
+            } => simconnect.request_client_data(
+                *client_data_id,
+                *request_id,
+                *define_id,
+                *period,
+                *flags,
+                *origin,
+                *interval,
+                *limit,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    Connected,
+    WaitingForSimulator { retry_in: Duration },
+    Closed,
+}
+
+#[derive(Debug, Clone)]
+pub enum SupervisorEvent {
+    StateChanged(ConnectionState),
+    Message(Message),
+    RegistrationFailed {
+        registration: Registration,
+        error: SimConnectError,
+    },
+}
+
+pub struct Supervisor {
+    simconnect: SimConnect,
+    program_name: String,
+    open_options: OpenOptions,
+    registrations: Vec<Registration>,
+    state: ConnectionState,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    backoff: Duration,
+    next_attempt: Option<Instant>,
+    heartbeat: Duration,
+    heartbeat_request_id: SIMCONNECT_DATA_REQUEST_ID,
+    last_heard: Option<Instant>,
+    pending: VecDeque<SupervisorEvent>,
+}
+
+impl Supervisor {
+    pub fn new(program_name: &str) -> Self {
+        Self {
+            simconnect: SimConnect::new(),
+            program_name: program_name.to_string(),
+            open_options: OpenOptions::new(),
+            registrations: Vec::new(),
+            state: ConnectionState::Closed,
+            initial_backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            backoff: INITIAL_BACKOFF,
+            next_attempt: None,
+            heartbeat: HEARTBEAT,
+            heartbeat_request_id: HEARTBEAT_REQUEST_ID,
+            last_heard: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self.backoff = initial;
+        self
+    }
+
+    // How long the connection may stay quiet before it is probed, 5 seconds
+    // by default. The dispatch queue reports a lost connection the same way
+    // as an empty one. Probes are system state requests under `request_id`,
+    // `u32::MAX - 1` by default, whose replies are not passed on.
+    pub fn with_heartbeat(
+        mut self,
+        heartbeat: Duration,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+    ) -> Self {
+        self.heartbeat = heartbeat;
+        self.heartbeat_request_id = request_id;
+        self
+    }
+
+    pub fn with_open_options(mut self, options: OpenOptions) -> Self {
+        self.open_options = options;
+        self
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn registrations(&self) -> &[Registration] {
+        &self.registrations
+    }
+
+    // Calls made directly on the connection are not replayed after a reconnect.
+    pub fn simconnect(&self) -> &SimConnect {
+        &self.simconnect
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.next_attempt = Some(now);
+    }
+
+    pub fn stop(&mut self) {
+        if self.simconnect.opened() {
+            let _ = self.simconnect.close();
+        }
+        self.next_attempt = None;
+        self.set_state(ConnectionState::Closed);
+    }
+
+    pub fn poll(&mut self, now: Instant) -> Option<SupervisorEvent> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+
+        if self.state != ConnectionState::Connected {
+            match self.next_attempt {
+                Some(at) if now >= at => self.connect(now),
+                _ => {}
+            }
+            return self.pending.pop_front();
+        }
+
+        let message = self.simconnect.get_next_message();
+        if let Ok(Some(_)) = message {
+            self.last_heard = Some(now);
+        }
+
+        match message {
+            Ok(Some(Message::Quit)) => {
+                // The simulator is going away, so look for its restart from the shortest wait.
+                self.backoff = self.initial_backoff;
+                self.disconnected(now);
+                self.pending.pop_front()
+            }
+            Ok(Some(Message::SystemState(SystemStateData { request_id, .. })))
+                if request_id == self.heartbeat_request_id =>
+            {
+                None
+            }
+            Ok(Some(message)) => Some(SupervisorEvent::Message(message)),
+            Ok(None) => {
+                self.probe(now);
+                self.pending.pop_front()
+            }
+            Err(_) => {
+                self.disconnected(now);
+                self.pending.pop_front()
+            }
+        }
+    }
+
+    pub fn register(&mut self, registration: Registration, now: Instant) -> SimConnectResult<()> {
+        let result = self.forward(|s| registration.apply(s), now);
+        self.record(registration);
+        result
+    }
+
+    pub fn add_to_data_definition(
+        &mut self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        datum_name: &str,
+        units_name: &str,
+        datum_type: DataType,
+        epsilon: f32,
+        datum_id: DWORD,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.register(
+            Registration::DataDefinition {
+                define_id,
+                datum_name: datum_name.to_string(),
+                units_name: units_name.to_string(),
+                datum_type,
+                epsilon,
+                datum_id,
+            },
+            now,
+        )
+    }
+
+    pub fn request_data_on_sim_object(
+        &mut self,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        flags: DataRequestFlag,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.register(
+            Registration::DataRequest {
+                request_id,
+                define_id,
+                object_id,
+                period,
+                flags,
+                origin: 0,
+                interval: 0,
+                limit: 0,
+            },
+            now,
+        )
+    }
+
+    pub fn map_client_event_to_sim_event(
+        &mut self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        event_name: &str,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.register(
+            Registration::ClientEvent {
+                event_id,
+                event_name: event_name.to_string(),
+            },
+            now,
+        )
+    }
+
+    pub fn subscribe_to_system_event(
+        &mut self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        system_event_name: &str,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.register(
+            Registration::SystemEvent {
+                event_id,
+                system_event_name: system_event_name.to_string(),
+            },
+            now,
+        )
+    }
+
+    pub fn clear_data_definition(
+        &mut self,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        // Requests on the definition would fail when replayed without it.
+        self.registrations.retain(|r| match r {
+            Registration::DataDefinition { define_id: d, .. }
+            | Registration::DataRequest { define_id: d, .. } => *d != define_id,
+            _ => true,
+        });
+        self.forward(|s| s.clear_data_definition(define_id), now)
+    }
+
+    pub fn clear_client_data_definition(
+        &mut self,
+        define_id: SIMCONNECT_CLIENT_DATA_DEFINITION_ID,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.registrations.retain(|r| {
+            !matches!(r, Registration::ClientDataDefinition { define_id: d, .. } if *d == define_id)
+        });
+        self.forward(|s| s.clear_client_data_definition(define_id), now)
+    }
+
+    pub fn clear_notification_group(
+        &mut self,
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.registrations.retain(|r| match r {
+            Registration::NotificationGroupEvent { group_id: g, .. }
+            | Registration::NotificationGroupPriority { group_id: g, .. } => *g != group_id,
+            _ => true,
+        });
+        self.forward(|s| s.clear_notification_group(group_id), now)
+    }
+
+    pub fn remove_client_event(
+        &mut self,
+        group_id: SIMCONNECT_NOTIFICATION_GROUP_ID,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.registrations.retain(|r| {
+            !matches!(r, Registration::NotificationGroupEvent { group_id: g, event_id: e, .. }
+                if *g == group_id && *e == event_id)
+        });
+        self.forward(|s| s.remove_client_event(group_id, event_id), now)
+    }
+
+    pub fn clear_input_group(
+        &mut self,
+        group_id: SIMCONNECT_INPUT_GROUP_ID,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.registrations.retain(|r| match r {
+            Registration::InputEvent { group_id: g, .. }
+            | Registration::InputGroupPriority { group_id: g, .. }
+            | Registration::InputGroupState { group_id: g, .. } => *g != group_id,
+            _ => true,
+        });
+        self.forward(|s| s.clear_input_group(group_id), now)
+    }
+
+    pub fn remove_input_event(
+        &mut self,
+        group_id: SIMCONNECT_INPUT_GROUP_ID,
+        input_definition: &str,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.registrations.retain(|r| {
+            !matches!(r, Registration::InputEvent { group_id: g, input_definition: d, .. }
+                if *g == group_id && d == input_definition)
+        });
+        self.forward(|s| s.remove_input_event(group_id, input_definition), now)
+    }
+
+    pub fn unsubscribe_from_system_event(
+        &mut self,
+        event_id: SIMCONNECT_CLIENT_EVENT_ID,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        self.registrations.retain(
+            |r| !matches!(r, Registration::SystemEvent { event_id: e, .. } if *e == event_id),
+        );
+        self.forward(|s| s.unsubscribe_from_system_event(event_id), now)
+    }
+
+    // Calls that are not part of the replayed state, such as transmitting an
+    // event, still go through here so a failure is noticed as a lost connection.
+    pub fn call<T>(
+        &mut self,
+        f: impl FnOnce(&SimConnect) -> SimConnectResult<T>,
+        now: Instant,
+    ) -> SimConnectResult<T> {
+        if self.state != ConnectionState::Connected {
+            return Err(SimConnectError::new("Not connected to the simulator", None));
+        }
+
+        let result = f(&self.simconnect);
+        if result.is_err() {
+            self.disconnected(now);
+        }
+        result
+    }
+
+    fn forward(
+        &mut self,
+        f: impl FnOnce(&SimConnect) -> SimConnectResult<()>,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        match self.state {
+            // Recorded already, it is applied on the next connect.
+            ConnectionState::Connected => self.call(f, now),
+            _ => Ok(()),
+        }
+    }
+
+    // Sends a request that fails once the connection is gone, if nothing has
+    // been heard for a while.
+    fn probe(&mut self, now: Instant) {
+        match self.last_heard {
+            Some(at) if now.duration_since(at) < self.heartbeat => {}
+            _ => {
+                self.last_heard = Some(now);
+                let request_id = self.heartbeat_request_id;
+                let _ = self.call(|s| s.request_system_state(request_id, "Sim"), now);
+            }
+        }
+    }
+
+    fn record(&mut self, registration: Registration) {
+        match &registration {
+            Registration::DataRequest {
+                request_id, period, ..
+            } => {
+                let request_id = *request_id;
+                self.registrations.retain(|r| {
+                    !matches!(r, Registration::DataRequest { request_id: id, .. } if *id == request_id)
+                });
+                if matches!(period, Period::Never | Period::Once) {
+                    return;
+                }
+            }
+            Registration::ClientDataRequest {
+                request_id, period, ..
+            } => {
+                let request_id = *request_id;
+                self.registrations.retain(|r| {
+                    !matches!(r, Registration::ClientDataRequest { request_id: id, .. } if *id == request_id)
+                });
+                if matches!(period, ClientDataPeriod::Never | ClientDataPeriod::Once) {
+                    return;
+                }
+            }
+            Registration::NotificationGroupPriority { group_id, .. } => {
+                let group_id = *group_id;
+                self.registrations.retain(|r| {
+                    !matches!(r, Registration::NotificationGroupPriority { group_id: g, .. } if *g == group_id)
+                });
+            }
+            Registration::InputGroupPriority { group_id, .. } => {
+                let group_id = *group_id;
+                self.registrations.retain(|r| {
+                    !matches!(r, Registration::InputGroupPriority { group_id: g, .. } if *g == group_id)
+                });
+            }
+            Registration::InputGroupState { group_id, .. } => {
+                let group_id = *group_id;
+                self.registrations.retain(|r| {
+                    !matches!(r, Registration::InputGroupState { group_id: g, .. } if *g == group_id)
+                });
+            }
+            _ => {}
+        }
+
+        self.registrations.push(registration);
+    }
+
+    fn connect(&mut self, now: Instant) {
+        if self
+            .simconnect
+            .open_with(&self.program_name, &self.open_options)
+            .is_err()
+        {
+            self.disconnected(now);
+            return;
+        }
+
+        let failed: Vec<SupervisorEvent> = self
+            .registrations
+            .iter()
+            .filter_map(|r| {
+                r.apply(&self.simconnect)
+                    .err()
+                    .map(|error| SupervisorEvent::RegistrationFailed {
+                        registration: r.clone(),
+                        error,
+                    })
+            })
+            .collect();
+
+        self.backoff = self.initial_backoff;
+        self.next_attempt = None;
+        self.last_heard = Some(now);
+        self.set_state(ConnectionState::Connected);
+        self.pending.extend(failed);
+    }
+
+    fn disconnected(&mut self, now: Instant) {
+        if self.simconnect.opened() {
+            let _ = self.simconnect.close();
+        }
+
+        let retry_in = self.backoff;
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+        self.next_attempt = Some(now + retry_in);
+        self.set_state(ConnectionState::WaitingForSimulator { retry_in });
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        if self.state != state {
+            self.state = state;
+            self.pending.push_back(SupervisorEvent::StateChanged(state));
+        }
+    }
+}
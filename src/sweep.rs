@@ -0,0 +1,376 @@
+// Reassembles the one-message-per-object replies of
+// `request_data_on_sim_object_type` into complete sweeps. `RadiusScanner`
+// repeats a sweep on an interval and diffs consecutive results, alternating
+// between two request ids so late replies to one sweep stay out of the next.
+
+use super::bindings::*;
+use super::message::Message;
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+pub type Decoder<T> = Box<dyn Fn(&[u8]) -> Option<T> + Send>;
+
+#[derive(Debug)]
+pub enum SweepEvent<T> {
+    Complete {
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        objects: Vec<(SIMCONNECT_OBJECT_ID, T)>,
+    },
+    // Carries whatever arrived before the deadline.
+    TimedOut {
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        objects: Vec<(SIMCONNECT_OBJECT_ID, T)>,
+    },
+}
+
+struct Sweep<T> {
+    deadline: Instant,
+    objects: Vec<(SIMCONNECT_OBJECT_ID, T)>,
+    received: DWORD,
+    // Taken from the first reply, replies counting another total belong to
+    // an earlier sweep on the same id.
+    out_of: Option<DWORD>,
+}
+
+pub struct SweepCollector<T> {
+    decode: Decoder<T>,
+    timeout: Duration,
+    sweeps: HashMap<SIMCONNECT_DATA_REQUEST_ID, Sweep<T>>,
+}
+
+impl<T> SweepCollector<T> {
+    // `decode` turns the data of one object into a `T`, objects it rejects
+    // are left out of the sweep.
+    pub fn new(decode: impl Fn(&[u8]) -> Option<T> + Send + 'static, timeout: Duration) -> Self {
+        Self {
+            decode: Box::new(decode),
+            timeout,
+            sweeps: HashMap::new(),
+        }
+    }
+
+    // Sweeps run concurrently as long as they use different request ids.
+    // Starting one with the id of a sweep in progress restarts it, though
+    // late replies to the old sweep are only told apart when they count a
+    // different total, so prefer a fresh id.
+    pub fn start(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        radius_meters: DWORD,
+        type_: SimObjectType,
+        now: Instant,
+    ) -> SimConnectResult<()> {
+        simconnect.request_data_on_sim_object_type(request_id, define_id, radius_meters, type_)?;
+        self.sweeps.insert(
+            request_id,
+            Sweep {
+                deadline: now + self.timeout,
+                objects: Vec::new(),
+                received: 0,
+                out_of: None,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn in_progress(&self, request_id: SIMCONNECT_DATA_REQUEST_ID) -> bool {
+        self.sweeps.contains_key(&request_id)
+    }
+
+    pub fn cancel(&mut self, request_id: SIMCONNECT_DATA_REQUEST_ID) {
+        self.sweeps.remove(&request_id);
+    }
+
+    pub fn handle_message(&mut self, message: &Message) -> Option<SweepEvent<T>> {
+        let data = match message {
+            Message::SimObjectDataByType(data) => data,
+            _ => return None,
+        };
+        let sweep = self.sweeps.get_mut(&data.request_id)?;
+        match sweep.out_of {
+            Some(out_of) if out_of != data.out_of => return None,
+            _ => sweep.out_of = Some(data.out_of),
+        }
+
+        // With nothing in range a single reply arrives with `out_of` at 0.
+        if data.out_of > 0 {
+            sweep.received += 1;
+            if let Some(value) = (self.decode)(&data.data) {
+                sweep.objects.push((data.object_id, value));
+            }
+        }
+
+        // `entry_number` counts from 1 up to `out_of`.
+        let done =
+            data.out_of == 0 || data.entry_number >= data.out_of || sweep.received >= data.out_of;
+        if !done {
+            return None;
+        }
+
+        let sweep = self.sweeps.remove(&data.request_id)?;
+        Some(SweepEvent::Complete {
+            request_id: data.request_id,
+            objects: sweep.objects,
+        })
+    }
+
+    pub fn expire(&mut self, now: Instant) -> Vec<SweepEvent<T>> {
+        let expired: Vec<_> = self
+            .sweeps
+            .iter()
+            .filter(|(_, s)| s.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|request_id| {
+                let sweep = self.sweeps.remove(&request_id)?;
+                Some(SweepEvent::TimedOut {
+                    request_id,
+                    objects: sweep.objects,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct ScanResult<T> {
+    pub objects: Vec<(SIMCONNECT_OBJECT_ID, T)>,
+    pub appeared: Vec<SIMCONNECT_OBJECT_ID>,
+    pub vanished: Vec<SIMCONNECT_OBJECT_ID>,
+}
+
+pub struct RadiusScanner<T> {
+    collector: SweepCollector<T>,
+    request_ids: [SIMCONNECT_DATA_REQUEST_ID; 2],
+    // Index of the id of the current or last sweep.
+    current: usize,
+    define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    radius_meters: DWORD,
+    type_: SimObjectType,
+    interval: Duration,
+    next_scan: Option<Instant>,
+    known: Option<HashSet<SIMCONNECT_OBJECT_ID>>,
+}
+
+impl<T> RadiusScanner<T> {
+    // The scan interval also bounds how long a single sweep may take. Sweeps
+    // take turns on the two request ids, which must differ.
+    pub fn new(
+        request_ids: [SIMCONNECT_DATA_REQUEST_ID; 2],
+        define_id: SIMCONNECT_DATA_DEFINITION_ID,
+        radius_meters: DWORD,
+        type_: SimObjectType,
+        interval: Duration,
+        decode: impl Fn(&[u8]) -> Option<T> + Send + 'static,
+    ) -> Self {
+        Self {
+            collector: SweepCollector::new(decode, interval),
+            request_ids,
+            current: 0,
+            define_id,
+            radius_meters,
+            type_,
+            interval,
+            next_scan: None,
+            known: None,
+        }
+    }
+
+    // Starts a sweep when one is due and none is running. Call regularly.
+    pub fn poll(&mut self, simconnect: &impl SimConnectApi, now: Instant) -> SimConnectResult<()> {
+        // A sweep that timed out is dropped without reporting anything vanished.
+        self.collector.expire(now);
+
+        if self.collector.in_progress(self.request_ids[self.current]) {
+            return Ok(());
+        }
+        if self.next_scan.is_some_and(|next| now < next) {
+            return Ok(());
+        }
+
+        self.next_scan = Some(now + self.interval);
+        self.current = 1 - self.current;
+        self.collector.start(
+            simconnect,
+            self.request_ids[self.current],
+            self.define_id,
+            self.radius_meters,
+            self.type_,
+            now,
+        )
+    }
+
+    pub fn handle_message(&mut self, message: &Message) -> Option<ScanResult<T>> {
+        let objects = match self.collector.handle_message(message)? {
+            SweepEvent::Complete { objects, .. } => objects,
+            SweepEvent::TimedOut { .. } => return None,
+        };
+
+        let current: HashSet<_> = objects.iter().map(|(id, _)| *id).collect();
+        let previous = self.known.replace(current.clone()).unwrap_or_default();
+
+        Some(ScanResult {
+            appeared: objects
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| !previous.contains(id))
+                .collect(),
+            vanished: previous.difference(&current).copied().collect(),
+            objects,
+        })
+    }
+
+    // Forgets the last sweep, so everything is reported as appeared again.
+    pub fn reset(&mut self) {
+        for request_id in self.request_ids {
+            self.collector.cancel(request_id);
+        }
+        self.known = None;
+        self.next_scan = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ObjectData;
+    use crate::replay::Replay;
+    use crate::session::{SessionMetadata, SessionWriter};
+    use std::io::Cursor;
+
+    // Accepts the requests and never answers, replies are made up below.
+    fn simconnect() -> Replay<Cursor<Vec<u8>>> {
+        let writer = SessionWriter::new(Vec::new(), &SessionMetadata::new("test")).unwrap();
+        Replay::open(Cursor::new(writer.finish().unwrap())).unwrap()
+    }
+
+    fn reply(request_id: u32, object_id: u32, entry_number: u32, out_of: u32) -> Message {
+        Message::SimObjectDataByType(ObjectData {
+            request_id,
+            object_id,
+            define_id: 1,
+            flags: 0,
+            entry_number,
+            out_of,
+            define_count: 1,
+            data: object_id.to_le_bytes().to_vec(),
+        })
+    }
+
+    fn decode(data: &[u8]) -> Option<u32> {
+        Some(u32::from_le_bytes(data.get(..4)?.try_into().ok()?))
+    }
+
+    fn ids<T>(objects: &[(SIMCONNECT_OBJECT_ID, T)]) -> Vec<SIMCONNECT_OBJECT_ID> {
+        objects.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn completes_a_sweep_with_nothing_in_range() {
+        let (simconnect, now) = (simconnect(), Instant::now());
+        let mut collector = SweepCollector::new(decode, Duration::from_secs(1));
+        collector
+            .start(&simconnect, 1, 1, 1000, SimObjectType::Aircraft, now)
+            .unwrap();
+
+        match collector.handle_message(&reply(1, 0, 0, 0)) {
+            Some(SweepEvent::Complete {
+                request_id,
+                objects,
+            }) => {
+                assert_eq!(request_id, 1);
+                assert!(objects.is_empty());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(!collector.in_progress(1));
+    }
+
+    #[test]
+    fn keeps_interleaved_sweeps_apart() {
+        let (simconnect, now) = (simconnect(), Instant::now());
+        let mut collector = SweepCollector::new(decode, Duration::from_secs(1));
+        for request_id in [1, 2] {
+            collector
+                .start(&simconnect, request_id, 1, 1000, SimObjectType::All, now)
+                .unwrap();
+        }
+
+        assert!(collector.handle_message(&reply(1, 10, 1, 2)).is_none());
+        assert!(collector.handle_message(&reply(2, 20, 1, 3)).is_none());
+        assert!(collector.handle_message(&reply(2, 21, 2, 3)).is_none());
+        match collector.handle_message(&reply(1, 11, 2, 2)) {
+            Some(SweepEvent::Complete {
+                request_id: 1,
+                objects,
+            }) => {
+                assert_eq!(ids(&objects), vec![10, 11])
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match collector.handle_message(&reply(2, 22, 3, 3)) {
+            Some(SweepEvent::Complete {
+                request_id: 2,
+                objects,
+            }) => {
+                assert_eq!(ids(&objects), vec![20, 21, 22])
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn times_out_with_what_arrived() {
+        let (simconnect, now) = (simconnect(), Instant::now());
+        let mut collector = SweepCollector::new(decode, Duration::from_secs(1));
+        collector
+            .start(&simconnect, 1, 1, 1000, SimObjectType::All, now)
+            .unwrap();
+        collector.handle_message(&reply(1, 10, 1, 3));
+
+        assert!(collector
+            .expire(now + Duration::from_millis(999))
+            .is_empty());
+        match collector.expire(now + Duration::from_secs(1)).pop() {
+            Some(SweepEvent::TimedOut {
+                request_id: 1,
+                objects,
+            }) => {
+                assert_eq!(ids(&objects), vec![10])
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(collector.handle_message(&reply(1, 11, 2, 3)).is_none());
+    }
+
+    #[test]
+    fn keeps_late_replies_out_of_the_next_scan() {
+        let (simconnect, now) = (simconnect(), Instant::now());
+        let interval = Duration::from_secs(1);
+        let mut scanner = RadiusScanner::new([1, 2], 1, 1000, SimObjectType::All, interval, decode);
+
+        scanner.poll(&simconnect, now).unwrap();
+        let first = scanner.handle_message(&reply(2, 10, 1, 2));
+        assert!(first.is_none());
+        let first = scanner.handle_message(&reply(2, 11, 2, 2)).unwrap();
+        assert_eq!(first.appeared.len(), 2);
+
+        // The second sweep times out halfway, the third gets the late rest.
+        scanner.poll(&simconnect, now + interval).unwrap();
+        scanner.handle_message(&reply(1, 10, 1, 2));
+        scanner.poll(&simconnect, now + interval * 2).unwrap();
+        assert!(scanner.handle_message(&reply(1, 11, 2, 2)).is_none());
+
+        scanner.handle_message(&reply(2, 10, 1, 2));
+        let third = scanner.handle_message(&reply(2, 11, 2, 2)).unwrap();
+        assert_eq!(ids(&third.objects), vec![10, 11]);
+        assert!(third.appeared.is_empty() && third.vanished.is_empty());
+    }
+}
@@ -0,0 +1,371 @@
+// Codec for the tagged data format, where every value is preceded by the
+// datum id it was registered with. Requested with `ChangedTagged`, replies
+// only carry the datums that moved.
+
+use super::bindings::*;
+use super::message::{ObjectData, Reader};
+use super::simconnect::SimConnectApi;
+use super::types::*;
+use std::os::raw;
+
+const TAG_SIZE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatumValue {
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    String(String),
+    LatLonAlt {
+        latitude: f64,
+        longitude: f64,
+        altitude: f64,
+    },
+    Xyz {
+        x: f64,
+        y: f64,
+        z: f64,
+    },
+    // InitPosition, MarkerState and Waypoint structs as sent by SimConnect.
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Datum {
+    datum_id: DWORD,
+    datum_type: DataType,
+    size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaggedDefinition {
+    define_id: SIMCONNECT_DATA_DEFINITION_ID,
+    datums: Vec<Datum>,
+}
+
+impl TaggedDefinition {
+    pub fn new(define_id: SIMCONNECT_DATA_DEFINITION_ID) -> Self {
+        Self {
+            define_id,
+            datums: Vec::new(),
+        }
+    }
+
+    pub fn define_id(&self) -> SIMCONNECT_DATA_DEFINITION_ID {
+        self.define_id
+    }
+
+    // Registers the datum with SimConnect and the codec.
+    pub fn add(
+        &mut self,
+        simconnect: &impl SimConnectApi,
+        datum_id: DWORD,
+        datum_name: &str,
+        units_name: &str,
+        datum_type: DataType,
+        epsilon: f32,
+    ) -> SimConnectResult<()> {
+        let datum = self.datum(datum_id, datum_type)?;
+        simconnect.add_to_data_definition(
+            self.define_id,
+            datum_name,
+            units_name,
+            datum_type,
+            epsilon,
+            datum_id,
+        )?;
+        self.datums.push(datum);
+        Ok(())
+    }
+
+    // Only records the datum, for definitions registered elsewhere.
+    pub fn with_datum(mut self, datum_id: DWORD, datum_type: DataType) -> SimConnectResult<Self> {
+        let datum = self.datum(datum_id, datum_type)?;
+        self.datums.push(datum);
+        Ok(self)
+    }
+
+    fn datum(&self, datum_id: DWORD, datum_type: DataType) -> SimConnectResult<Datum> {
+        if datum_id == SIMCONNECT_UNUSED {
+            return Err(SimConnectError::new(
+                "Tagged datums need an explicit datum id",
+                None,
+            ));
+        }
+        if self.find(datum_id).is_some() {
+            return Err(SimConnectError::new(
+                &format!("Datum id {} is already defined", datum_id),
+                None,
+            ));
+        }
+        let size = datum_type.size().ok_or_else(|| {
+            SimConnectError::new(
+                &format!("{:?} cannot be used in tagged data", datum_type),
+                None,
+            )
+        })?;
+        Ok(Datum {
+            datum_id,
+            datum_type,
+            size,
+        })
+    }
+
+    fn find(&self, datum_id: DWORD) -> Option<&Datum> {
+        self.datums.iter().find(|d| d.datum_id == datum_id)
+    }
+
+    pub fn request(
+        &self,
+        simconnect: &impl SimConnectApi,
+        request_id: SIMCONNECT_DATA_REQUEST_ID,
+        object_id: SIMCONNECT_OBJECT_ID,
+        period: Period,
+        changed_only: bool,
+    ) -> SimConnectResult<()> {
+        let flags = match changed_only {
+            true => DataRequestFlag::ChangedTagged,
+            false => DataRequestFlag::Tagged,
+        };
+        simconnect.request_data_on_sim_object(
+            request_id,
+            self.define_id,
+            object_id,
+            period,
+            flags,
+            0,
+            0,
+            0,
+        )
+    }
+
+    pub fn decode(&self, data: &ObjectData) -> SimConnectResult<Vec<(DWORD, DatumValue)>> {
+        let r = Reader::new(&data.data);
+        let mut offset = 0;
+        let mut values = Vec::with_capacity(data.define_count as usize);
+
+        for _ in 0..data.define_count {
+            let datum_id = r.u32(offset)?;
+            let datum = self.find(datum_id).ok_or_else(|| {
+                SimConnectError::new(
+                    &format!("Unknown datum id {} in tagged data", datum_id),
+                    None,
+                )
+            })?;
+            offset += TAG_SIZE;
+            values.push((datum_id, read_value(&r, offset, datum)?));
+            offset += datum.size;
+        }
+
+        Ok(values)
+    }
+
+    pub fn encode(&self, values: &[(DWORD, DatumValue)]) -> SimConnectResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        for (datum_id, value) in values {
+            let datum = self.find(*datum_id).ok_or_else(|| {
+                SimConnectError::new(&format!("Unknown datum id {}", datum_id), None)
+            })?;
+            buffer.extend_from_slice(&datum_id.to_le_bytes());
+            write_value(&mut buffer, datum, value)?;
+        }
+        Ok(buffer)
+    }
+
+    pub fn set(
+        &self,
+        simconnect: &impl SimConnectApi,
+        object_id: SIMCONNECT_OBJECT_ID,
+        values: &[(DWORD, DatumValue)],
+    ) -> SimConnectResult<()> {
+        if values.is_empty() {
+            return Err(SimConnectError::new("Nothing to set", None));
+        }
+        let mut buffer = self.encode(values)?;
+
+        // SimConnect reads `array_count * unit_size` bytes and walks the tags
+        // itself, so mixed sizes are sent as a single unit.
+        let sizes: Vec<_> = values
+            .iter()
+            .filter_map(|(id, _)| self.find(*id))
+            .map(|d| TAG_SIZE + d.size)
+            .collect();
+        let (array_count, unit_size) = match sizes.windows(2).all(|w| w[0] == w[1]) {
+            true => (sizes.len(), sizes.first().copied().unwrap_or(0)),
+            false => (1, buffer.len()),
+        };
+
+        simconnect.set_data_on_sim_object(
+            self.define_id,
+            object_id,
+            DataSetFlag::Tagged,
+            array_count as DWORD,
+            unit_size as DWORD,
+            buffer.as_mut_ptr() as *mut raw::c_void,
+        )
+    }
+}
+
+fn is_string(datum_type: DataType) -> bool {
+    matches!(
+        datum_type,
+        DataType::String8
+            | DataType::String32
+            | DataType::String64
+            | DataType::String128
+            | DataType::String256
+            | DataType::String260
+    )
+}
+
+fn read_value(r: &Reader, offset: usize, datum: &Datum) -> SimConnectResult<DatumValue> {
+    Ok(match datum.datum_type {
+        DataType::Int32 => DatumValue::Int32(r.u32(offset)? as i32),
+        DataType::Int64 => {
+            let mut raw = [0; 8];
+            raw.copy_from_slice(r.slice(offset, 8)?);
+            DatumValue::Int64(i64::from_le_bytes(raw))
+        }
+        DataType::Float32 => DatumValue::Float32(r.f32(offset)?),
+        DataType::Float64 => DatumValue::Float64(r.f64(offset)?),
+        DataType::LatLonAlt => DatumValue::LatLonAlt {
+            latitude: r.f64(offset)?,
+            longitude: r.f64(offset + 8)?,
+            altitude: r.f64(offset + 16)?,
+        },
+        DataType::Xyz => DatumValue::Xyz {
+            x: r.f64(offset)?,
+            y: r.f64(offset + 8)?,
+            z: r.f64(offset + 16)?,
+        },
+        DataType::InitPosition | DataType::MarkerState | DataType::Waypoint => {
+            DatumValue::Bytes(r.slice(offset, datum.size)?.to_vec())
+        }
+        _ => DatumValue::String(r.string(offset, datum.size)?),
+    })
+}
+
+fn write_value(buffer: &mut Vec<u8>, datum: &Datum, value: &DatumValue) -> SimConnectResult<()> {
+    let start = buffer.len();
+    match (datum.datum_type, value) {
+        (DataType::Int32, DatumValue::Int32(v)) => buffer.extend_from_slice(&v.to_le_bytes()),
+        (DataType::Int64, DatumValue::Int64(v)) => buffer.extend_from_slice(&v.to_le_bytes()),
+        (DataType::Float32, DatumValue::Float32(v)) => buffer.extend_from_slice(&v.to_le_bytes()),
+        (DataType::Float64, DatumValue::Float64(v)) => buffer.extend_from_slice(&v.to_le_bytes()),
+        (
+            DataType::LatLonAlt,
+            DatumValue::LatLonAlt {
+                latitude,
+                longitude,
+                altitude,
+            },
+        ) => {
+            for v in [latitude, longitude, altitude] {
+                buffer.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        (DataType::Xyz, DatumValue::Xyz { x, y, z }) => {
+            for v in [x, y, z] {
+                buffer.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        (
+            DataType::InitPosition | DataType::MarkerState | DataType::Waypoint,
+            DatumValue::Bytes(b),
+        ) if b.len() == datum.size => buffer.extend_from_slice(b),
+        (t, DatumValue::String(s)) if is_string(t) && s.len() < datum.size => {
+            buffer.extend_from_slice(s.as_bytes());
+            buffer.resize(start + datum.size, 0);
+        }
+        _ => {
+            return Err(SimConnectError::new(
+                &format!(
+                    "Value {:?} does not fit datum {} of type {:?}",
+                    value, datum.datum_id, datum.datum_type
+                ),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::Replay;
+    use crate::session::{SessionMetadata, SessionWriter};
+    use std::io::Cursor;
+
+    fn definition() -> TaggedDefinition {
+        TaggedDefinition::new(1)
+            .with_datum(10, DataType::Float64)
+            .and_then(|d| d.with_datum(11, DataType::Int32))
+            .and_then(|d| d.with_datum(12, DataType::String32))
+            .and_then(|d| d.with_datum(13, DataType::LatLonAlt))
+            .unwrap()
+    }
+
+    fn object_data(data: Vec<u8>, define_count: DWORD) -> ObjectData {
+        ObjectData {
+            request_id: 1,
+            object_id: 0,
+            define_id: 1,
+            flags: 0,
+            entry_number: 1,
+            out_of: 1,
+            define_count,
+            data,
+        }
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let definition = definition();
+        // Out of definition order and missing a datum, as in a changed reply.
+        let values = vec![
+            (
+                13,
+                DatumValue::LatLonAlt {
+                    latitude: 47.45,
+                    longitude: -122.3,
+                    altitude: 130.0,
+                },
+            ),
+            (12, DatumValue::String("N172SP".to_string())),
+            (10, DatumValue::Float64(1013.25)),
+        ];
+
+        let bytes = definition.encode(&values).unwrap();
+        assert_eq!(bytes.len(), 3 * TAG_SIZE + 24 + 32 + 8);
+        let decoded = definition.decode(&object_data(bytes, 3)).unwrap();
+        assert_eq!(decoded, values);
+    }
+
+    #[test]
+    fn rejects_unknown_and_mismatched_datums() {
+        let definition = definition();
+        assert!(definition.encode(&[(99, DatumValue::Int32(1))]).is_err());
+        assert!(definition
+            .encode(&[(11, DatumValue::Float64(1.0))])
+            .is_err());
+        assert!(definition
+            .encode(&[(12, DatumValue::String("x".repeat(32)))])
+            .is_err());
+
+        let mut bytes = 99u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&1i32.to_le_bytes());
+        assert!(definition.decode(&object_data(bytes, 1)).is_err());
+    }
+
+    #[test]
+    fn refuses_to_set_nothing() {
+        let writer = SessionWriter::new(Vec::new(), &SessionMetadata::new("test")).unwrap();
+        let simconnect = Replay::open(Cursor::new(writer.finish().unwrap())).unwrap();
+        assert!(definition().set(&simconnect, 0, &[]).is_err());
+        assert!(definition()
+            .set(&simconnect, 0, &[(10, DatumValue::Float64(1.0))])
+            .is_ok());
+    }
+}
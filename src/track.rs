@@ -0,0 +1,424 @@
+// Flight tracks of the user aircraft, built from `StateStore` snapshots of
+// `track_vars` and written as GPX 1.1, KML or GeoJSON. Altitudes are written
+// in meters as all three formats expect.
+
+use super::state::{Snapshot, StateVar};
+use super::types::*;
+use std::io::Write;
+use std::time::Duration;
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+const FEET_TO_METERS: f64 = 0.3048;
+
+pub fn track_vars() -> Vec<StateVar> {
+    vec![
+        StateVar::new("PLANE LATITUDE", "degrees"),
+        StateVar::new("PLANE LONGITUDE", "degrees"),
+        StateVar::new("PLANE ALTITUDE", "feet"),
+        StateVar::new("PLANE HEADING DEGREES TRUE", "degrees"),
+        StateVar::new("GROUND VELOCITY", "knots"),
+        StateVar::new("SIM ON GROUND", "bool"),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    // Since the start of the track.
+    pub time: Duration,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+    pub heading: f64,
+    pub ground_speed_kt: f64,
+    pub on_ground: bool,
+}
+
+impl TrackPoint {
+    // `None` until the snapshot holds a position.
+    pub fn from_snapshot(time: Duration, snapshot: &Snapshot) -> Option<Self> {
+        let number = |name| match snapshot.get(name) {
+            Some(SimVarValue::Number(n)) => Some(*n),
+            _ => None,
+        };
+
+        Some(Self {
+            time,
+            latitude: number("PLANE LATITUDE")?,
+            longitude: number("PLANE LONGITUDE")?,
+            altitude_ft: number("PLANE ALTITUDE")?,
+            heading: number("PLANE HEADING DEGREES TRUE").unwrap_or(0.0),
+            ground_speed_kt: number("GROUND VELOCITY").unwrap_or(0.0),
+            on_ground: number("SIM ON GROUND").is_some_and(|n| n != 0.0),
+        })
+    }
+
+    fn altitude_m(&self) -> f64 {
+        self.altitude_ft * FEET_TO_METERS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackEvent {
+    Takeoff,
+    Landing,
+}
+
+impl TrackEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            TrackEvent::Takeoff => "Takeoff",
+            TrackEvent::Landing => "Landing",
+        }
+    }
+}
+
+pub struct TrackBuilder {
+    started_at_ms: u64,
+    settle: Duration,
+    points: Vec<TrackPoint>,
+    events: Vec<(TrackEvent, usize)>,
+    on_ground: Option<bool>,
+    // A change of `SIM ON GROUND` at this point, not yet held for `settle`.
+    pending: Option<usize>,
+}
+
+impl TrackBuilder {
+    // `started_at_ms` is the wall clock time of the start of the track, in
+    // milliseconds since the epoch.
+    pub fn new(started_at_ms: u64) -> Self {
+        Self {
+            started_at_ms,
+            settle: Duration::from_secs(5),
+            points: Vec::new(),
+            events: Vec::new(),
+            on_ground: None,
+            pending: None,
+        }
+    }
+
+    // How long the aircraft has to stay in the air or on the ground for a
+    // takeoff or landing to count, so bounces are not reported.
+    pub fn settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    pub fn push(&mut self, point: TrackPoint) {
+        let index = self.points.len();
+        let on_ground = point.on_ground;
+        let time = point.time;
+        self.points.push(point);
+
+        let settled = match self.on_ground {
+            None => {
+                self.on_ground = Some(on_ground);
+                return;
+            }
+            Some(settled) => settled,
+        };
+
+        match self.pending {
+            Some(_) if on_ground == settled => self.pending = None,
+            None if on_ground != settled => self.pending = Some(index),
+            _ => {}
+        }
+
+        if let Some(start) = self.pending {
+            if time.saturating_sub(self.points[start].time) >= self.settle {
+                let event = match on_ground {
+                    true => TrackEvent::Landing,
+                    false => TrackEvent::Takeoff,
+                };
+                self.events.push((event, start));
+                self.on_ground = Some(on_ground);
+                self.pending = None;
+            }
+        }
+    }
+
+    pub fn push_snapshot(&mut self, time: Duration, snapshot: &Snapshot) {
+        if let Some(point) = TrackPoint::from_snapshot(time, snapshot) {
+            self.push(point);
+        }
+    }
+
+    pub fn build(&self) -> Track {
+        Track {
+            started_at_ms: self.started_at_ms,
+            points: self.points.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub started_at_ms: u64,
+    pub points: Vec<TrackPoint>,
+    // Events with the index of the point they happened at.
+    pub events: Vec<(TrackEvent, usize)>,
+}
+
+impl Track {
+    // Douglas-Peucker, dropping points that stay within `horizontal_m` of the
+    // simplified path and within `vertical_ft` of its altitude. Takeoff and
+    // landing points are always kept.
+    pub fn simplify(&self, horizontal_m: f64, vertical_ft: f64) -> Track {
+        if self.points.len() < 3 {
+            return self.clone();
+        }
+
+        let last = self.points.len() - 1;
+        let mut keep = vec![false; self.points.len()];
+        keep[0] = true;
+        keep[last] = true;
+        for (_, index) in &self.events {
+            keep[*index] = true;
+        }
+
+        let fixed: Vec<usize> = (0..=last).filter(|i| keep[*i]).collect();
+        let mut stack: Vec<(usize, usize)> = fixed.windows(2).map(|w| (w[0], w[1])).collect();
+        while let Some((first, end)) = stack.pop() {
+            let (a, b) = (&self.points[first], &self.points[end]);
+            let farthest = (first + 1..end)
+                .map(|i| {
+                    let (horizontal, vertical) = deviation(a, b, &self.points[i]);
+                    (i, (horizontal / horizontal_m).max(vertical / vertical_ft))
+                })
+                .max_by(|x, y| x.1.total_cmp(&y.1));
+            if let Some((index, error)) = farthest {
+                if error > 1.0 {
+                    keep[index] = true;
+                    stack.push((first, index));
+                    stack.push((index, end));
+                }
+            }
+        }
+
+        let mut remap = vec![0; self.points.len()];
+        let mut points = Vec::new();
+        for (index, point) in self.points.iter().enumerate() {
+            if keep[index] {
+                remap[index] = points.len();
+                points.push(point.clone());
+            }
+        }
+
+        Track {
+            started_at_ms: self.started_at_ms,
+            points,
+            events: self.events.iter().map(|(e, i)| (*e, remap[*i])).collect(),
+        }
+    }
+
+    fn timestamp(&self, point: &TrackPoint) -> String {
+        UtcTime::from_unix_ms(self.started_at_ms + point.time.as_millis() as u64).rfc3339()
+    }
+
+    fn event_points(&self) -> impl Iterator<Item = (TrackEvent, &TrackPoint)> {
+        self.events.iter().map(|(e, i)| (*e, &self.points[*i]))
+    }
+
+    pub fn write_gpx(&self, mut out: impl Write, name: &str) -> SimConnectResult<()> {
+        let mut gpx = String::new();
+        gpx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        gpx.push_str(
+            "<gpx version=\"1.1\" creator=\"simply-simconnect\" \
+             xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+        );
+        gpx.push_str(&format!(
+            "  <metadata>\n    <name>{}</name>\n    <time>{}</time>\n  </metadata>\n",
+            xml_escape(name),
+            UtcTime::from_unix_ms(self.started_at_ms).rfc3339()
+        ));
+        for (event, point) in self.event_points() {
+            gpx.push_str(&format!(
+                "  <wpt lat=\"{:.7}\" lon=\"{:.7}\">\n    <ele>{:.1}</ele>\n    <time>{}</time>\n    <name>{}</name>\n  </wpt>\n",
+                point.latitude,
+                point.longitude,
+                point.altitude_m(),
+                self.timestamp(point),
+                event.name()
+            ));
+        }
+        gpx.push_str(&format!(
+            "  <trk>\n    <name>{}</name>\n    <trkseg>\n",
+            xml_escape(name)
+        ));
+        for point in &self.points {
+            gpx.push_str(&format!(
+                "      <trkpt lat=\"{:.7}\" lon=\"{:.7}\">\n        <ele>{:.1}</ele>\n        <time>{}</time>\n      </trkpt>\n",
+                point.latitude,
+                point.longitude,
+                point.altitude_m(),
+                self.timestamp(point)
+            ));
+        }
+        gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+
+        write_all(&mut out, &gpx)
+    }
+
+    // Uses a `gx:Track` so Google Earth can animate the flight, extruded down
+    // to the ground.
+    pub fn write_kml(&self, mut out: impl Write, name: &str) -> SimConnectResult<()> {
+        let mut kml = String::new();
+        kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        kml.push_str(
+            "<kml xmlns=\"http://www.opengis.net/kml/2.2\" \
+             xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n",
+        );
+        kml.push_str(&format!(
+            "<Document>\n  <name>{}</name>\n",
+            xml_escape(name)
+        ));
+        kml.push_str(&format!(
+            "  <Placemark>\n    <name>{}</name>\n    <gx:Track>\n      <extrude>1</extrude>\n      <altitudeMode>absolute</altitudeMode>\n",
+            xml_escape(name)
+        ));
+        for point in &self.points {
+            kml.push_str(&format!("      <when>{}</when>\n", self.timestamp(point)));
+        }
+        for point in &self.points {
+            kml.push_str(&format!(
+                "      <gx:coord>{:.7} {:.7} {:.1}</gx:coord>\n",
+                point.longitude,
+                point.latitude,
+                point.altitude_m()
+            ));
+        }
+        kml.push_str("    </gx:Track>\n  </Placemark>\n");
+        for (event, point) in self.event_points() {
+            kml.push_str(&format!(
+                "  <Placemark>\n    <name>{}</name>\n    <TimeStamp><when>{}</when></TimeStamp>\n    <Point>\n      <altitudeMode>absolute</altitudeMode>\n      <coordinates>{:.7},{:.7},{:.1}</coordinates>\n    </Point>\n  </Placemark>\n",
+                event.name(),
+                self.timestamp(point),
+                point.longitude,
+                point.latitude,
+                point.altitude_m()
+            ));
+        }
+        kml.push_str("</Document>\n</kml>\n");
+
+        write_all(&mut out, &kml)
+    }
+
+    // A `FeatureCollection` with the track as a `LineString` feature and a
+    // `Point` feature per takeoff and landing. Point times go in the
+    // `coordTimes` property.
+    pub fn write_geojson(&self, out: impl Write, name: &str) -> SimConnectResult<()> {
+        let coordinates: Vec<_> = self
+            .points
+            .iter()
+            .map(|p| serde_json::json!([p.longitude, p.latitude, p.altitude_m()]))
+            .collect();
+        let times: Vec<_> = self.points.iter().map(|p| self.timestamp(p)).collect();
+
+        let mut features = vec![serde_json::json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": coordinates },
+            "properties": { "name": name, "coordTimes": times },
+        })];
+        features.extend(self.event_points().map(|(event, point)| {
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [point.longitude, point.latitude, point.altitude_m()],
+                },
+                "properties": {
+                    "name": event.name(),
+                    "time": self.timestamp(point),
+                },
+            })
+        }));
+
+        let collection = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+        serde_json::to_writer_pretty(out, &collection)
+            .map_err(|e| SimConnectError::new(&format!("Failed to write track: {}", e), None))
+    }
+}
+
+// Distance of `p` from the segment `a`-`b` in meters, and from the altitude
+// interpolated along it in feet.
+fn deviation(a: &TrackPoint, b: &TrackPoint, p: &TrackPoint) -> (f64, f64) {
+    let scale = EARTH_RADIUS_M * std::f64::consts::PI / 180.0;
+    let cos = a.latitude.to_radians().cos();
+    let project = |q: &TrackPoint| {
+        (
+            (q.longitude - a.longitude) * cos * scale,
+            (q.latitude - a.latitude) * scale,
+        )
+    };
+
+    let (bx, by) = project(b);
+    let (px, py) = project(p);
+    let length = bx * bx + by * by;
+    let t = match length > 0.0 {
+        true => ((px * bx + py * by) / length).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    let horizontal = (px - t * bx).hypot(py - t * by);
+    let vertical = (p.altitude_ft - (a.altitude_ft + t * (b.altitude_ft - a.altitude_ft))).abs();
+    (horizontal, vertical)
+}
+
+fn write_all(out: &mut impl Write, text: &str) -> SimConnectResult<()> {
+    out.write_all(text.as_bytes())
+        .map_err(|e| SimConnectError::new(&format!("Failed to write track: {}", e), None))
+}
+
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UtcTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl UtcTime {
+    pub fn from_unix_ms(ms: u64) -> Self {
+        let seconds = ms / 1000;
+        let days = (seconds / 86_400) as i64;
+        let time = seconds % 86_400;
+
+        // Civil date from days since 1970-01-01, after Howard Hinnant.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (time / 3600) as u32,
+            minute: (time % 3600 / 60) as u32,
+            second: (time % 60) as u32,
+        }
+    }
+
+    pub fn rfc3339(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
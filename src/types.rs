@@ -1,11 +1,14 @@
 use super::bindings::*;
+use std::time::Duration;
 
+#[cfg(feature = "sdk")]
 macro_rules! as_c_string {
     ($target:expr) => {
         std::ffi::CString::new($target).unwrap().as_ptr()
     };
 }
 
+#[cfg(feature = "sdk")]
 pub(crate) use as_c_string;
 
 macro_rules! as_c_bool {
@@ -18,6 +21,7 @@ macro_rules! as_c_bool {
     }};
 }
 
+#[cfg(feature = "sdk")]
 pub(crate) use as_c_bool;
 
 #[derive(Debug, Clone)]
@@ -33,10 +37,207 @@ impl SimConnectError {
             result,
         }
     }
+
+    pub fn unsupported(feature: Feature, server: &ServerInfo) -> Self {
+        Self::new(
+            &format!("{:?} is unsupported by server {}", feature, server),
+            None,
+        )
+    }
+
+    pub fn removed(feature: Feature, server: &ServerInfo) -> Self {
+        Self::new(
+            &format!(
+                "{:?} was removed in this product, server {}",
+                feature, server
+            ),
+            None,
+        )
+    }
 }
 
+impl std::fmt::Display for SimConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.result {
+            Some(result) => write!(f, "{} (HRESULT {:#010x})", self.message, result),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for SimConnectError {}
+
 pub type SimConnectResult<T> = Result<T, SimConnectError>;
 
+// An argument of a SimConnect call, as handed to a `simconnect::Tap`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallArg {
+    Int(i64),
+    Float(f64),
+    Text(String),
+    // Structs passed by value, in their packed C layout.
+    Bytes(Vec<u8>),
+    // Buffers and callbacks, only their position in the call is kept.
+    Pointer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimVarValue {
+    Number(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    pub(crate) window: HWND,
+    pub(crate) user_message_id: DWORD,
+    pub(crate) event_handle: HANDLE,
+    pub(crate) config_index: DWORD,
+    pub(crate) open_timeout: Duration,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self {
+            window: std::ptr::null_mut(),
+            user_message_id: 0,
+            event_handle: std::ptr::null_mut(),
+            config_index: 0,
+            open_timeout: Duration::from_secs(5),
+        }
+    }
+
+    // Selects the `[SimConnect.N]` entry of SimConnect.cfg, 0 being `[SimConnect]`.
+    pub fn config_index(mut self, index: DWORD) -> Self {
+        self.config_index = index;
+        self
+    }
+
+    // How long `open` waits for the server to answer with its version.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.open_timeout = timeout;
+        self
+    }
+
+    pub fn event_handle(mut self, handle: HANDLE) -> Self {
+        self.event_handle = handle;
+        self
+    }
+
+    pub fn window_message(mut self, window: HWND, user_message_id: DWORD) -> Self {
+        self.window = window;
+        self.user_message_id = user_message_id;
+        self
+    }
+}
+
+// The window and event handles are process wide, so the options can be handed
+// to the thread that opens the connection.
+unsafe impl Send for OpenOptions {}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        OpenOptions::new()
+    }
+}
+
+// Decoded from the `SIMCONNECT_RECV_OPEN` reply to `SimConnect::open`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub application_name: String,
+    pub application_version: (DWORD, DWORD),
+    pub application_build: (DWORD, DWORD),
+    pub simconnect_version: (DWORD, DWORD),
+    pub simconnect_build: (DWORD, DWORD),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Product {
+    Fsx,
+    Prepar3d,
+    Msfs2020,
+    Msfs2024,
+    Unknown,
+}
+
+// Parts of the API that not every simulator implements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    FacilitiesList,
+    Missions,
+    Camera6Dof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    Supported,
+    // The server predates the feature.
+    Unsupported,
+    // The product dropped the feature.
+    Removed,
+}
+
+// FSX RTM predates the facilities list calls added in SP1.
+const FSX_RTM_BUILD: DWORD = 60905;
+
+impl ServerInfo {
+    pub fn product(&self) -> Product {
+        let name = self.application_name.to_ascii_lowercase();
+        if name.contains("prepar3d") {
+            return Product::Prepar3d;
+        }
+        if name == "kittyhawk" {
+            return Product::Msfs2020;
+        }
+        if name == "sunrise" {
+            return Product::Msfs2024;
+        }
+
+        match self.application_version.0 {
+            10 => Product::Fsx,
+            11 => Product::Msfs2020,
+            12 => Product::Msfs2024,
+            _ => Product::Unknown,
+        }
+    }
+
+    pub fn support(&self, feature: Feature) -> Support {
+        let product = self.product();
+        let msfs = matches!(product, Product::Msfs2020 | Product::Msfs2024);
+        match feature {
+            Feature::FacilitiesList
+                if product == Product::Fsx && self.simconnect_build.0 == FSX_RTM_BUILD =>
+            {
+                Support::Unsupported
+            }
+            Feature::Missions | Feature::Camera6Dof if msfs => Support::Removed,
+            _ => Support::Supported,
+        }
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.support(feature) == Support::Supported
+    }
+}
+
+impl std::fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}.{} (build {}.{}, SimConnect {}.{}.{}.{})",
+            self.application_name,
+            self.application_version.0,
+            self.application_version.1,
+            self.application_build.0,
+            self.application_build.1,
+            self.simconnect_version.0,
+            self.simconnect_version.1,
+            self.simconnect_build.0,
+            self.simconnect_build.1,
+        )
+    }
+}
+
 pub struct InitPosition {
     pub latitude: f64,
     pub longitude: f64,
@@ -63,6 +264,8 @@ impl InitPosition {
     }
 }
 
+// The SimConnect values sit at the top of the DWORD range.
+#[allow(clippy::enum_clike_unportable_variant)]
 pub enum ClientDataType {
     Int8 = SIMCONNECT_CLIENTDATATYPE_INT8 as isize,
     Int16 = SIMCONNECT_CLIENTDATATYPE_INT16 as isize,
@@ -72,6 +275,7 @@ pub enum ClientDataType {
     Float64 = SIMCONNECT_CLIENTDATATYPE_FLOAT64 as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataType {
     Invalid = SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INVALID as isize,
     Int32 = SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INT32 as isize,
@@ -93,11 +297,35 @@ pub enum DataType {
     Max = SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_MAX as isize,
 }
 
+impl DataType {
+    // Bytes taken by one datum, following the packed structs of SimConnect.h.
+    // `None` for variable length strings.
+    pub fn size(self) -> Option<usize> {
+        match self {
+            DataType::Int32 | DataType::Float32 => Some(4),
+            DataType::Int64 | DataType::Float64 => Some(8),
+            DataType::String8 => Some(8),
+            DataType::String32 => Some(32),
+            DataType::String64 => Some(64),
+            DataType::String128 => Some(128),
+            DataType::String256 => Some(256),
+            DataType::String260 => Some(260),
+            DataType::LatLonAlt | DataType::Xyz => Some(24),
+            DataType::InitPosition => Some(56),
+            DataType::MarkerState => Some(68),
+            DataType::Waypoint => Some(44),
+            DataType::StringV | DataType::Invalid | DataType::Max => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CreateClientDataFlag {
     Default = SIMCONNECT_CREATE_CLIENT_DATA_FLAG_DEFAULT as isize,
     ReadOnly = SIMCONNECT_CREATE_CLIENT_DATA_FLAG_READ_ONLY as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Period {
     Never = SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_NEVER as isize,
     Once = SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_ONCE as isize,
@@ -106,12 +334,16 @@ pub enum Period {
     Second = SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_SECOND as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataRequestFlag {
     Default = SIMCONNECT_DATA_REQUEST_FLAG_DEFAULT as isize,
     Changed = SIMCONNECT_DATA_REQUEST_FLAG_CHANGED as isize,
     Tagged = SIMCONNECT_DATA_REQUEST_FLAG_TAGGED as isize,
+    ChangedTagged =
+        (SIMCONNECT_DATA_REQUEST_FLAG_CHANGED | SIMCONNECT_DATA_REQUEST_FLAG_TAGGED) as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SimObjectType {
     User = SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_USER as isize,
     All = SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_ALL as isize,
@@ -121,6 +353,7 @@ pub enum SimObjectType {
     Ground = SIMCONNECT_SIMOBJECT_TYPE_SIMCONNECT_SIMOBJECT_TYPE_GROUND as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FacilityListType {
     Airport = SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_AIRPORT as isize,
     Waypoint = SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_WAYPOINT as isize,
@@ -129,21 +362,25 @@ pub enum FacilityListType {
     Count = SIMCONNECT_FACILITY_LIST_TYPE_SIMCONNECT_FACILITY_LIST_TYPE_COUNT as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClientDataSetFlag {
     Default = SIMCONNECT_CLIENT_DATA_SET_FLAG_DEFAULT as isize,
     Tagged = SIMCONNECT_CLIENT_DATA_SET_FLAG_TAGGED as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataSetFlag {
     Default = SIMCONNECT_DATA_SET_FLAG_DEFAULT as isize,
     Tagged = SIMCONNECT_DATA_SET_FLAG_TAGGED as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Off = SIMCONNECT_STATE_SIMCONNECT_STATE_OFF as isize,
     On = SIMCONNECT_STATE_SIMCONNECT_STATE_ON as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventFlag {
     Default = SIMCONNECT_EVENT_FLAG_DEFAULT as isize,
     FastRepeatTimer = SIMCONNECT_EVENT_FLAG_FAST_REPEAT_TIMER as isize,
@@ -151,6 +388,7 @@ pub enum EventFlag {
     GroupIdIsPriority = SIMCONNECT_EVENT_FLAG_GROUPID_IS_PRIORITY as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClientDataPeriod {
     Never = SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_NEVER as isize,
     Once = SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_ONCE as isize,
@@ -159,8 +397,59 @@ pub enum ClientDataPeriod {
     Second = SIMCONNECT_CLIENT_DATA_PERIOD_SIMCONNECT_CLIENT_DATA_PERIOD_SECOND as isize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClientDataRequestFlag {
     Default = SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_DEFAULT as isize,
     Changed = SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_CHANGED as isize,
     Tagged = SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_TAGGED as isize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(name: &str, version: DWORD, simconnect_build: DWORD) -> ServerInfo {
+        ServerInfo {
+            application_name: name.to_string(),
+            application_version: (version, 0),
+            application_build: (0, 0),
+            simconnect_version: (version, 0),
+            simconnect_build: (simconnect_build, 0),
+        }
+    }
+
+    #[test]
+    fn tells_missing_features_from_removed_ones() {
+        let fsx_rtm = server("Microsoft Flight Simulator X", 10, FSX_RTM_BUILD);
+        assert_eq!(fsx_rtm.product(), Product::Fsx);
+        assert_eq!(
+            fsx_rtm.support(Feature::FacilitiesList),
+            Support::Unsupported
+        );
+        assert_eq!(fsx_rtm.support(Feature::Missions), Support::Supported);
+
+        let msfs = server("KittyHawk", 11, 62651);
+        assert_eq!(msfs.product(), Product::Msfs2020);
+        assert_eq!(msfs.support(Feature::FacilitiesList), Support::Supported);
+        assert_eq!(msfs.support(Feature::Missions), Support::Removed);
+        assert_eq!(msfs.support(Feature::Camera6Dof), Support::Removed);
+    }
+
+    #[test]
+    fn sizes_match_the_packed_structs() {
+        use std::mem::size_of;
+        assert_eq!(
+            DataType::InitPosition.size(),
+            Some(size_of::<SIMCONNECT_DATA_INITPOSITION>())
+        );
+        assert_eq!(
+            DataType::LatLonAlt.size(),
+            Some(size_of::<SIMCONNECT_DATA_LATLONALT>())
+        );
+        assert_eq!(
+            DataType::Waypoint.size(),
+            Some(size_of::<SIMCONNECT_DATA_WAYPOINT>())
+        );
+        assert_eq!(DataType::Xyz.size(), Some(size_of::<SIMCONNECT_DATA_XYZ>()));
+    }
+}
@@ -0,0 +1,86 @@
+use super::server::{ClientId, Command};
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tungstenite::{Error, Message};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+pub fn listen(addr: impl ToSocketAddrs, commands: Sender<Command>) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+
+    Ok(thread::spawn(move || {
+        let clients = (1 as ClientId)..;
+        for (client, stream) in clients.zip(listener.incoming().flatten()) {
+            let commands = commands.clone();
+            thread::spawn(move || serve_client(client, stream, commands));
+        }
+    }))
+}
+
+fn serve_client(client: ClientId, stream: TcpStream, commands: Sender<Command>) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    if socket
+        .get_ref()
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .is_err()
+    {
+        return;
+    }
+
+    let (sender, outgoing) = mpsc::channel();
+    if commands
+        .send(Command::Connected { client, sender })
+        .is_err()
+    {
+        return;
+    }
+
+    pump(client, &mut socket, &commands, &outgoing);
+    let _ = commands.send(Command::Disconnected { client });
+}
+
+fn pump(
+    client: ClientId,
+    socket: &mut tungstenite::WebSocket<TcpStream>,
+    commands: &Sender<Command>,
+    outgoing: &Receiver<String>,
+) {
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if commands.send(Command::Text { client, text }).is_err() {
+                    return;
+                }
+            }
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            }
+            Err(_) => return,
+        }
+
+        loop {
+            match outgoing.try_recv() {
+                Ok(text) => {
+                    if socket.send(Message::Text(text)).is_err() {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // The server is shutting down, everything queued has been sent.
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return;
+                }
+            }
+        }
+    }
+}